@@ -0,0 +1,275 @@
+//! Fee rate estimation
+//!
+//! Estimates are fetched from the chain backend, cached, and shared between on-chain sends and
+//! the Lightning node. Until the first refresh succeeds, conservative fallback rates are used.
+
+use bdk_electrum::electrum_client::{self, ElectrumApi};
+use bitcoin::FeeRate;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use ulw_core::{Error, Result};
+
+/// Lowest fee rate ever used, just above the 1 sat/vB relay minimum to survive rounding
+pub const FEE_RATE_FLOOR: FeeRate = FeeRate::from_sat_per_kwu(253);
+
+/// How urgently a transaction needs to confirm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeeTarget {
+    /// Next block
+    HighPriority,
+    /// Within about an hour
+    Normal,
+    /// Within about two hours
+    Economy,
+    /// Within about a day
+    Background,
+    /// Whenever; about a week
+    Minimum,
+}
+
+impl FeeTarget {
+    pub const ALL: [FeeTarget; 5] = [
+        FeeTarget::HighPriority,
+        FeeTarget::Normal,
+        FeeTarget::Economy,
+        FeeTarget::Background,
+        FeeTarget::Minimum,
+    ];
+
+    /// Number of blocks to confirm within
+    pub fn blocks(self) -> usize {
+        match self {
+            FeeTarget::HighPriority => 1,
+            FeeTarget::Normal => 6,
+            FeeTarget::Economy => 12,
+            FeeTarget::Background => 144,
+            FeeTarget::Minimum => 1008,
+        }
+    }
+
+    /// Rate used before any estimate has been fetched
+    fn fallback(self) -> FeeRate {
+        FeeRate::from_sat_per_kwu(match self {
+            FeeTarget::HighPriority => 5000,
+            FeeTarget::Normal => 2000,
+            FeeTarget::Economy => 1000,
+            FeeTarget::Background => 500,
+            FeeTarget::Minimum => 253,
+        })
+    }
+}
+
+/// Somewhere to fetch fee estimates from
+///
+/// Calls may block on the network.
+pub trait FeeSource: Send + Sync {
+    /// Fee rate needed to confirm within `blocks` blocks
+    fn estimate(&self, blocks: usize) -> Result<FeeRate>;
+}
+
+/// Fee estimates from an Electrum server
+pub struct ElectrumFeeSource {
+    server_url: String,
+}
+
+impl ElectrumFeeSource {
+    pub fn new(server_url: String) -> Self {
+        Self { server_url }
+    }
+}
+
+impl FeeSource for ElectrumFeeSource {
+    fn estimate(&self, blocks: usize) -> Result<FeeRate> {
+        let client = electrum_client::Client::new(&self.server_url)
+            .map_err(|e| Error::Network(e.to_string()))?;
+        let btc_per_kvb = client
+            .estimate_fee(blocks)
+            .map_err(|e| Error::Network(e.to_string()))?;
+
+        // The server answers -1 when it has no estimate for this target
+        if btc_per_kvb <= 0.0 {
+            return Err(Error::Network(format!(
+                "No fee estimate available for {} blocks",
+                blocks
+            )));
+        }
+        let sat_per_kvb = (btc_per_kvb * 100_000_000.0).round() as u64;
+        Ok(FeeRate::from_sat_per_kwu(sat_per_kvb / 4))
+    }
+}
+
+struct Cached {
+    rates: HashMap<FeeTarget, FeeRate>,
+    updated_at: Option<Instant>,
+}
+
+/// Cached fee estimates for each [`FeeTarget`]
+pub struct FeeEstimates {
+    source: Arc<dyn FeeSource>,
+    cached: RwLock<Cached>,
+}
+
+impl FeeEstimates {
+    pub fn new(source: Arc<dyn FeeSource>) -> Self {
+        Self {
+            source,
+            cached: RwLock::new(Cached {
+                rates: HashMap::new(),
+                updated_at: None,
+            }),
+        }
+    }
+
+    /// Current fee rate for `target`, never below [`FEE_RATE_FLOOR`]
+    pub fn fee_rate(&self, target: FeeTarget) -> FeeRate {
+        self.cached
+            .read()
+            .unwrap()
+            .rates
+            .get(&target)
+            .copied()
+            .unwrap_or_else(|| target.fallback())
+    }
+
+    /// Fetch fresh estimates for every target
+    ///
+    /// Targets the source cannot estimate keep their previous rate. Estimates are made
+    /// monotonic so that a more urgent target never pays less than a less urgent one.
+    pub async fn refresh(&self) -> Result<()> {
+        let source = self.source.clone();
+        let fetched = tokio::task::spawn_blocking(move || {
+            FeeTarget::ALL.map(|target| (target, source.estimate(target.blocks())))
+        })
+        .await
+        .map_err(|e| Error::Internal(format!("Fee estimation task failed: {}", e)))?;
+
+        if let Some((_, Err(e))) = fetched.iter().find(|(_, rate)| rate.is_err()) {
+            if fetched.iter().all(|(_, rate)| rate.is_err()) {
+                return Err(Error::Network(format!("Fee estimation failed: {}", e)));
+            }
+            tracing::warn!("Some fee estimates are unavailable: {}", e);
+        }
+
+        let mut cached = self.cached.write().unwrap();
+        // Walk from least to most urgent so each rate is at least the one before it
+        let mut floor = FEE_RATE_FLOOR;
+        for (target, rate) in fetched.into_iter().rev() {
+            let rate = match rate {
+                Ok(rate) => rate,
+                Err(_) => cached
+                    .rates
+                    .get(&target)
+                    .copied()
+                    .unwrap_or_else(|| target.fallback()),
+            };
+            floor = rate.max(floor);
+            cached.rates.insert(target, floor);
+        }
+        cached.updated_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Refresh unless the estimates were fetched within `max_age`
+    pub async fn refresh_if_stale(&self, max_age: Duration) -> Result<()> {
+        let fresh = self
+            .cached
+            .read()
+            .unwrap()
+            .updated_at
+            .is_some_and(|updated_at| updated_at.elapsed() < max_age);
+        if fresh {
+            return Ok(());
+        }
+        self.refresh().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Fee source returning preset rates in sat/kwu, or failing for missing targets
+    struct MockFeeSource {
+        rates: Mutex<HashMap<usize, u64>>,
+    }
+
+    impl MockFeeSource {
+        fn new(rates: &[(FeeTarget, u64)]) -> Arc<Self> {
+            Arc::new(Self {
+                rates: Mutex::new(
+                    rates
+                        .iter()
+                        .map(|(target, rate)| (target.blocks(), *rate))
+                        .collect(),
+                ),
+            })
+        }
+    }
+
+    impl FeeSource for MockFeeSource {
+        fn estimate(&self, blocks: usize) -> Result<FeeRate> {
+            self.rates
+                .lock()
+                .unwrap()
+                .get(&blocks)
+                .map(|rate| FeeRate::from_sat_per_kwu(*rate))
+                .ok_or_else(|| Error::Network("unavailable".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_rates_before_refresh() {
+        let estimates = FeeEstimates::new(MockFeeSource::new(&[]));
+
+        assert_eq!(estimates.fee_rate(FeeTarget::Minimum), FEE_RATE_FLOOR);
+        assert!(
+            estimates.fee_rate(FeeTarget::HighPriority) > estimates.fee_rate(FeeTarget::Normal)
+        );
+        assert!(estimates.refresh().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_clamps_and_orders_rates() {
+        let source = MockFeeSource::new(&[
+            (FeeTarget::HighPriority, 8000),
+            (FeeTarget::Normal, 3000),
+            (FeeTarget::Economy, 4000),
+            (FeeTarget::Background, 100),
+            (FeeTarget::Minimum, 100),
+        ]);
+        let estimates = FeeEstimates::new(source);
+        estimates.refresh().await.unwrap();
+
+        let rate = |target| estimates.fee_rate(target).to_sat_per_kwu();
+        assert_eq!(rate(FeeTarget::HighPriority), 8000);
+        // A more urgent target never pays less than a less urgent one
+        assert_eq!(rate(FeeTarget::Normal), 4000);
+        assert_eq!(rate(FeeTarget::Economy), 4000);
+        // Nothing goes below the relay floor
+        assert_eq!(rate(FeeTarget::Background), 253);
+        assert_eq!(rate(FeeTarget::Minimum), 253);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_previous_rate_when_unavailable() {
+        let source =
+            MockFeeSource::new(&[(FeeTarget::HighPriority, 9000), (FeeTarget::Normal, 6000)]);
+        let estimates = FeeEstimates::new(source.clone());
+        estimates.refresh().await.unwrap();
+
+        source
+            .rates
+            .lock()
+            .unwrap()
+            .remove(&FeeTarget::Normal.blocks());
+        estimates.refresh().await.unwrap();
+
+        assert_eq!(estimates.fee_rate(FeeTarget::Normal).to_sat_per_kwu(), 6000);
+        assert!(estimates
+            .refresh_if_stale(Duration::from_secs(60))
+            .await
+            .is_ok());
+    }
+}
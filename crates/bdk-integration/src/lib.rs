@@ -1,5 +1,6 @@
 //! BDK wallet integration for on-chain functionality
 
+pub mod fees;
 pub mod wallet;
 
 pub use wallet::BdkWallet;
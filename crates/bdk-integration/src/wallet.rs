@@ -1,15 +1,24 @@
 //! BDK wallet implementation
 
 use bdk_electrum::electrum_client::{self, ElectrumApi};
-use bdk_wallet::{KeychainKind, Wallet};
-use bitcoin::{Address, Amount, FeeRate, Network, Txid};
+use bdk_wallet::{KeychainKind, SignOptions, Wallet};
+use bitcoin::{
+    Address, Amount, FeeRate, Network, OutPoint, Psbt, ScriptBuf, Transaction, TxOut, Txid,
+};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 use ulw_core::{traits::OnChainTransaction, Error, Result};
 
+use crate::fees::{ElectrumFeeSource, FeeEstimates, FeeTarget};
+
+/// How old fee estimates may be before a send fetches new ones
+const FEE_ESTIMATE_MAX_AGE: Duration = Duration::from_secs(600);
+
 pub struct BdkWallet {
     wallet: Arc<Mutex<Wallet>>,
     electrum_url: String,
+    fee_estimates: Arc<FeeEstimates>,
 }
 
 impl BdkWallet {
@@ -25,12 +34,27 @@ impl BdkWallet {
             .create_wallet_no_persist()
             .map_err(|e| Error::Internal(e.to_string()))?;
 
+        let fee_estimates = Arc::new(FeeEstimates::new(Arc::new(ElectrumFeeSource::new(
+            electrum_url.clone(),
+        ))));
+
         Ok(Self {
             wallet: Arc::new(Mutex::new(wallet)),
             electrum_url,
+            fee_estimates,
         })
     }
 
+    /// Electrum server the wallet syncs from
+    pub fn electrum_url(&self) -> &str {
+        &self.electrum_url
+    }
+
+    /// Fee estimates shared with the Lightning node
+    pub fn fee_estimates(&self) -> Arc<FeeEstimates> {
+        self.fee_estimates.clone()
+    }
+
     /// Sync wallet with blockchain
     pub async fn sync(&self) -> Result<()> {
         // Placeholder - full sync would use Electrum client
@@ -47,20 +71,99 @@ impl BdkWallet {
         Ok(addr.address)
     }
 
+    /// Get a new receiving address without waiting for the wallet lock
+    ///
+    /// For callers that cannot await, such as LDK's change destination source.
+    pub fn try_get_new_address(&self) -> Result<Address> {
+        let mut wallet = self
+            .wallet
+            .try_lock()
+            .map_err(|_| Error::Internal("Wallet is busy".to_string()))?;
+        let addr = wallet.reveal_next_address(KeychainKind::External);
+        Ok(addr.address)
+    }
+
+    /// Confirmed unspent outputs, without waiting for the wallet lock
+    ///
+    /// For callers that cannot await, such as LDK's fee bumping of anchor channels.
+    pub fn try_list_confirmed_utxos(&self) -> Result<Vec<(OutPoint, TxOut)>> {
+        let wallet = self
+            .wallet
+            .try_lock()
+            .map_err(|_| Error::Internal("Wallet is busy".to_string()))?;
+        Ok(wallet
+            .list_unspent()
+            .filter(|utxo| utxo.chain_position.is_confirmed())
+            .map(|utxo| (utxo.outpoint, utxo.txout))
+            .collect())
+    }
+
+    /// Get a new change script without waiting for the wallet lock
+    pub fn try_get_change_script(&self) -> Result<ScriptBuf> {
+        let mut wallet = self
+            .wallet
+            .try_lock()
+            .map_err(|_| Error::Internal("Wallet is busy".to_string()))?;
+        Ok(wallet
+            .reveal_next_address(KeychainKind::Internal)
+            .address
+            .script_pubkey())
+    }
+
+    /// Sign the inputs of `psbt` that spend wallet outputs, without waiting for the wallet lock
+    ///
+    /// Other inputs are left for the caller to sign, so the transaction may not be complete.
+    pub fn try_sign_psbt(&self, mut psbt: Psbt) -> Result<Transaction> {
+        let wallet = self
+            .wallet
+            .try_lock()
+            .map_err(|_| Error::Internal("Wallet is busy".to_string()))?;
+        let options = SignOptions {
+            // Inputs are described by their witness UTXO only, which is safe for segwit v0
+            trust_witness_utxo: true,
+            ..Default::default()
+        };
+        wallet
+            .sign(&mut psbt, options)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        Ok(psbt.extract_tx_unchecked_fee_rate())
+    }
+
     /// Get balance
     pub async fn get_balance(&self) -> Result<Amount> {
         let wallet = self.wallet.lock().await;
         Ok(wallet.balance().total())
     }
 
+    /// Get the confirmed and unconfirmed parts of the balance
+    pub async fn get_balance_details(&self) -> Result<(Amount, Amount)> {
+        let wallet = self.wallet.lock().await;
+        let balance = wallet.balance();
+        Ok((
+            balance.confirmed,
+            balance.trusted_pending + balance.untrusted_pending + balance.immature,
+        ))
+    }
+
     /// Send transaction
+    ///
+    /// Pays the current fee estimate for confirmation within about an hour.
     pub async fn send(&self, address: Address, amount: Amount) -> Result<Txid> {
+        if let Err(e) = self
+            .fee_estimates
+            .refresh_if_stale(FEE_ESTIMATE_MAX_AGE)
+            .await
+        {
+            tracing::warn!("Using cached fee estimates: {}", e);
+        }
+        let fee_rate = self.fee_estimates.fee_rate(FeeTarget::Normal);
+
         let mut wallet = self.wallet.lock().await;
 
         let mut tx_builder = wallet.build_tx();
         tx_builder
             .add_recipient(address.script_pubkey(), amount)
-            .fee_rate(FeeRate::from_sat_per_vb_unchecked(1));
+            .fee_rate(fee_rate);
 
         let mut psbt = tx_builder
             .finish()
@@ -87,6 +190,48 @@ impl BdkWallet {
         Ok(txid)
     }
 
+    /// Build and sign a transaction paying `amount` to `output_script` without broadcasting it
+    ///
+    /// Used to fund Lightning channels: LDK hands us the 2-of-2 funding script and
+    /// broadcasts the transaction itself once the counterparty has signed.
+    pub async fn create_funding_transaction(
+        &self,
+        output_script: ScriptBuf,
+        amount: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<Transaction> {
+        let mut wallet = self.wallet.lock().await;
+
+        let available = wallet.balance().trusted_spendable();
+        if available < amount {
+            return Err(Error::InsufficientFunds {
+                required: amount.to_sat(),
+                available: available.to_sat(),
+            });
+        }
+
+        let mut tx_builder = wallet.build_tx();
+        tx_builder
+            .add_recipient(output_script, amount)
+            .fee_rate(fee_rate);
+
+        let mut psbt = tx_builder
+            .finish()
+            .map_err(|e| Error::Internal(e.to_string()))?;
+
+        let finalized = wallet
+            .sign(&mut psbt, Default::default())
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        if !finalized {
+            return Err(Error::Internal(
+                "Funding transaction could not be fully signed".to_string(),
+            ));
+        }
+
+        psbt.extract_tx()
+            .map_err(|e| Error::Internal(e.to_string()))
+    }
+
     /// List all transactions
     pub async fn list_transactions(&self) -> Result<Vec<OnChainTransaction>> {
         let wallet = self.wallet.lock().await;
@@ -122,4 +267,22 @@ mod tests {
 
         assert!(wallet.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_funding_transaction_requires_funds() {
+        let wallet = BdkWallet::new(
+            Network::Regtest,
+            "wpkh(tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m/84'/1'/0'/0/*)".to_string(),
+            "wpkh(tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m/84'/1'/0'/1/*)".to_string(),
+            "tcp://localhost:50001".to_string(),
+        )
+        .unwrap();
+
+        let script = wallet.get_new_address().await.unwrap().script_pubkey();
+        let result = wallet
+            .create_funding_transaction(script, Amount::from_sat(100_000), FeeRate::BROADCAST_MIN)
+            .await;
+
+        assert!(matches!(result, Err(Error::InsufficientFunds { .. })));
+    }
 }
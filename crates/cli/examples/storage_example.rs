@@ -39,6 +39,14 @@ async fn main() -> Result<()> {
         invoice: None,
         created_at: chrono::Utc::now(),
         settled_at: None,
+        preimage: None,
+        fee_paid_msat: None,
+        custom_tlvs: Vec::new(),
+        parts: None,
+        path_fees_msat: Vec::new(),
+        path_ids: Vec::new(),
+        offer_id: None,
+        claim_deadline: None,
     };
 
     // Save the payment
@@ -80,6 +88,9 @@ async fn main() -> Result<()> {
         local_balance_msat: 500_000_000,
         remote_balance_msat: 500_000_000,
         state: ChannelState::Active,
+        funding_txo: None,
+        closing_txid: None,
+        claimable_at_height: None,
     };
 
     // Save the channel
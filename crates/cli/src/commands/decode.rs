@@ -0,0 +1,794 @@
+//! Decode payment requests and transactions without acting on them
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::constants::ChainHash;
+use bitcoin::psbt::Psbt;
+use bitcoin::{Address, Amount, Denomination, Network, Transaction, TxOut};
+use lightning::blinded_path::IntroductionNode;
+use lightning::offers::offer::{self, Offer, Quantity};
+use lightning::util::scid_utils;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use reqwest::Url;
+use std::fmt::Display;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use ulw_core::{Error, Result};
+use ulw_ldk::lnurl::{self, LnurlRequest};
+
+use crate::config::WalletConfig;
+
+/// An input recognized by [`detect`]
+#[derive(Debug)]
+enum Decodable {
+    Invoice(Box<Bolt11Invoice>),
+    Offer(Box<Offer>),
+    Bip21(Url),
+    Lnurl(String),
+    Psbt(Psbt),
+    Tx(Transaction),
+}
+
+/// What was decoded, as fields to print and warnings about anything expired or for another
+/// network
+#[derive(Debug)]
+struct Description {
+    title: &'static str,
+    fields: Vec<(String, String)>,
+    warnings: Vec<String>,
+    /// Lightning requests carried in a BIP21 URI
+    nested: Vec<Description>,
+}
+
+impl Description {
+    fn new(title: &'static str) -> Self {
+        Self {
+            title,
+            fields: Vec::new(),
+            warnings: Vec::new(),
+            nested: Vec::new(),
+        }
+    }
+
+    fn field(&mut self, label: impl Into<String>, value: impl Display) {
+        self.fields.push((label.into(), value.to_string()));
+    }
+
+    fn warn(&mut self, message: impl Into<String>) {
+        self.warnings.push(message.into());
+    }
+
+    fn print(&self) {
+        println!("{}", self.title);
+        for (label, value) in &self.fields {
+            println!("{}: {}", label, value);
+        }
+        for warning in &self.warnings {
+            println!("⚠️  {}", warning);
+        }
+        for nested in &self.nested {
+            println!();
+            nested.print();
+        }
+    }
+}
+
+/// Print everything in a BOLT11 invoice, BOLT12 offer, BIP21 URI, LNURL, PSBT or raw
+/// transaction, warning about anything expired or for another network
+pub async fn decode(config: &WalletConfig, input: String) -> Result<()> {
+    let network = config.network.network;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let description = match detect(&input)? {
+        Decodable::Invoice(invoice) => describe_invoice(&invoice, network, now),
+        Decodable::Offer(offer) => describe_offer(&offer, network, now),
+        Decodable::Bip21(url) => describe_bip21(&url, network, now)?,
+        Decodable::Lnurl(lnurl) => describe_lnurl(&lnurl).await?,
+        Decodable::Psbt(psbt) => describe_psbt(&psbt, network),
+        Decodable::Tx(tx) => describe_tx("₿ Raw Transaction", &tx, network, |_| None),
+    };
+    description.print();
+
+    Ok(())
+}
+
+/// Work out what `input` is, trying Lightning requests before the on-chain formats
+fn detect(input: &str) -> Result<Decodable> {
+    let input = input.trim();
+    let lightning = input
+        .strip_prefix("lightning:")
+        .or_else(|| input.strip_prefix("LIGHTNING:"))
+        .unwrap_or(input);
+
+    if let Ok(invoice) = lightning.parse::<Bolt11Invoice>() {
+        Ok(Decodable::Invoice(Box::new(invoice)))
+    } else if let Ok(offer) = lightning.parse::<Offer>() {
+        Ok(Decodable::Offer(Box::new(offer)))
+    } else if input.to_lowercase().starts_with("bitcoin:") {
+        Url::parse(input)
+            .map(Decodable::Bip21)
+            .map_err(|e| Error::InvalidAddress(format!("Invalid BIP21 URI: {}", e)))
+    } else if lnurl::is_lnurl(input) {
+        Ok(Decodable::Lnurl(input.to_string()))
+    } else if let Some(psbt) = parse_psbt(input) {
+        Ok(Decodable::Psbt(psbt))
+    } else if let Some(tx) = parse_tx(input) {
+        Ok(Decodable::Tx(tx))
+    } else {
+        Err(Error::InvalidInvoice(
+            "Not an invoice, offer, BIP21 URI, LNURL, PSBT or raw transaction".to_string(),
+        ))
+    }
+}
+
+/// Format a time given as a duration since the Unix epoch
+fn format_time(since_epoch: Duration) -> String {
+    chrono::DateTime::from_timestamp(since_epoch.as_secs() as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| format!("{} seconds after the epoch", since_epoch.as_secs()))
+}
+
+/// Format a short channel ID as block x transaction x output
+fn format_scid(scid: u64) -> String {
+    format!(
+        "{}x{}x{}",
+        scid_utils::block_from_scid(scid),
+        scid_utils::tx_index_from_scid(scid),
+        scid_utils::vout_from_scid(scid)
+    )
+}
+
+fn describe_invoice(invoice: &Bolt11Invoice, network: Network, now: Duration) -> Description {
+    let mut description = Description::new("⚡ BOLT11 Invoice");
+    description.field("Network", invoice.network());
+    match invoice.amount_milli_satoshis() {
+        Some(amount) => description.field("Amount", format!("{} msats", amount)),
+        None => description.field("Amount", "any, chosen by the payer"),
+    }
+    match invoice.description() {
+        Bolt11InvoiceDescription::Direct(text) => description.field("Description", text),
+        Bolt11InvoiceDescription::Hash(hash) => description.field("Description Hash", hash.0),
+    }
+    description.field("Payee", invoice.get_payee_pub_key());
+    description.field("Payment Hash", invoice.payment_hash());
+    description.field("Payment Secret", hex::encode(invoice.payment_secret().0));
+    if let Some(metadata) = invoice.payment_metadata() {
+        description.field("Payment Metadata", hex::encode(metadata));
+    }
+    description.field("Created", format_time(invoice.duration_since_epoch()));
+    match invoice.expires_at() {
+        Some(expires_at) => description.field(
+            "Expiry",
+            format!(
+                "{} seconds ({})",
+                invoice.expiry_time().as_secs(),
+                format_time(expires_at)
+            ),
+        ),
+        None => description.field(
+            "Expiry",
+            format!("{} seconds", invoice.expiry_time().as_secs()),
+        ),
+    }
+    description.field(
+        "Min Final CLTV Expiry Delta",
+        invoice.min_final_cltv_expiry_delta(),
+    );
+    if let Some(features) = invoice.features() {
+        description.field("Features", features);
+    }
+    for address in invoice.fallback_addresses() {
+        description.field("Fallback Address", address);
+    }
+    for (i, hint) in invoice.route_hints().iter().enumerate() {
+        for hop in &hint.0 {
+            description.field(
+                format!("Route Hint {}", i + 1),
+                format!(
+                    "{} via {}, fee {} msats + {} ppm, CLTV delta {}",
+                    hop.src_node_id,
+                    format_scid(hop.short_channel_id),
+                    hop.fees.base_msat,
+                    hop.fees.proportional_millionths,
+                    hop.cltv_expiry_delta
+                ),
+            );
+        }
+    }
+
+    if invoice.network() != network {
+        description.warn(format!(
+            "Invoice is for {}, but the wallet is on {}",
+            invoice.network(),
+            network
+        ));
+    }
+    if invoice.would_expire(now) {
+        description.warn("Invoice has expired");
+    }
+    description
+}
+
+fn describe_offer(offer: &Offer, network: Network, now: Duration) -> Description {
+    let mut description = Description::new("⚡ BOLT12 Offer");
+    description.field("ID", hex::encode(offer.id().0));
+    let chains: Vec<String> = offer
+        .chains()
+        .into_iter()
+        .map(|chain| match Network::from_chain_hash(chain) {
+            Some(network) => network.to_string(),
+            None => chain.to_string(),
+        })
+        .collect();
+    description.field("Chains", chains.join(", "));
+    match offer.amount() {
+        Some(offer::Amount::Bitcoin { amount_msats }) => {
+            description.field("Amount", format!("{} msats per item", amount_msats))
+        }
+        Some(offer::Amount::Currency {
+            iso4217_code,
+            amount,
+        }) => description.field(
+            "Amount",
+            format!(
+                "{} {} per item, in the currency's smallest unit",
+                amount,
+                String::from_utf8_lossy(&iso4217_code)
+            ),
+        ),
+        None => description.field("Amount", "any, chosen by the payer"),
+    }
+    if let Some(text) = offer.description() {
+        description.field("Description", text);
+    }
+    if let Some(issuer) = offer.issuer() {
+        description.field("Issuer", issuer);
+    }
+    match offer.supported_quantity() {
+        Quantity::One => description.field("Quantity", 1),
+        Quantity::Bounded(max) => description.field("Quantity", format!("up to {}", max)),
+        Quantity::Unbounded => description.field("Quantity", "any"),
+    }
+    if let Some(signing_pubkey) = offer.signing_pubkey() {
+        description.field("Signing Key", signing_pubkey);
+    }
+    for path in offer.paths() {
+        let introduction = match path.introduction_node() {
+            IntroductionNode::NodeId(node_id) => node_id.to_string(),
+            IntroductionNode::DirectedShortChannelId(direction, scid) => {
+                format!("{} ({:?})", format_scid(*scid), direction)
+            }
+        };
+        description.field(
+            "Blinded Path",
+            format!("{} hops from {}", path.blinded_hops().len(), introduction),
+        );
+    }
+    if let Some(expiry) = offer.absolute_expiry() {
+        description.field("Expires", format_time(expiry));
+    }
+
+    if !offer.supports_chain(ChainHash::using_genesis_block(network)) {
+        description.warn(format!(
+            "Offer is for {}, but the wallet is on {}",
+            chains.join(", "),
+            network
+        ));
+    }
+    if offer.is_expired_no_std(now) {
+        description.warn("Offer has expired");
+    }
+    description
+}
+
+fn describe_bip21(url: &Url, network: Network, now: Duration) -> Result<Description> {
+    let mut description = Description::new("₿ BIP21 Payment URI");
+    // The address may be left out when the URI only carries a Lightning request
+    if !url.path().is_empty() {
+        let address = url
+            .path()
+            .parse::<Address<NetworkUnchecked>>()
+            .map_err(|e| Error::InvalidAddress(e.to_string()))?;
+        description.field("Address", address.assume_checked_ref());
+        if !address.is_valid_for_network(network) {
+            description.warn(format!(
+                "Address is not for {}, the wallet's network",
+                network
+            ));
+        }
+    }
+
+    for (key, value) in url.query_pairs() {
+        match key.to_lowercase().as_str() {
+            "amount" => match Amount::from_str_in(&value, Denomination::Bitcoin) {
+                Ok(amount) => description.field("Amount", format!("{} sats", amount.to_sat())),
+                Err(e) => description.warn(format!("Invalid amount {}: {}", value, e)),
+            },
+            "label" => description.field("Label", value),
+            "message" => description.field("Message", value),
+            // Lightning requests carried along for wallets that prefer them
+            "lightning" | "lno" => {
+                if let Ok(invoice) = value.parse::<Bolt11Invoice>() {
+                    description
+                        .nested
+                        .push(describe_invoice(&invoice, network, now));
+                } else if let Ok(offer) = value.parse::<Offer>() {
+                    description
+                        .nested
+                        .push(describe_offer(&offer, network, now));
+                } else {
+                    description.warn(format!("Invalid Lightning request: {}", value));
+                }
+            }
+            key if key.starts_with("req-") => description.warn(format!(
+                "Unknown required parameter {}; the URI must not be paid",
+                key
+            )),
+            key => description.field(key, value),
+        }
+    }
+
+    Ok(description)
+}
+
+async fn describe_lnurl(input: &str) -> Result<Description> {
+    let url = lnurl::parse_lnurl(input)?;
+    let mut description = Description::new("⚡ LNURL");
+    description.field("URL", &url);
+
+    if let Ok(request) = lnurl::parse_auth(input) {
+        description.field("Type", "login");
+        description.field("Domain", &request.domain);
+        if let Some(action) = &request.action {
+            description.field("Action", action);
+        }
+        return Ok(description);
+    }
+
+    match lnurl::fetch_lnurl(input).await {
+        Ok(LnurlRequest::Pay(request)) => {
+            description.field("Type", "pay request");
+            if let Some(text) = request.description() {
+                description.field("Description", text);
+            }
+            description.field(
+                "Accepts",
+                format!(
+                    "{} - {} sats",
+                    request.min_sendable_msat / 1000,
+                    request.max_sendable_msat / 1000
+                ),
+            );
+            if request.comment_allowed > 0 {
+                description.field(
+                    "Comment",
+                    format!("up to {} characters", request.comment_allowed),
+                );
+            }
+            description.field("Callback", &request.callback);
+        }
+        Ok(LnurlRequest::Withdraw(request)) => {
+            description.field("Type", "withdraw request");
+            if !request.default_description.is_empty() {
+                description.field("Description", &request.default_description);
+            }
+            description.field(
+                "Allows",
+                format!(
+                    "{} - {} sats",
+                    request.min_withdrawable_msat / 1000,
+                    request.max_withdrawable_msat / 1000
+                ),
+            );
+            description.field("Callback", &request.callback);
+        }
+        Err(e) => description.warn(format!("Could not fetch the request: {}", e)),
+    }
+
+    Ok(description)
+}
+
+/// Parse a base64 or hex-encoded PSBT
+fn parse_psbt(input: &str) -> Option<Psbt> {
+    input.parse::<Psbt>().ok().or_else(|| {
+        hex::decode(input)
+            .ok()
+            .and_then(|bytes| Psbt::deserialize(&bytes).ok())
+    })
+}
+
+/// Parse a hex-encoded transaction
+fn parse_tx(input: &str) -> Option<Transaction> {
+    hex::decode(input)
+        .ok()
+        .and_then(|bytes| bitcoin::consensus::deserialize(&bytes).ok())
+}
+
+fn describe_psbt(psbt: &Psbt, network: Network) -> Description {
+    // The outputs the inputs spend, where the PSBT includes them
+    let spent: Vec<Option<TxOut>> = psbt
+        .inputs
+        .iter()
+        .zip(&psbt.unsigned_tx.input)
+        .map(|(input, txin)| {
+            input.witness_utxo.clone().or_else(|| {
+                input
+                    .non_witness_utxo
+                    .as_ref()
+                    .and_then(|tx| tx.output.get(txin.previous_output.vout as usize).cloned())
+            })
+        })
+        .collect();
+
+    let mut description = describe_tx("₿ PSBT", &psbt.unsigned_tx, network, |i| {
+        let input = &psbt.inputs[i];
+        let signed = if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+            "finalized".to_string()
+        } else {
+            format!("{} signatures", input.partial_sigs.len())
+        };
+        Some(match &spent[i] {
+            Some(txout) => format!("{} sats, {}", txout.value.to_sat(), signed),
+            None => format!("unknown amount, {}", signed),
+        })
+    });
+
+    match psbt.fee() {
+        Ok(fee) => description.field("Fee", format!("{} sats", fee.to_sat())),
+        Err(_) => description.warn("Fee unknown, the PSBT does not include every spent output"),
+    }
+    description
+}
+
+/// Describe a transaction, with `input_detail` adding what is known about each input
+fn describe_tx(
+    title: &'static str,
+    tx: &Transaction,
+    network: Network,
+    input_detail: impl Fn(usize) -> Option<String>,
+) -> Description {
+    let mut description = Description::new(title);
+    description.field("Txid", tx.compute_txid());
+    description.field("Version", tx.version.0);
+    description.field("Lock Time", tx.lock_time);
+    description.field(
+        "Size",
+        format!(
+            "{} bytes, {} vbytes, {} weight units",
+            tx.total_size(),
+            tx.vsize(),
+            tx.weight().to_wu()
+        ),
+    );
+    if tx.is_explicitly_rbf() {
+        description.field("Replaceable", "yes");
+    }
+
+    for (i, input) in tx.input.iter().enumerate() {
+        match input_detail(i) {
+            Some(detail) => description.field(
+                format!("Input {}", i),
+                format!("{} ({})", input.previous_output, detail),
+            ),
+            None => description.field(format!("Input {}", i), input.previous_output),
+        }
+    }
+
+    for (i, output) in tx.output.iter().enumerate() {
+        let destination = match Address::from_script(&output.script_pubkey, network) {
+            Ok(address) => address.to_string(),
+            Err(_) => format!("script {}", output.script_pubkey.to_hex_string()),
+        };
+        description.field(
+            format!("Output {}", i),
+            format!("{} sats to {}", output.value.to_sat(), destination),
+        );
+    }
+    let total: Amount = tx.output.iter().map(|output| output.value).sum();
+    description.field("Total Out", format!("{} sats", total.to_sat()));
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use bitcoin::{CompressedPublicKey, OutPoint, ScriptBuf, Sequence, TxIn, Witness};
+    use lightning::ln::PaymentSecret;
+    use lightning::offers::offer::OfferBuilder;
+    use lightning_invoice::{Currency, InvoiceBuilder};
+
+    /// When invoices and offers in these tests are created
+    const CREATED: Duration = Duration::from_secs(1_700_000_000);
+
+    fn key() -> SecretKey {
+        SecretKey::from_slice(&[7u8; 32]).unwrap()
+    }
+
+    /// Testnet invoice for 5000 msats that expires an hour after [`CREATED`]
+    fn invoice() -> Bolt11Invoice {
+        InvoiceBuilder::new(Currency::BitcoinTestnet)
+            .description("coffee".to_string())
+            .payment_hash(sha256::Hash::hash(&[1u8; 32]))
+            .payment_secret(PaymentSecret([2u8; 32]))
+            .duration_since_epoch(CREATED)
+            .expiry_time(Duration::from_secs(3600))
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(5_000)
+            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key()))
+            .unwrap()
+    }
+
+    /// Testnet offer for 1000 msats that expires an hour after [`CREATED`]
+    fn offer() -> Offer {
+        let signing_pubkey = PublicKey::from_secret_key(&Secp256k1::new(), &key());
+        OfferBuilder::new(signing_pubkey)
+            .chain(Network::Testnet)
+            .description("tea".to_string())
+            .amount_msats(1_000)
+            .absolute_expiry(CREATED + Duration::from_secs(3600))
+            .build()
+            .unwrap()
+    }
+
+    fn testnet_address() -> Address {
+        let pubkey = CompressedPublicKey(PublicKey::from_secret_key(&Secp256k1::new(), &key()));
+        Address::p2wpkh(&pubkey, Network::Testnet)
+    }
+
+    fn tx() -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(bitcoin::Txid::all_zeros(), 1),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: testnet_address().script_pubkey(),
+            }],
+        }
+    }
+
+    fn field<'a>(description: &'a Description, label: &str) -> Option<&'a str> {
+        description
+            .fields
+            .iter()
+            .find(|(field, _)| field == label)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_detect_order() {
+        let invoice = invoice().to_string();
+        let offer = offer().to_string();
+        let psbt = Psbt::from_unsigned_tx(tx()).unwrap();
+
+        assert!(matches!(detect(&invoice), Ok(Decodable::Invoice(_))));
+        assert!(matches!(
+            detect(&format!("lightning:{}", invoice)),
+            Ok(Decodable::Invoice(_))
+        ));
+        assert!(matches!(detect(&offer), Ok(Decodable::Offer(_))));
+        assert!(matches!(
+            detect(&format!("LIGHTNING:{}", offer)),
+            Ok(Decodable::Offer(_))
+        ));
+        // A URI carrying a Lightning request is still described as a whole
+        assert!(matches!(
+            detect(&format!(
+                "bitcoin:{}?lightning={}",
+                testnet_address(),
+                invoice
+            )),
+            Ok(Decodable::Bip21(_))
+        ));
+        assert!(matches!(
+            detect(&format!("BITCOIN:{}?req-pop=1", testnet_address())),
+            Ok(Decodable::Bip21(_))
+        ));
+        assert!(matches!(
+            detect("keyauth://service.com/login?tag=login&k1=00"),
+            Ok(Decodable::Lnurl(_))
+        ));
+        assert!(matches!(detect(&psbt.to_string()), Ok(Decodable::Psbt(_))));
+        // Checked before raw transactions, whose hex a PSBT's could never be
+        assert!(matches!(
+            detect(&hex::encode(psbt.serialize())),
+            Ok(Decodable::Psbt(_))
+        ));
+        assert!(matches!(
+            detect(&serialize_hex(&tx())),
+            Ok(Decodable::Tx(_))
+        ));
+        assert!(matches!(
+            detect("not a payment request"),
+            Err(Error::InvalidInvoice(_))
+        ));
+    }
+
+    #[test]
+    fn test_describe_invoice() {
+        let invoice = invoice();
+        let description = describe_invoice(
+            &invoice,
+            Network::Testnet,
+            CREATED + Duration::from_secs(60),
+        );
+        assert_eq!(field(&description, "Network"), Some("testnet"));
+        assert_eq!(field(&description, "Amount"), Some("5000 msats"));
+        assert_eq!(field(&description, "Description"), Some("coffee"));
+        assert_eq!(
+            field(&description, "Payment Hash"),
+            Some(invoice.payment_hash().to_string().as_str())
+        );
+        assert_eq!(
+            field(&description, "Expiry"),
+            Some("3600 seconds (2023-11-14 23:13:20 UTC)")
+        );
+        assert_eq!(
+            field(&description, "Min Final CLTV Expiry Delta"),
+            Some("144")
+        );
+        assert!(description.warnings.is_empty());
+
+        let description = describe_invoice(
+            &invoice,
+            Network::Bitcoin,
+            CREATED + Duration::from_secs(7200),
+        );
+        assert_eq!(
+            description.warnings,
+            vec![
+                "Invoice is for testnet, but the wallet is on bitcoin",
+                "Invoice has expired"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_describe_offer() {
+        let offer = offer();
+        let description = describe_offer(&offer, Network::Testnet, CREATED);
+        assert_eq!(
+            field(&description, "ID"),
+            Some(hex::encode(offer.id().0).as_str())
+        );
+        assert_eq!(field(&description, "Chains"), Some("testnet"));
+        assert_eq!(field(&description, "Amount"), Some("1000 msats per item"));
+        assert_eq!(field(&description, "Description"), Some("tea"));
+        assert_eq!(field(&description, "Quantity"), Some("1"));
+        assert_eq!(
+            field(&description, "Expires"),
+            Some("2023-11-14 23:13:20 UTC")
+        );
+        assert!(description.warnings.is_empty());
+
+        let description = describe_offer(
+            &offer,
+            Network::Bitcoin,
+            CREATED + Duration::from_secs(7200),
+        );
+        assert_eq!(
+            description.warnings,
+            vec![
+                "Offer is for testnet, but the wallet is on bitcoin",
+                "Offer has expired"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_describe_bip21() {
+        let uri = format!(
+            "bitcoin:{}?amount=0.0005&label=Shop&req-pop=1&lightning={}&lno={}",
+            testnet_address(),
+            invoice(),
+            offer()
+        );
+        let url = Url::parse(&uri).unwrap();
+
+        let description = describe_bip21(&url, Network::Testnet, CREATED).unwrap();
+        assert_eq!(
+            field(&description, "Address"),
+            Some(testnet_address().to_string().as_str())
+        );
+        assert_eq!(field(&description, "Amount"), Some("50000 sats"));
+        assert_eq!(field(&description, "Label"), Some("Shop"));
+        assert_eq!(
+            description.warnings,
+            vec!["Unknown required parameter req-pop; the URI must not be paid"]
+        );
+        let nested: Vec<_> = description.nested.iter().map(|d| d.title).collect();
+        assert_eq!(nested, vec!["⚡ BOLT11 Invoice", "⚡ BOLT12 Offer"]);
+        assert!(description.nested.iter().all(|d| d.warnings.is_empty()));
+
+        // Everything carried is checked against the wallet's network
+        let description = describe_bip21(&url, Network::Bitcoin, CREATED).unwrap();
+        assert_eq!(
+            description.warnings[0],
+            "Address is not for bitcoin, the wallet's network"
+        );
+        assert!(description.nested.iter().all(|d| !d.warnings.is_empty()));
+
+        // The address may be left out, and a broken Lightning request is only warned about
+        let url = Url::parse("bitcoin:?amount=abc&lightning=lnbc1broken").unwrap();
+        let description = describe_bip21(&url, Network::Testnet, CREATED).unwrap();
+        assert_eq!(field(&description, "Address"), None);
+        assert_eq!(description.warnings.len(), 2);
+        assert!(description.warnings[0].starts_with("Invalid amount abc"));
+        assert_eq!(
+            description.warnings[1],
+            "Invalid Lightning request: lnbc1broken"
+        );
+        assert!(description.nested.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_describe_lnurl_login() {
+        let k1 = hex::encode([3u8; 32]);
+        let input = format!(
+            "keyauth://service.com/login?tag=login&k1={}&action=link",
+            k1
+        );
+
+        // Login requests are described without contacting the service
+        let description = describe_lnurl(&input).await.unwrap();
+        assert_eq!(field(&description, "Type"), Some("login"));
+        assert_eq!(field(&description, "Domain"), Some("service.com"));
+        assert_eq!(field(&description, "Action"), Some("link"));
+        assert!(description.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_describe_psbt_and_tx() {
+        let tx = tx();
+        let description = describe_tx("₿ Raw Transaction", &tx, Network::Testnet, |_| None);
+        assert_eq!(
+            field(&description, "Txid"),
+            Some(tx.compute_txid().to_string().as_str())
+        );
+        assert_eq!(field(&description, "Replaceable"), Some("yes"));
+        assert_eq!(
+            field(&description, "Input 0"),
+            Some(format!("{}:1", bitcoin::Txid::all_zeros()).as_str())
+        );
+        assert_eq!(
+            field(&description, "Output 0"),
+            Some(format!("90000 sats to {}", testnet_address()).as_str())
+        );
+        assert_eq!(field(&description, "Total Out"), Some("90000 sats"));
+
+        // The fee is only known once the PSBT includes the spent output
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let description = describe_psbt(&psbt, Network::Testnet);
+        assert_eq!(field(&description, "Fee"), None);
+        assert_eq!(
+            description.warnings,
+            vec!["Fee unknown, the PSBT does not include every spent output"]
+        );
+
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: testnet_address().script_pubkey(),
+        });
+        let description = describe_psbt(&psbt, Network::Testnet);
+        assert_eq!(field(&description, "Fee"), Some("10000 sats"));
+        assert_eq!(
+            field(&description, "Input 0"),
+            Some(
+                format!(
+                    "{}:1 (100000 sats, 0 signatures)",
+                    bitcoin::Txid::all_zeros()
+                )
+                .as_str()
+            )
+        );
+        assert!(description.warnings.is_empty());
+    }
+}
@@ -1,10 +1,26 @@
 //! Lightning Network command implementations
 
-use ulw_core::Result;
+use bitcoin::secp256k1::PublicKey;
+use std::future::Future;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use ulw_core::{
+    types::{ChannelInfo, ChannelState, PaymentConstraints, PaymentStatus},
+    Error, Result,
+};
+use ulw_ldk::backup::RecoveryStatus;
+use ulw_ldk::lnurl::{fetch_lnurl, parse_auth, LnurlRequest};
+use ulw_ldk::payments::InvoiceOptions;
 use ulw_ldk::LdkNode;
+use ulw_storage::WalletDatabase;
 
 use crate::config::WalletConfig;
 
+/// How long to wait for an LNURL withdraw service to pay our invoice
+const LNURL_WITHDRAW_WAIT_SECS: u64 = 60;
+
 /// Create a Lightning node from wallet configuration
 async fn create_ldk_node(config: &WalletConfig) -> Result<LdkNode> {
     let ldk_storage = config.data_dir.join("lightning");
@@ -13,13 +29,84 @@ async fn create_ldk_node(config: &WalletConfig) -> Result<LdkNode> {
     // In production, this would be derived from the wallet's mnemonic
     let entropy_seed = derive_entropy_from_name(&config.wallet_name);
 
+    let wallet = Arc::new(crate::create_bdk_wallet(config).await?);
+    let storage = Arc::new(WalletDatabase::new(config.database_path())?);
+
     // Create node
-    LdkNode::new(config.network.network, ldk_storage, entropy_seed).await
+    let node = LdkNode::new(
+        config.network.network,
+        ldk_storage,
+        entropy_seed,
+        wallet,
+        storage,
+    )
+    .await?;
+    node.set_accept_keysend(config.accept_keysend);
+    node.set_inbound_channel_policy(config.inbound_channels.clone());
+    node.set_channel_backup_path(config.channel_backup_path())?;
+    if let Some(tower) = config.watchtower {
+        node.set_watchtower(tower).await?;
+    }
+    Ok(node)
+}
+
+/// Create a Lightning node and start its background processor
+async fn start_ldk_node(config: &WalletConfig) -> Result<LdkNode> {
+    let node = create_ldk_node(config).await?;
+    node.start()?;
+    Ok(node)
+}
+
+/// Run `task` on a started node, then shut the node down cleanly
+///
+/// Ctrl-C abandons the task but still stops the node so channel state is persisted.
+async fn run_until_interrupted<T>(
+    node: &LdkNode,
+    task: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let result = tokio::select! {
+        result = task => result,
+        _ = tokio::signal::ctrl_c() => {
+            println!("\n🛑 Interrupted, shutting down Lightning node...");
+            Err(Error::Internal("Interrupted".to_string()))
+        }
+    };
+    node.stop().await?;
+    result
+}
+
+/// Parse a peer given as `<pubkey>` or `<pubkey>@<host>:<port>`
+fn parse_peer(peer: &str) -> Result<(PublicKey, Option<SocketAddr>)> {
+    let (node_id, addr) = match peer.split_once('@') {
+        Some((node_id, addr)) => (node_id, Some(addr)),
+        None => (peer, None),
+    };
+
+    let node_id = node_id
+        .parse::<PublicKey>()
+        .map_err(|e| Error::Network(format!("Invalid node ID {}: {}", node_id, e)))?;
+
+    let addr = addr
+        .map(|addr| {
+            addr.to_socket_addrs()
+                .map_err(|e| Error::Network(format!("Invalid peer address {}: {}", addr, e)))?
+                .next()
+                .ok_or_else(|| Error::Network(format!("Could not resolve {}", addr)))
+        })
+        .transpose()?;
+
+    Ok((node_id, addr))
 }
 
 /// Derive a deterministic 32-byte seed from wallet name
 /// WARNING: This is for demo purposes only!
 /// In production, derive from the BIP39 mnemonic
+/// Convert an amount given in satoshis to millisatoshis, rejecting amounts that would overflow
+fn sats_to_msat(sats: u64) -> Result<u64> {
+    sats.checked_mul(1000)
+        .ok_or_else(|| Error::InvalidInvoice(format!("Amount of {} sats is too large", sats)))
+}
+
 fn derive_entropy_from_name(wallet_name: &str) -> [u8; 32] {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
@@ -39,11 +126,87 @@ fn derive_entropy_from_name(wallet_name: &str) -> [u8; 32] {
     entropy
 }
 
+/// Show the unified on-chain and Lightning balance
+pub async fn show_balance(config: &WalletConfig) -> Result<()> {
+    let node = create_ldk_node(config).await?;
+    let balance = node.get_unified_balance().await?;
+
+    println!("💰 Wallet Balance");
+    println!(
+        "  On-chain confirmed:   {} sats",
+        balance.confirmed.to_sat()
+    );
+    println!(
+        "  On-chain unconfirmed: {} sats",
+        balance.unconfirmed.to_sat()
+    );
+    println!(
+        "  Lightning:            {} sats ({} sats spendable)",
+        balance.lightning_total.to_sat(),
+        balance.lightning_available.to_sat()
+    );
+    if balance.pending_sweep > bitcoin::Amount::ZERO {
+        println!(
+            "  Pending sweep:        {} sats (from closed channels)",
+            balance.pending_sweep.to_sat()
+        );
+    }
+    println!("  Total: {} sats", balance.total().to_sat());
+
+    let reserve = node.anchor_reserve().await?;
+    if reserve.anchor_channels > 0 {
+        println!(
+            "  Anchor reserve:       {} sats for {} channels",
+            reserve.required_sats, reserve.anchor_channels
+        );
+        if !reserve.is_sufficient() {
+            println!(
+                "⚠️  Confirmed on-chain balance is below the reserve needed to fee bump force closes"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Show the size and freshness of the network graph
+pub async fn show_graph_stats(config: &WalletConfig) -> Result<()> {
+    let node = create_ldk_node(config).await?;
+    let stats = node.graph_stats();
+
+    println!("🕸️  Network Graph");
+    println!("  Nodes:    {}", stats.nodes);
+    println!("  Channels: {}", stats.channels);
+    match stats.last_rapid_sync {
+        Some(time) => println!("  Last rapid gossip sync: {}", time),
+        None => println!("  Last rapid gossip sync: never"),
+    }
+
+    Ok(())
+}
+
+/// Load a Rapid Gossip Sync snapshot into the network graph
+pub async fn sync_graph(config: &WalletConfig, source: String) -> Result<()> {
+    println!("🔄 Loading rapid gossip snapshot from {}...", source);
+
+    let node = create_ldk_node(config).await?;
+    let timestamp = node.sync_rapid_gossip(&source).await?;
+    let stats = node.graph_stats();
+
+    println!("✅ Graph synced!");
+    println!("  Nodes:    {}", stats.nodes);
+    println!("  Channels: {}", stats.channels);
+    println!("  Snapshot timestamp: {}", timestamp);
+
+    Ok(())
+}
+
 /// Create a Lightning invoice
 pub async fn create_invoice(
     config: &WalletConfig,
     amount_sats: u64,
     description: Option<String>,
+    options: InvoiceOptions,
 ) -> Result<()> {
     println!("⚡ Creating Lightning Invoice");
 
@@ -52,7 +215,9 @@ pub async fn create_invoice(
     let amount_msats = amount_sats * 1000;
     let desc = description.unwrap_or_else(|| "Payment request".to_string());
 
-    let invoice = node.create_invoice(Some(amount_msats), desc, 3600).await?;
+    let invoice = node
+        .create_invoice(Some(amount_msats), desc, &options)
+        .await?;
 
     println!("\n✅ Invoice created!");
     println!("Amount: {} sats ({} msats)", amount_sats, amount_msats);
@@ -64,19 +229,564 @@ pub async fn create_invoice(
     Ok(())
 }
 
+/// Create a hold invoice for a payment hash whose preimage only the caller knows
+pub async fn create_hold_invoice(
+    config: &WalletConfig,
+    amount_sats: u64,
+    payment_hash: String,
+    description: Option<String>,
+    options: InvoiceOptions,
+) -> Result<()> {
+    println!("⚡ Creating Hold Invoice");
+
+    let amount_msats = sats_to_msat(amount_sats)?;
+    let node = create_ldk_node(config).await?;
+
+    let desc = description.unwrap_or_else(|| "Payment request".to_string());
+
+    let invoice = node
+        .create_hold_invoice(Some(amount_msats), &payment_hash, desc, &options)
+        .await?;
+
+    println!("\n✅ Hold invoice created!");
+    println!("Amount: {} sats ({} msats)", amount_sats, amount_msats);
+    println!("Payment Hash: {}", payment_hash);
+    println!("\nInvoice:");
+    println!("{}", invoice);
+    println!(
+        "\n💡 Incoming payments are held until 'ulw invoice settle <preimage>' or \
+         'ulw invoice cancel <payment_hash>'"
+    );
+
+    Ok(())
+}
+
+/// Settle a held payment with its preimage
+pub async fn settle_hold_invoice(config: &WalletConfig, preimage: String) -> Result<()> {
+    println!("⚡ Settling Hold Invoice");
+
+    let node = start_ldk_node(config).await?;
+    let payment_hash = run_until_interrupted(&node, node.settle_hold_invoice(&preimage)).await?;
+
+    println!("\n✅ Payment settled!");
+    println!("Payment Hash: {}", payment_hash);
+
+    Ok(())
+}
+
+/// Cancel a hold invoice, failing back any held HTLCs
+pub async fn cancel_hold_invoice(config: &WalletConfig, payment_hash: String) -> Result<()> {
+    println!("⚡ Cancelling Hold Invoice");
+
+    let node = start_ldk_node(config).await?;
+    run_until_interrupted(&node, node.cancel_hold_invoice(&payment_hash)).await?;
+
+    println!("\n✅ Hold invoice cancelled!");
+    println!("Payment Hash: {}", payment_hash);
+
+    Ok(())
+}
+
 /// Pay a Lightning invoice
-pub async fn pay_invoice(config: &WalletConfig, invoice_str: String) -> Result<()> {
+pub async fn pay_invoice(
+    config: &WalletConfig,
+    invoice_str: String,
+    amount_sats: Option<u64>,
+    constraints: PaymentConstraints,
+) -> Result<()> {
     println!("⚡ Paying Lightning Invoice");
 
+    let amount_msat = amount_sats.map(sats_to_msat).transpose()?;
+    let node = start_ldk_node(config).await?;
+
+    println!("Sending payment...");
+    let sent = run_until_interrupted(
+        &node,
+        node.pay_invoice(&invoice_str, amount_msat, &constraints),
+    )
+    .await?;
+
+    println!("\n✅ Payment sent!");
+    println!("Payment Hash: {}", hex::encode(sent.payment_hash.0));
+    println!("Preimage: {}", hex::encode(sent.preimage.0));
+    println!("Amount: {} msats", sent.amount_msat);
+    if let Some(fee) = sent.fee_paid_msat {
+        println!("Fee paid: {} msats", fee);
+    }
+
+    Ok(())
+}
+
+/// Pay an LNURL pay request or Lightning Address, or withdraw from an LNURL withdraw request
+pub async fn pay_lnurl(
+    config: &WalletConfig,
+    lnurl: String,
+    amount_sats: Option<u64>,
+    comment: Option<String>,
+    constraints: PaymentConstraints,
+) -> Result<()> {
+    let amount_msat = amount_sats.map(sats_to_msat).transpose()?;
+
+    match fetch_lnurl(&lnurl).await? {
+        LnurlRequest::Pay(request) => {
+            println!("⚡ Paying LNURL");
+            if let Some(description) = request.description() {
+                println!("Description: {}", description);
+            }
+            println!(
+                "Accepts: {} - {} sats",
+                request.min_sendable_msat / 1000,
+                request.max_sendable_msat / 1000
+            );
+
+            let node = start_ldk_node(config).await?;
+            println!("Sending payment...");
+            let sent = run_until_interrupted(
+                &node,
+                node.pay_lnurl_request(&request, amount_msat, comment.as_deref(), &constraints),
+            )
+            .await?;
+
+            println!("\n✅ Payment sent!");
+            println!("Payment Hash: {}", hex::encode(sent.payment_hash.0));
+            println!("Preimage: {}", hex::encode(sent.preimage.0));
+            println!("Amount: {} msats", sent.amount_msat);
+            if let Some(fee) = sent.fee_paid_msat {
+                println!("Fee paid: {} msats", fee);
+            }
+        }
+        LnurlRequest::Withdraw(request) => {
+            println!("⚡ Withdrawing from LNURL");
+            if !request.default_description.is_empty() {
+                println!("Description: {}", request.default_description);
+            }
+            println!(
+                "Allows: {} - {} sats",
+                request.min_withdrawable_msat / 1000,
+                request.max_withdrawable_msat / 1000
+            );
+
+            let node = start_ldk_node(config).await?;
+            let received = async {
+                let payment_hash = node.withdraw_lnurl_request(&request, amount_msat).await?;
+                println!("Waiting for the service to pay...");
+                for _ in 0..LNURL_WITHDRAW_WAIT_SECS {
+                    let paid = node.list_payments().await?.into_iter().find(|payment| {
+                        payment.payment_hash == payment_hash
+                            && payment.status == PaymentStatus::Succeeded
+                    });
+                    if let Some(payment) = paid {
+                        return Ok(payment);
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(Error::PaymentFailed(format!(
+                    "Service accepted the withdrawal but did not pay invoice {} in time",
+                    payment_hash
+                )))
+            };
+            let payment = run_until_interrupted(&node, received).await?;
+
+            println!("\n✅ Withdrawal received!");
+            println!("Payment Hash: {}", payment.payment_hash);
+            println!("Amount: {} msats", payment.amount_msat);
+        }
+    }
+
+    Ok(())
+}
+
+/// Log in to the service behind an LNURL-auth login request
+pub async fn lnurl_auth(config: &WalletConfig, lnurl: String) -> Result<()> {
+    let request = parse_auth(&lnurl)?;
+    println!("🔑 Logging in to {}", request.domain);
+    if let Some(action) = &request.action {
+        println!("Action: {}", action);
+    }
+
+    let node = create_ldk_node(config).await?;
+    let record = node.lnurl_auth_request(&request).await?;
+
+    println!("\n✅ Logged in!");
+    println!("Linking Key: {}", record.linking_key);
+    if record.first_login_at != record.last_login_at {
+        println!(
+            "First login: {}",
+            record.first_login_at.format("%Y-%m-%d %H:%M")
+        );
+    }
+
+    Ok(())
+}
+
+/// List the services logged in to with LNURL-auth
+pub async fn list_lnurl_auths(config: &WalletConfig) -> Result<()> {
+    println!("🔑 LNURL-auth Logins");
+
+    let node = create_ldk_node(config).await?;
+    let records = node.list_lnurl_auths().await?;
+
+    if records.is_empty() {
+        println!("\nNo logins found.");
+        return Ok(());
+    }
+
+    for record in records {
+        println!("\n{}", record.domain);
+        println!("   Linking Key: {}", record.linking_key);
+        println!(
+            "   First login: {}",
+            record.first_login_at.format("%Y-%m-%d %H:%M")
+        );
+        println!(
+            "   Last login: {}",
+            record.last_login_at.format("%Y-%m-%d %H:%M")
+        );
+    }
+
+    Ok(())
+}
+
+/// Send a keysend payment to a node
+pub async fn keysend(
+    config: &WalletConfig,
+    node_id: PublicKey,
+    amount_sats: u64,
+    custom_tlvs: Vec<(u64, Vec<u8>)>,
+) -> Result<()> {
+    let amount_msat = sats_to_msat(amount_sats)?;
+    println!("⚡ Sending {} sats to {}", amount_sats, node_id);
+
+    let node = start_ldk_node(config).await?;
+
+    println!("Sending payment...");
+    let sent =
+        run_until_interrupted(&node, node.send_keysend(node_id, amount_msat, custom_tlvs)).await?;
+
+    println!("\n✅ Payment sent!");
+    println!("Payment Hash: {}", hex::encode(sent.payment_hash.0));
+    println!("Preimage: {}", hex::encode(sent.preimage.0));
+    println!("Amount: {} msats", sent.amount_msat);
+    if let Some(fee) = sent.fee_paid_msat {
+        println!("Fee paid: {} msats", fee);
+    }
+
+    Ok(())
+}
+
+/// Connect to a peer given as `<pubkey>@<host>:<port>`, to route offer messages through
+async fn connect_to(node: &LdkNode, peer: &str) -> Result<()> {
+    match parse_peer(peer)? {
+        (node_id, Some(addr)) => node.connect_peer(node_id, addr).await,
+        (_, None) => Err(Error::Network(format!(
+            "Peer {} needs an address, as <node_id>@<host>:<port>",
+            peer
+        ))),
+    }
+}
+
+/// Create a BOLT12 offer
+pub async fn create_offer(
+    config: &WalletConfig,
+    amount_sats: Option<u64>,
+    description: Option<String>,
+    quantity_max: Option<u64>,
+    peer: String,
+) -> Result<()> {
+    println!("⚡ Creating BOLT12 Offer");
+
+    let amount_msat = amount_sats.map(sats_to_msat).transpose()?;
+    let node = start_ldk_node(config).await?;
+    let offer = run_until_interrupted(&node, async {
+        connect_to(&node, &peer).await?;
+        node.create_offer(
+            amount_msat,
+            description.unwrap_or_else(|| "Payment request".to_string()),
+            quantity_max,
+        )
+        .await
+    })
+    .await?;
+
+    println!("\n✅ Offer created!");
+    match amount_sats {
+        Some(amount) => println!("Amount: {} sats", amount),
+        None => println!("Amount: chosen by the payer"),
+    }
+    println!("\nOffer:");
+    println!("{}", offer);
+    println!("\n💡 The node must be running and connected to answer payers of this offer");
+
+    Ok(())
+}
+
+/// Pay a BOLT12 offer
+pub async fn pay_offer(
+    config: &WalletConfig,
+    offer: String,
+    amount_sats: Option<u64>,
+    quantity: Option<u64>,
+    payer_note: Option<String>,
+    peer: String,
+) -> Result<()> {
+    println!("⚡ Paying BOLT12 Offer");
+
+    let amount_msat = amount_sats.map(sats_to_msat).transpose()?;
+    let node = start_ldk_node(config).await?;
+
+    println!("Requesting invoice...");
+    let sent = run_until_interrupted(&node, async {
+        connect_to(&node, &peer).await?;
+        node.pay_offer(&offer, amount_msat, quantity, payer_note)
+            .await
+    })
+    .await?;
+
+    println!("\n✅ Payment sent!");
+    println!("Payment Hash: {}", hex::encode(sent.payment_hash.0));
+    println!("Preimage: {}", hex::encode(sent.preimage.0));
+    println!("Amount: {} msats", sent.amount_msat);
+    if let Some(fee) = sent.fee_paid_msat {
+        println!("Fee paid: {} msats", fee);
+    }
+
+    Ok(())
+}
+
+/// List BOLT12 offers and refunds created or paid by this wallet
+pub async fn list_offers(config: &WalletConfig) -> Result<()> {
+    println!("⚡ BOLT12 Offers and Refunds");
+
     let node = create_ldk_node(config).await?;
+    let offers = node.list_offers().await?;
 
-    println!("Parsing invoice...");
-    let payment_hash = node.pay_invoice(invoice_str.clone()).await?;
+    if offers.is_empty() {
+        println!("\nNo offers found.");
+        return Ok(());
+    }
 
-    println!("\n✅ Payment initiated!");
-    println!("Payment Hash: {}", hex::encode(payment_hash.0));
-    println!("\n⚠️  Note: Full payment routing requires active channels");
-    println!("   This is a simplified implementation for testing");
+    for offer in offers {
+        println!(
+            "\n{:?} ({:?}) {}",
+            offer.kind,
+            offer.direction,
+            offer.created_at.format("%Y-%m-%d %H:%M")
+        );
+        println!("   ID: {}", offer.id);
+        if let Some(amount) = offer.amount_msat {
+            println!("   Amount: {} msats", amount);
+        }
+        if let Some(description) = &offer.description {
+            println!("   Description: {}", description);
+        }
+        if let Some(max) = offer.quantity_max {
+            println!("   Quantity: up to {}", max);
+        }
+        println!("   {}", offer.encoded);
+    }
+
+    Ok(())
+}
+
+/// Create a BOLT12 refund paying out `amount_sats`
+pub async fn create_refund(
+    config: &WalletConfig,
+    amount_sats: u64,
+    description: Option<String>,
+    expiry_secs: u64,
+    peer: String,
+) -> Result<()> {
+    println!("⚡ Creating BOLT12 Refund");
+
+    let amount_msat = sats_to_msat(amount_sats)?;
+    let node = start_ldk_node(config).await?;
+    let refund = run_until_interrupted(&node, async {
+        connect_to(&node, &peer).await?;
+        node.create_refund(
+            amount_msat,
+            description.unwrap_or_else(|| "Refund".to_string()),
+            expiry_secs,
+        )
+        .await
+    })
+    .await?;
+
+    println!("\n✅ Refund created!");
+    println!("Amount: {} sats", amount_sats);
+    println!("\nRefund:");
+    println!("{}", refund);
+    println!("\n💡 The recipient's invoice is paid while the node is running and connected");
+
+    Ok(())
+}
+
+/// Claim a BOLT12 refund by sending its creator an invoice
+pub async fn request_refund(config: &WalletConfig, refund: String, peer: String) -> Result<()> {
+    println!("⚡ Requesting Refund Payment");
+
+    let node = start_ldk_node(config).await?;
+    let payment_hash = run_until_interrupted(&node, async {
+        connect_to(&node, &peer).await?;
+        node.request_refund_payment(&refund).await
+    })
+    .await?;
+
+    println!("\n✅ Invoice sent!");
+    println!("Payment Hash: {}", payment_hash);
+    println!("\n💡 The payment arrives once the refund's creator pays the invoice");
+
+    Ok(())
+}
+
+/// Open a channel funded from the on-chain wallet
+pub async fn open_channel(
+    config: &WalletConfig,
+    peer: String,
+    amount_sats: u64,
+    push_msat: u64,
+    announce: bool,
+) -> Result<()> {
+    let (node_id, addr) = parse_peer(&peer)?;
+    println!(
+        "⚡ Opening channel to {} with {} satoshis",
+        node_id, amount_sats
+    );
+
+    let addr = addr.ok_or_else(|| {
+        Error::Network("Peer address required, use <node_id>@<host>:<port>".to_string())
+    })?;
+
+    let node = start_ldk_node(config).await?;
+    let channel_id = run_until_interrupted(&node, async {
+        node.connect_peer(node_id, addr).await?;
+        node.open_channel(node_id, amount_sats, push_msat, announce)
+            .await
+    })
+    .await?;
+
+    println!("\n✅ Channel funding broadcast!");
+    println!("Channel ID: {}", channel_id);
+    println!("State: {:?}", ChannelState::Opening);
+    println!("\n💡 The channel becomes active once the funding transaction confirms");
+
+    Ok(())
+}
+
+/// Close a channel cooperatively, or unilaterally with `force`
+pub async fn close_channel(
+    config: &WalletConfig,
+    channel_id: String,
+    force: bool,
+    fee_rate: Option<u32>,
+    peer_addr: Option<String>,
+) -> Result<()> {
+    if force {
+        println!("⚡ Force closing channel {}", channel_id);
+    } else {
+        println!("⚡ Closing channel {}", channel_id);
+    }
+
+    let node = start_ldk_node(config).await?;
+    let channel = run_until_interrupted(&node, async {
+        if let Some(addr) = peer_addr {
+            let channel = node
+                .list_channels()
+                .await?
+                .into_iter()
+                .find(|c| c.channel_id == channel_id)
+                .ok_or_else(|| Error::ChannelNotFound(channel_id.clone()))?;
+            let (node_id, addr) =
+                parse_peer(&format!("{}@{}", channel.counterparty_node_id, addr))?;
+            if let Some(addr) = addr {
+                node.connect_peer(node_id, addr).await?;
+            }
+        }
+
+        node.close_channel(&channel_id, force, fee_rate).await
+    })
+    .await?;
+
+    println!("\n✅ Channel closed!");
+    print_channel_close_details(&channel);
+
+    Ok(())
+}
+
+/// Ask the peers of the channels in a static channel backup to force close them
+pub async fn recover_channels(config: &WalletConfig, backup_path: PathBuf) -> Result<()> {
+    println!("⚡ Recovering channels from {}", backup_path.display());
+
+    let bytes = std::fs::read(&backup_path)
+        .map_err(|e| Error::InvalidConfig(format!("Failed to read backup: {}", e)))?;
+    let node = start_ldk_node(config).await?;
+    let backup = node.read_channel_backup(&bytes)?;
+    let results = run_until_interrupted(&node, node.recover_channels(&backup)).await?;
+
+    if results.is_empty() {
+        println!("No channels in backup");
+        return Ok(());
+    }
+
+    for (i, (channel, status)) in results.iter().enumerate() {
+        println!("\n{}. Channel ID: {}", i + 1, channel.channel_id);
+        println!("   Peer: {}", channel.peer_node_id);
+        println!("   Funding outpoint: {}", channel.funding_outpoint);
+        println!("   Capacity: {} sats", channel.capacity_sats);
+        match status {
+            RecoveryStatus::CloseRequested if channel.channel_keys_id.is_some() => println!(
+                "   ✅ Peer asked to force close; our balance is swept to the wallet once the \
+                 close confirms"
+            ),
+            RecoveryStatus::CloseRequested => println!(
+                "   ⚠️  Peer asked to force close, but the backup predates sweeping; our balance \
+                 is not swept automatically"
+            ),
+            RecoveryStatus::StillOpen => println!("   Channel state still available, left open"),
+            RecoveryStatus::NoAddress => println!("   ⚠️  No address known for the peer"),
+            RecoveryStatus::Unreachable(e) => println!("   ⚠️  Peer unreachable: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_channel_close_details(channel: &ChannelInfo) {
+    if let Some(txid) = &channel.closing_txid {
+        println!("   Closing TXID: {}", txid);
+    }
+    if let Some(height) = channel.claimable_at_height {
+        println!("   Funds claimable at block height: {}", height);
+    }
+}
+
+/// List Lightning channels
+pub async fn list_channels(config: &WalletConfig) -> Result<()> {
+    println!("⚡ Lightning Channels");
+
+    let node = create_ldk_node(config).await?;
+    let channels = node.list_channels().await?;
+
+    if channels.is_empty() {
+        println!("No channels yet");
+        println!("   Open one with: ulw channels open <node_id>@<host>:<port> <amount>");
+        return Ok(());
+    }
+
+    for (i, channel) in channels.iter().enumerate() {
+        println!("\n{}. Channel ID: {}", i + 1, channel.channel_id);
+        println!("   Peer: {}", channel.counterparty_node_id);
+        println!("   Capacity: {} sats", channel.capacity_sats);
+        println!(
+            "   Local balance: {} sats",
+            channel.local_balance_msat / 1000
+        );
+        println!(
+            "   Remote balance: {} sats",
+            channel.remote_balance_msat / 1000
+        );
+        println!("   State: {:?}", channel.state);
+        print_channel_close_details(channel);
+    }
 
     Ok(())
 }
@@ -97,15 +807,17 @@ pub async fn list_payments(config: &WalletConfig) -> Result<()> {
 
     println!("Found {} payment(s):\n", payments.len());
     for (i, payment) in payments.iter().enumerate() {
+        println!("{}. Payment Hash: {}", i + 1, payment.payment_hash);
         println!(
-            "{}. Payment Hash: {}",
-            i + 1,
-            hex::encode(payment.payment_hash)
+            "   Amount: {} msats ({} sats)",
+            payment.amount_msat,
+            payment.amount_msat / 1000
         );
-        if let Some(amt) = payment.amount_msat {
-            println!("   Amount: {} msats ({} sats)", amt, amt / 1000);
-        }
+        println!("   Direction: {:?}", payment.direction);
         println!("   Status: {:?}", payment.status);
+        if let Some(settled_at) = payment.settled_at {
+            println!("   Settled: {}", settled_at);
+        }
         println!();
     }
 
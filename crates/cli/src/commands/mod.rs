@@ -1,7 +1,14 @@
 //! CLI command modules
 
+pub mod decode;
 pub mod init;
 pub mod lightning;
 
+pub use decode::decode;
 pub use init::init_wallet;
-pub use lightning::{create_invoice, pay_invoice};
+pub use lightning::{
+    cancel_hold_invoice, close_channel, create_hold_invoice, create_invoice, create_offer,
+    create_refund, keysend, list_channels, list_lnurl_auths, list_offers, lnurl_auth, open_channel,
+    pay_invoice, pay_lnurl, pay_offer, recover_channels, request_refund, settle_hold_invoice,
+    show_balance, show_graph_stats, sync_graph,
+};
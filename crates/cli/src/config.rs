@@ -1,14 +1,28 @@
 //! Wallet configuration and management
 
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use ulw_core::{types::NetworkConfig, Error, Result};
+use ulw_core::types::{InboundChannelPolicy, NetworkConfig};
+use ulw_core::{Error, Result};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletConfig {
     pub data_dir: PathBuf,
     pub network: NetworkConfig,
     pub wallet_name: String,
+    /// Claim incoming keysend payments instead of failing them back
+    #[serde(default)]
+    pub accept_keysend: bool,
+    /// Which peers may open channels to us
+    #[serde(default)]
+    pub inbound_channels: InboundChannelPolicy,
+    /// Where to keep the static channel backup, `channels.backup` in the data directory if unset
+    #[serde(default)]
+    pub channel_backup_path: Option<PathBuf>,
+    /// Watchtower to back up justice transactions to
+    #[serde(default)]
+    pub watchtower: Option<SocketAddr>,
 }
 
 impl WalletConfig {
@@ -18,6 +32,10 @@ impl WalletConfig {
             data_dir,
             network,
             wallet_name: "default".to_string(),
+            accept_keysend: false,
+            inbound_channels: InboundChannelPolicy::default(),
+            channel_backup_path: None,
+            watchtower: None,
         }
     }
 
@@ -34,11 +52,16 @@ impl WalletConfig {
         Ok(())
     }
 
-    #[allow(dead_code)]
     pub fn database_path(&self) -> PathBuf {
         self.data_dir.join(format!("{}.db", self.wallet_name))
     }
 
+    pub fn channel_backup_path(&self) -> PathBuf {
+        self.channel_backup_path
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("channels.backup"))
+    }
+
     pub fn config_path(&self) -> PathBuf {
         self.data_dir.join("config.json")
     }
@@ -51,6 +74,10 @@ impl Default for WalletConfig {
             data_dir: home_dir.join(".ulw"),
             network: NetworkConfig::default(),
             wallet_name: "default".to_string(),
+            accept_keysend: false,
+            inbound_channels: InboundChannelPolicy::default(),
+            channel_backup_path: None,
+            watchtower: None,
         }
     }
 }
@@ -3,11 +3,15 @@
 mod commands;
 mod config;
 
+use bitcoin::secp256k1::PublicKey;
 use clap::{Parser, Subcommand};
 use config::WalletConfig;
+use std::path::PathBuf;
 use tracing_subscriber::EnvFilter;
 use ulw_bdk::BdkWallet;
+use ulw_core::types::PaymentConstraints;
 use ulw_core::Result;
+use ulw_ldk::payments::InvoiceOptions;
 
 #[derive(Parser)]
 #[command(name = "ulw")]
@@ -57,19 +61,189 @@ enum Commands {
         action: Option<ChannelCommands>,
     },
 
-    /// Create a Lightning invoice
+    /// Create a Lightning invoice, or settle or cancel a hold invoice
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Invoice {
+        #[command(subcommand)]
+        action: Option<InvoiceCommands>,
         /// Amount in satoshis
-        amount: u64,
+        #[arg(required = true)]
+        amount: Option<u64>,
         /// Invoice description
         #[arg(short, long)]
         description: Option<String>,
+        /// Hold incoming HTLCs until the invoice is settled or cancelled
+        #[arg(long, requires = "payment_hash")]
+        hold: bool,
+        /// Payment hash of the hold invoice, from a preimage known only to you
+        #[arg(long, requires = "hold")]
+        payment_hash: Option<String>,
+        /// Seconds until the invoice expires
+        #[arg(long, default_value = "3600")]
+        expiry: u32,
+        /// Blocks the payment's final HTLC must have left when it reaches us
+        #[arg(long, default_value = "144")]
+        cltv_delta: u16,
+        /// Include a fresh on-chain address the payer can fall back to
+        #[arg(long)]
+        fallback: bool,
     },
 
-    /// Pay a Lightning invoice
+    /// Pay a Lightning invoice, LNURL or Lightning Address, or withdraw from an LNURL
     Pay {
-        /// BOLT11 invoice
+        /// BOLT11 invoice, LNURL or Lightning Address (user@domain)
         invoice: String,
+        /// Amount in satoshis, for invoices that do not specify one
+        #[arg(short, long)]
+        amount: Option<u64>,
+        /// Comment for the recipient of an LNURL payment
+        #[arg(long)]
+        comment: Option<String>,
+        /// Most routing fees to pay, in satoshis
+        #[arg(long)]
+        max_fee_sats: Option<u64>,
+        /// Most routing fees to pay, as a percentage of the amount
+        #[arg(long)]
+        max_fee_percent: Option<f64>,
+        /// Most blocks the payment may be locked up for
+        #[arg(long)]
+        max_cltv: Option<u32>,
+        /// Node ID to route around; may be repeated
+        #[arg(long)]
+        avoid: Vec<PublicKey>,
+        /// Most paths to split the payment over
+        #[arg(long)]
+        max_paths: Option<u8>,
+    },
+
+    /// Pay a node directly, without an invoice
+    Keysend {
+        /// Node ID to pay
+        node_id: PublicKey,
+        /// Amount in satoshis
+        amount: u64,
+        /// Custom TLV record as <type>=<hex value>; may be repeated
+        #[arg(long = "tlv", value_parser = parse_tlv)]
+        tlvs: Vec<(u64, Vec<u8>)>,
+    },
+
+    /// Show what an invoice, offer, BIP21 URI, LNURL, PSBT or raw transaction contains
+    Decode {
+        /// Text to decode
+        input: String,
+    },
+
+    /// Log in to a service with LNURL-auth, or list the services logged in to
+    LnurlAuth {
+        /// LNURL-auth login request; lists the services logged in to if omitted
+        lnurl: Option<String>,
+    },
+
+    /// Create, pay and list BOLT12 offers
+    Offer {
+        #[command(subcommand)]
+        action: OfferCommands,
+    },
+
+    /// Create and claim BOLT12 refunds
+    Refund {
+        #[command(subcommand)]
+        action: RefundCommands,
+    },
+
+    /// Inspect and sync the Lightning network graph
+    Graph {
+        #[command(subcommand)]
+        action: GraphCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum InvoiceCommands {
+    /// Settle a held payment, claiming its HTLCs
+    Settle {
+        /// Hex-encoded preimage of the payment hash
+        preimage: String,
+    },
+    /// Cancel a hold invoice, failing back any held HTLCs
+    Cancel {
+        /// Hex-encoded payment hash
+        payment_hash: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum OfferCommands {
+    /// Create an offer that can be paid repeatedly
+    Create {
+        /// Amount in satoshis per item; the payer chooses if omitted
+        #[arg(short, long)]
+        amount: Option<u64>,
+        /// Offer description
+        #[arg(short, long)]
+        description: Option<String>,
+        /// Most items that can be paid for at once
+        #[arg(long)]
+        quantity_max: Option<u64>,
+        /// Peer to receive invoice requests through, as <node_id>@<host>:<port>
+        #[arg(long)]
+        peer: String,
+    },
+    /// Pay an offer
+    Pay {
+        /// BOLT12 offer
+        offer: String,
+        /// Amount in satoshis, for offers that do not specify one
+        #[arg(short, long)]
+        amount: Option<u64>,
+        /// Number of items to pay for
+        #[arg(short, long)]
+        quantity: Option<u64>,
+        /// Note for the recipient
+        #[arg(long)]
+        note: Option<String>,
+        /// Peer to send the invoice request through, as <node_id>@<host>:<port>
+        #[arg(long)]
+        peer: String,
+    },
+    /// List offers and refunds created or paid by this wallet
+    List,
+}
+
+#[derive(Subcommand)]
+enum RefundCommands {
+    /// Create a refund that the holder can claim from this wallet
+    Create {
+        /// Amount in satoshis
+        amount: u64,
+        /// Refund description
+        #[arg(short, long)]
+        description: Option<String>,
+        /// Seconds the refund can be claimed for
+        #[arg(long, default_value = "3600")]
+        expiry: u64,
+        /// Peer to receive the invoice through, as <node_id>@<host>:<port>
+        #[arg(long)]
+        peer: String,
+    },
+    /// Claim a refund by sending its creator an invoice
+    Request {
+        /// BOLT12 refund
+        refund: String,
+        /// Peer to send the invoice through, as <node_id>@<host>:<port>
+        #[arg(long)]
+        peer: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum GraphCommands {
+    /// Show node and channel counts and the last sync time
+    Stats,
+    /// Load a Rapid Gossip Sync snapshot
+    Sync {
+        /// Snapshot file path or http(s) URL
+        source: String,
     },
 }
 
@@ -79,15 +253,35 @@ enum ChannelCommands {
     List,
     /// Open a new channel
     Open {
-        /// Node public key
+        /// Node public key, as <node_id>@<host>:<port>
         node_id: String,
         /// Channel capacity in satoshis
         amount: u64,
+        /// Amount to push to the counterparty in millisatoshis
+        #[arg(long, default_value = "0")]
+        push_msat: u64,
+        /// Announce the channel to the network
+        #[arg(long)]
+        announce: bool,
     },
     /// Close a channel
     Close {
         /// Channel ID
         channel_id: String,
+        /// Force close by broadcasting our latest commitment transaction
+        #[arg(long)]
+        force: bool,
+        /// Target fee rate for a cooperative close in sat/vB
+        #[arg(long)]
+        fee_rate: Option<u32>,
+        /// Peer address (<host>:<port>) to reconnect to for a cooperative close
+        #[arg(long)]
+        peer: Option<String>,
+    },
+    /// Ask the peers of channels in a static channel backup to force close them
+    Recover {
+        /// Path to the backup file
+        backup: PathBuf,
     },
 }
 
@@ -110,10 +304,7 @@ async fn main() -> Result<()> {
         }
         Commands::Balance => {
             let config = load_config()?;
-            let wallet = create_bdk_wallet(&config).await?;
-            let balance = wallet.get_balance().await?;
-            println!("💰 Wallet Balance");
-            println!("  Total: {} sats", balance.to_sat());
+            commands::show_balance(&config).await?;
         }
         Commands::Receive => {
             let config = load_config()?;
@@ -167,35 +358,188 @@ async fn main() -> Result<()> {
         }
         Commands::Channels { action } => match action {
             None | Some(ChannelCommands::List) => {
-                println!("⚡ Lightning Channels");
-                println!("📝 Channel management coming soon!");
-                println!("   For now, use invoice/pay commands for Lightning");
+                let config = load_config()?;
+                commands::list_channels(&config).await?;
             }
-            Some(ChannelCommands::Open { node_id, amount }) => {
-                println!("⚡ Opening channel to {} with {} satoshis", node_id, amount);
-                println!("📝 Channel management coming soon!");
+            Some(ChannelCommands::Open {
+                node_id,
+                amount,
+                push_msat,
+                announce,
+            }) => {
+                let config = load_config()?;
+                commands::open_channel(&config, node_id, amount, push_msat, announce).await?;
             }
-            Some(ChannelCommands::Close { channel_id }) => {
-                println!("⚡ Closing channel {}", channel_id);
-                println!("📝 Channel management coming soon!");
+            Some(ChannelCommands::Close {
+                channel_id,
+                force,
+                fee_rate,
+                peer,
+            }) => {
+                let config = load_config()?;
+                commands::close_channel(&config, channel_id, force, fee_rate, peer).await?;
+            }
+            Some(ChannelCommands::Recover { backup }) => {
+                let config = load_config()?;
+                commands::recover_channels(&config, backup).await?;
             }
         },
         Commands::Invoice {
+            action,
             amount,
             description,
+            hold: _,
+            payment_hash,
+            expiry,
+            cltv_delta,
+            fallback,
         } => {
             let config = load_config()?;
-            commands::create_invoice(&config, amount, description).await?;
+            match action {
+                Some(InvoiceCommands::Settle { preimage }) => {
+                    commands::settle_hold_invoice(&config, preimage).await?
+                }
+                Some(InvoiceCommands::Cancel { payment_hash }) => {
+                    commands::cancel_hold_invoice(&config, payment_hash).await?
+                }
+                None => {
+                    let amount = amount.expect("amount is required without a subcommand");
+                    let options = InvoiceOptions {
+                        expiry_secs: expiry,
+                        min_final_cltv_expiry_delta: cltv_delta,
+                        fallback_address: fallback,
+                        ..Default::default()
+                    };
+                    match payment_hash {
+                        Some(payment_hash) => {
+                            commands::create_hold_invoice(
+                                &config,
+                                amount,
+                                payment_hash,
+                                description,
+                                options,
+                            )
+                            .await?
+                        }
+                        None => {
+                            commands::create_invoice(&config, amount, description, options).await?
+                        }
+                    }
+                }
+            }
         }
-        Commands::Pay { invoice } => {
+        Commands::Pay {
+            invoice,
+            amount,
+            comment,
+            max_fee_sats,
+            max_fee_percent,
+            max_cltv,
+            avoid,
+            max_paths,
+        } => {
             let config = load_config()?;
-            commands::pay_invoice(&config, invoice).await?;
+            let max_fee_msat = max_fee_sats
+                .map(|sats| {
+                    sats.checked_mul(1000).ok_or_else(|| {
+                        ulw_core::Error::RouteConstraints(format!(
+                            "Maximum fee of {} sats is too large",
+                            sats
+                        ))
+                    })
+                })
+                .transpose()?;
+            let constraints = PaymentConstraints {
+                max_fee_msat,
+                max_fee_percent,
+                max_cltv_expiry_delta: max_cltv,
+                avoid_nodes: avoid,
+                max_paths,
+            };
+            if ulw_ldk::lnurl::is_lnurl(&invoice) {
+                commands::pay_lnurl(&config, invoice, amount, comment, constraints).await?;
+            } else {
+                commands::pay_invoice(&config, invoice, amount, constraints).await?;
+            }
+        }
+        Commands::Keysend {
+            node_id,
+            amount,
+            tlvs,
+        } => {
+            let config = load_config()?;
+            commands::keysend(&config, node_id, amount, tlvs).await?;
+        }
+        Commands::Decode { input } => {
+            let config = load_config()?;
+            commands::decode(&config, input).await?;
+        }
+        Commands::LnurlAuth { lnurl } => {
+            let config = load_config()?;
+            match lnurl {
+                Some(lnurl) => commands::lnurl_auth(&config, lnurl).await?,
+                None => commands::list_lnurl_auths(&config).await?,
+            }
+        }
+        Commands::Offer { action } => {
+            let config = load_config()?;
+            match action {
+                OfferCommands::Create {
+                    amount,
+                    description,
+                    quantity_max,
+                    peer,
+                } => {
+                    commands::create_offer(&config, amount, description, quantity_max, peer).await?
+                }
+                OfferCommands::Pay {
+                    offer,
+                    amount,
+                    quantity,
+                    note,
+                    peer,
+                } => commands::pay_offer(&config, offer, amount, quantity, note, peer).await?,
+                OfferCommands::List => commands::list_offers(&config).await?,
+            }
+        }
+        Commands::Refund { action } => {
+            let config = load_config()?;
+            match action {
+                RefundCommands::Create {
+                    amount,
+                    description,
+                    expiry,
+                    peer,
+                } => commands::create_refund(&config, amount, description, expiry, peer).await?,
+                RefundCommands::Request { refund, peer } => {
+                    commands::request_refund(&config, refund, peer).await?
+                }
+            }
+        }
+        Commands::Graph { action } => {
+            let config = load_config()?;
+            match action {
+                GraphCommands::Stats => commands::show_graph_stats(&config).await?,
+                GraphCommands::Sync { source } => commands::sync_graph(&config, source).await?,
+            }
         }
     }
 
     Ok(())
 }
 
+/// Parse a `<type>=<hex value>` TLV record
+fn parse_tlv(s: &str) -> std::result::Result<(u64, Vec<u8>), String> {
+    let (tlv_type, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected <type>=<hex value>, got {}", s))?;
+    let tlv_type = tlv_type
+        .parse()
+        .map_err(|e| format!("invalid TLV type {}: {}", tlv_type, e))?;
+    let value = hex::decode(value).map_err(|e| format!("invalid TLV value {}: {}", value, e))?;
+    Ok((tlv_type, value))
+}
+
 fn load_config() -> Result<WalletConfig> {
     let config = WalletConfig::default();
     let config_path = config.config_path();
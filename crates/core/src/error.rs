@@ -27,6 +27,9 @@ pub enum Error {
     #[error("Network error: {0}")]
     Network(String),
 
+    #[error("No route within the payment constraints: {0}")]
+    RouteConstraints(String),
+
     #[error("Invalid invoice: {0}")]
     InvalidInvoice(String),
 
@@ -42,11 +42,22 @@ pub trait LightningNode: Send + Sync {
     /// Connect to a peer
     async fn connect_peer(&self, node_id: String, addr: String) -> Result<()>;
 
-    /// Open a new channel
-    async fn open_channel(&self, node_id: String, amount_sats: u64) -> Result<String>;
-
-    /// Close a channel
-    async fn close_channel(&self, channel_id: String) -> Result<()>;
+    /// Open a new channel funded from the on-chain wallet
+    async fn open_channel(
+        &self,
+        node_id: String,
+        amount_sats: u64,
+        push_msat: u64,
+        announce: bool,
+    ) -> Result<String>;
+
+    /// Close a channel, cooperatively at `target_feerate_sat_per_vb` unless `force` is set
+    async fn close_channel(
+        &self,
+        channel_id: String,
+        force: bool,
+        target_feerate_sat_per_vb: Option<u32>,
+    ) -> Result<ChannelInfo>;
 
     /// List all channels
     async fn list_channels(&self) -> Result<Vec<ChannelInfo>>;
@@ -54,8 +65,12 @@ pub trait LightningNode: Send + Sync {
     /// Create an invoice
     async fn create_invoice(&self, amount_msat: u64, description: String) -> Result<String>;
 
-    /// Pay an invoice
-    async fn pay_invoice(&self, invoice: String) -> Result<Payment>;
+    /// Pay an invoice, routing within `constraints`
+    async fn pay_invoice(
+        &self,
+        invoice: String,
+        constraints: PaymentConstraints,
+    ) -> Result<Payment>;
 
     /// Get Lightning balance
     async fn get_balance(&self) -> Result<(bitcoin::Amount, bitcoin::Amount)>;
@@ -81,4 +96,31 @@ pub trait WalletStorage: Send + Sync {
 
     /// List all channels
     async fn list_channels(&self) -> Result<Vec<ChannelInfo>>;
+
+    /// Save broadcast transaction record
+    async fn save_broadcast(&self, record: &BroadcastRecord) -> Result<()>;
+
+    /// Get broadcast record by txid
+    async fn get_broadcast(&self, txid: &str) -> Result<Option<BroadcastRecord>>;
+
+    /// List broadcast transactions that have not confirmed yet
+    async fn list_unconfirmed_broadcasts(&self) -> Result<Vec<BroadcastRecord>>;
+
+    /// Save BOLT12 offer or refund record
+    async fn save_offer(&self, offer: &OfferRecord) -> Result<()>;
+
+    /// Get offer or refund record by ID
+    async fn get_offer(&self, id: &str) -> Result<Option<OfferRecord>>;
+
+    /// List all offer and refund records, newest first
+    async fn list_offers(&self) -> Result<Vec<OfferRecord>>;
+
+    /// Save LNURL-auth login record
+    async fn save_lnurl_auth(&self, record: &LnurlAuthRecord) -> Result<()>;
+
+    /// Get LNURL-auth login record by domain
+    async fn get_lnurl_auth(&self, domain: &str) -> Result<Option<LnurlAuthRecord>>;
+
+    /// List all domains logged in to with LNURL-auth, most recent login first
+    async fn list_lnurl_auths(&self) -> Result<Vec<LnurlAuthRecord>>;
 }
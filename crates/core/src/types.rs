@@ -1,5 +1,6 @@
 //! Core domain types
 
+use bitcoin::secp256k1::PublicKey;
 use bitcoin::{Amount, Network};
 use serde::{Deserialize, Serialize};
 
@@ -14,12 +15,14 @@ pub struct Balance {
     pub lightning_available: Amount,
     /// Total Lightning balance (including pending)
     pub lightning_total: Amount,
+    /// Funds from closed channels waiting to be swept into the on-chain wallet
+    pub pending_sweep: Amount,
 }
 
 impl Balance {
     /// Total balance across on-chain and Lightning
     pub fn total(&self) -> Amount {
-        self.confirmed + self.unconfirmed + self.lightning_total
+        self.confirmed + self.unconfirmed + self.lightning_total + self.pending_sweep
     }
 
     /// Spendable balance (confirmed on-chain + Lightning available)
@@ -35,6 +38,7 @@ impl Default for Balance {
             unconfirmed: Amount::ZERO,
             lightning_available: Amount::ZERO,
             lightning_total: Amount::ZERO,
+            pending_sweep: Amount::ZERO,
         }
     }
 }
@@ -50,6 +54,8 @@ pub enum PaymentDirection {
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum PaymentStatus {
     Pending,
+    /// HTLCs of a hold invoice arrived and await settling or cancelling
+    Held,
     Succeeded,
     Failed,
 }
@@ -64,6 +70,229 @@ pub struct Payment {
     pub invoice: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub settled_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Hex-encoded payment preimage, once known
+    pub preimage: Option<String>,
+    /// Routing fees paid for an outbound payment, in millisatoshis
+    pub fee_paid_msat: Option<u64>,
+    /// Custom TLV records sent or received with a keysend payment, as `(type, value)`
+    #[serde(default)]
+    pub custom_tlvs: Vec<(u64, Vec<u8>)>,
+    /// Number of HTLCs the payment was split into, once known
+    #[serde(default)]
+    pub parts: Option<u32>,
+    /// Routing fee of each successful path of an outbound payment, in millisatoshis
+    #[serde(default)]
+    pub path_fees_msat: Vec<u64>,
+    /// Hex-encoded hash of each successful path, so a replayed path event is not counted twice
+    #[serde(default)]
+    pub path_ids: Vec<String>,
+    /// ID of the [`OfferRecord`] the payment was made for
+    #[serde(default)]
+    pub offer_id: Option<String>,
+    /// Block height by which a held payment must be settled, after which LDK fails it back
+    #[serde(default)]
+    pub claim_deadline: Option<u32>,
+}
+
+/// Kind of BOLT12 object
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum OfferKind {
+    /// Reusable request to be paid
+    Offer,
+    /// Offer to pay back, answered by the recipient with an invoice
+    Refund,
+}
+
+/// A BOLT12 offer or refund the wallet created or paid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfferRecord {
+    /// Offer ID for offers we created, otherwise the ID of our outgoing payment
+    pub id: String,
+    pub kind: OfferKind,
+    /// Inbound for offers we are paid through, outbound for offers and refunds we pay
+    pub direction: PaymentDirection,
+    /// Bech32 encoding of the offer or refund
+    pub encoded: String,
+    pub amount_msat: Option<u64>,
+    pub description: Option<String>,
+    /// Most items that can be bought at once, if more than one
+    pub quantity_max: Option<u64>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A service the wallet has logged in to with LNURL-auth
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LnurlAuthRecord {
+    pub domain: String,
+    /// Hex-encoded linking key the service knows us by
+    pub linking_key: String,
+    pub first_login_at: chrono::DateTime<chrono::Utc>,
+    pub last_login_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Limits on how an outbound payment may be routed
+///
+/// Unset limits fall back to the node's defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PaymentConstraints {
+    /// Most routing fees to pay, in millisatoshis
+    pub max_fee_msat: Option<u64>,
+    /// Most routing fees to pay, as a percentage of the amount
+    pub max_fee_percent: Option<f64>,
+    /// Most blocks the payment's HTLCs may be locked up for
+    pub max_cltv_expiry_delta: Option<u32>,
+    /// Nodes not to route through
+    pub avoid_nodes: Vec<PublicKey>,
+    /// Most paths to split the payment over
+    pub max_paths: Option<u8>,
+}
+
+impl PaymentConstraints {
+    /// Whether no limit is set
+    pub fn is_unconstrained(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fee limit for paying `amount_msat`, the lower of the absolute and percentage limits
+    pub fn max_fee_msat(&self, amount_msat: u64) -> Option<u64> {
+        let percent_limit = self
+            .max_fee_percent
+            .map(|percent| (amount_msat as f64 * percent / 100.0) as u64);
+        match (self.max_fee_msat, percent_limit) {
+            (Some(absolute), Some(percent)) => Some(absolute.min(percent)),
+            (absolute, percent) => absolute.or(percent),
+        }
+    }
+}
+
+/// Rules for which peers may open channels to us
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct InboundChannelPolicy {
+    /// Smallest channel to accept, in satoshis
+    pub min_channel_size_sats: u64,
+    /// Largest channel to accept, in satoshis
+    pub max_channel_size_sats: Option<u64>,
+    /// Only these nodes may open channels, unless empty
+    pub allowlist: Vec<PublicKey>,
+    /// Nodes that may never open channels
+    pub denylist: Vec<PublicKey>,
+    /// Reject channels without anchor outputs
+    pub require_anchors: bool,
+    /// Accept zero-conf channels from nodes on the allowlist
+    ///
+    /// The funds are usable before the funding transaction confirms, so the peer must be
+    /// trusted not to double-spend it.
+    pub allow_zero_conf: bool,
+}
+
+impl Default for InboundChannelPolicy {
+    fn default() -> Self {
+        Self {
+            min_channel_size_sats: 20_000,
+            max_channel_size_sats: None,
+            allowlist: Vec::new(),
+            denylist: Vec::new(),
+            require_anchors: false,
+            allow_zero_conf: false,
+        }
+    }
+}
+
+/// Outcome of checking a channel open request against an [`InboundChannelPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InboundChannelDecision {
+    Accept,
+    /// Accept, treating the channel as usable before its funding confirms
+    AcceptZeroConf,
+    Reject(String),
+}
+
+impl InboundChannelPolicy {
+    /// Decide on a request from `node_id` to open a `funding_sats` channel
+    ///
+    /// `anchors` is whether the channel uses anchor outputs and `zero_conf` whether the opener
+    /// requires it to be usable before confirmation.
+    pub fn evaluate(
+        &self,
+        node_id: &PublicKey,
+        funding_sats: u64,
+        anchors: bool,
+        zero_conf: bool,
+    ) -> InboundChannelDecision {
+        let allowlisted = self.allowlist.contains(node_id);
+        let reason = if self.denylist.contains(node_id) {
+            Some("peer is on the denylist".to_string())
+        } else if !self.allowlist.is_empty() && !allowlisted {
+            Some("peer is not on the allowlist".to_string())
+        } else if funding_sats < self.min_channel_size_sats {
+            Some(format!(
+                "channel of {} sats is below the minimum of {} sats",
+                funding_sats, self.min_channel_size_sats
+            ))
+        } else if let Some(max) = self.max_channel_size_sats.filter(|max| funding_sats > *max) {
+            Some(format!(
+                "channel of {} sats is above the maximum of {} sats",
+                funding_sats, max
+            ))
+        } else if self.require_anchors && !anchors {
+            Some("anchor outputs are required".to_string())
+        } else if zero_conf && !(self.allow_zero_conf && allowlisted) {
+            Some("zero-conf channels are only accepted from trusted peers".to_string())
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => InboundChannelDecision::Reject(reason),
+            None if self.allow_zero_conf && allowlisted => InboundChannelDecision::AcceptZeroConf,
+            None => InboundChannelDecision::Accept,
+        }
+    }
+}
+
+/// A channel as recorded in a static channel backup
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BackupChannel {
+    pub channel_id: String,
+    pub peer_node_id: PublicKey,
+    /// Address to reach the peer at, as `<host>:<port>`, if one is known
+    pub peer_address: Option<String>,
+    pub funding_outpoint: bitcoin::OutPoint,
+    pub capacity_sats: u64,
+    /// Hex-encoded ID the channel's keys are derived from, needed to sweep our balance
+    ///
+    /// Missing from backups made before balances could be swept after recovery.
+    #[serde(default)]
+    pub channel_keys_id: Option<String>,
+    /// Hex-encoded LDK serialization of the channel's transaction parameters
+    #[serde(default)]
+    pub channel_parameters: Option<String>,
+}
+
+/// What is needed to have peers force close our channels after the channel state is lost
+///
+/// Unlike the channel state, this only changes when channels are opened or closed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChannelBackup {
+    /// Node the channels belong to
+    pub node_id: PublicKey,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub channels: Vec<BackupChannel>,
+}
+
+/// A transaction handed to the network, with the outcome of its broadcast attempts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastRecord {
+    pub txid: String,
+    /// Hex-encoded consensus serialization of the transaction
+    pub raw_tx: String,
+    pub attempts: u32,
+    /// Error from the most recent attempt, if it failed
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub confirmed: bool,
 }
 
 /// Channel state
@@ -84,6 +313,12 @@ pub struct ChannelInfo {
     pub local_balance_msat: u64,
     pub remote_balance_msat: u64,
     pub state: ChannelState,
+    /// Funding outpoint as `txid:vout`, once the funding transaction exists
+    pub funding_txo: Option<String>,
+    /// Txid of the transaction that closed the channel
+    pub closing_txid: Option<String>,
+    /// Block height at which our timelocked funds become spendable after a force close
+    pub claimable_at_height: Option<u32>,
 }
 
 /// Network configuration
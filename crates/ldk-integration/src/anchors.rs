@@ -0,0 +1,205 @@
+//! Fee bumping of anchor channels
+//!
+//! Anchor channels pre-sign their commitment and HTLC transactions at a low fee rate and rely on
+//! us to bump them with CPFP when they need to confirm during a force close. The fees come from
+//! confirmed on-chain wallet UTXOs, so a reserve is kept per anchor channel.
+
+use bitcoin::hashes::Hash;
+use bitcoin::{Psbt, ScriptBuf, Transaction, WPubkeyHash};
+use lightning::events::bump_transaction::{
+    BumpTransactionEventHandler, Utxo, Wallet as LdkWallet, WalletSource,
+};
+use lightning::sign::KeysManager;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ulw_bdk::BdkWallet;
+use ulw_core::Result;
+
+use crate::broadcast::Broadcaster;
+use crate::node::{ChannelManager, LdkNode, SimpleLogger};
+
+/// Confirmed on-chain balance kept aside for fee bumping, per anchor channel
+pub const ANCHOR_RESERVE_PER_CHANNEL_SATS: u64 = 25_000;
+
+/// How often the background processor checks the reserve
+pub(crate) const ANCHOR_RESERVE_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+pub(crate) type BumpHandler = BumpTransactionEventHandler<
+    Arc<Broadcaster>,
+    Arc<LdkWallet<Arc<WalletUtxoSource>, Arc<SimpleLogger>>>,
+    Arc<KeysManager>,
+    Arc<SimpleLogger>,
+>;
+
+/// Offers the on-chain wallet's confirmed UTXOs to LDK for fee bumping
+pub struct WalletUtxoSource {
+    wallet: Arc<BdkWallet>,
+}
+
+impl WalletUtxoSource {
+    pub fn new(wallet: Arc<BdkWallet>) -> Self {
+        Self { wallet }
+    }
+}
+
+impl WalletSource for WalletUtxoSource {
+    fn list_confirmed_utxos(&self) -> std::result::Result<Vec<Utxo>, ()> {
+        // LDK retries the bump on the next block if the wallet happens to be busy
+        let utxos = self
+            .wallet
+            .try_list_confirmed_utxos()
+            .map_err(|e| tracing::warn!("No UTXOs available for fee bumping: {}", e))?;
+
+        // The wallet only uses P2WPKH outputs, which is all LDK can weigh for us
+        Ok(utxos
+            .into_iter()
+            .filter(|(_, txout)| txout.script_pubkey.is_p2wpkh())
+            .filter_map(|(outpoint, txout)| {
+                let hash = WPubkeyHash::from_slice(&txout.script_pubkey.as_bytes()[2..]).ok()?;
+                Some(Utxo::new_v0_p2wpkh(outpoint, txout.value, &hash))
+            })
+            .collect())
+    }
+
+    fn get_change_script(&self) -> std::result::Result<ScriptBuf, ()> {
+        self.wallet
+            .try_get_change_script()
+            .map_err(|e| tracing::warn!("No change script for fee bumping: {}", e))
+    }
+
+    fn sign_psbt(&self, psbt: Psbt) -> std::result::Result<Transaction, ()> {
+        self.wallet
+            .try_sign_psbt(psbt)
+            .map_err(|e| tracing::warn!("Failed to sign fee bump: {}", e))
+    }
+}
+
+/// Build the handler for `Event::BumpTransaction`, spending from `wallet`
+pub(crate) fn bump_handler(
+    broadcaster: Arc<Broadcaster>,
+    wallet: Arc<BdkWallet>,
+    keys_manager: Arc<KeysManager>,
+    logger: Arc<SimpleLogger>,
+) -> BumpHandler {
+    let utxo_source = Arc::new(LdkWallet::new(
+        Arc::new(WalletUtxoSource::new(wallet)),
+        logger.clone(),
+    ));
+    BumpTransactionEventHandler::new(broadcaster, utxo_source, keys_manager, logger)
+}
+
+/// On-chain funds kept aside for fee bumping anchor channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnchorReserve {
+    /// Open channels with anchor outputs
+    pub anchor_channels: usize,
+    /// Confirmed balance needed to bump all of them, in satoshis
+    pub required_sats: u64,
+    /// Confirmed on-chain balance, in satoshis
+    pub available_sats: u64,
+}
+
+impl AnchorReserve {
+    /// Whether the confirmed balance covers the reserve
+    pub fn is_sufficient(&self) -> bool {
+        self.available_sats >= self.required_sats
+    }
+}
+
+/// Compare the confirmed on-chain balance against the reserve for our anchor channels
+pub(crate) async fn anchor_reserve(
+    channel_manager: &ChannelManager,
+    wallet: &BdkWallet,
+) -> Result<AnchorReserve> {
+    let anchor_channels = channel_manager
+        .list_channels()
+        .iter()
+        .filter(|c| {
+            c.channel_type
+                .as_ref()
+                .is_some_and(|t| t.supports_anchors_zero_fee_htlc_tx())
+        })
+        .count();
+    let (confirmed, _) = wallet.get_balance_details().await?;
+    Ok(AnchorReserve {
+        anchor_channels,
+        required_sats: anchor_channels as u64 * ANCHOR_RESERVE_PER_CHANNEL_SATS,
+        available_sats: confirmed.to_sat(),
+    })
+}
+
+/// Warn if the confirmed balance no longer covers the reserve
+pub(crate) async fn check_anchor_reserve(
+    channel_manager: &ChannelManager,
+    wallet: &BdkWallet,
+) -> Result<()> {
+    let reserve = anchor_reserve(channel_manager, wallet).await?;
+    if !reserve.is_sufficient() {
+        tracing::warn!(
+            "On-chain reserve too low to fee bump {} anchor channels: {} of {} sats confirmed",
+            reserve.anchor_channels,
+            reserve.available_sats,
+            reserve.required_sats
+        );
+    }
+    Ok(())
+}
+
+impl LdkNode {
+    /// On-chain reserve for fee bumping anchor channels, and whether it is covered
+    pub async fn anchor_reserve(&self) -> Result<AnchorReserve> {
+        anchor_reserve(&self.channel_manager, &self.wallet).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::default_user_config;
+    use crate::node::tests::test_node;
+    use crate::offers::tests::connect;
+    use ulw_core::Error;
+
+    #[tokio::test]
+    async fn test_anchor_reserve_without_channels() {
+        let (node, _temp) = test_node([120u8; 32]).await;
+
+        assert!(
+            default_user_config()
+                .channel_handshake_config
+                .negotiate_anchors_zero_fee_htlc_tx
+        );
+        let reserve = node.anchor_reserve().await.unwrap();
+        assert_eq!(
+            reserve,
+            AnchorReserve {
+                anchor_channels: 0,
+                required_sats: 0,
+                available_sats: 0,
+            }
+        );
+        assert!(reserve.is_sufficient());
+
+        let source = WalletUtxoSource::new(node.wallet.clone());
+        assert!(source.list_confirmed_utxos().unwrap().is_empty());
+        assert!(source.get_change_script().unwrap().is_p2wpkh());
+    }
+
+    #[tokio::test]
+    async fn test_open_channel_keeps_anchor_reserve() {
+        let (node, _temp) = test_node([121u8; 32]).await;
+        let (peer, _peer_temp) = test_node([122u8; 32]).await;
+        connect(&node, &peer).await;
+
+        let result = node
+            .open_channel(peer.get_node_id(), 100_000, 0, false)
+            .await;
+        match result {
+            Err(Error::InsufficientFunds { required, .. }) => {
+                assert_eq!(required, 100_000 + ANCHOR_RESERVE_PER_CHANNEL_SATS)
+            }
+            other => panic!("Expected insufficient funds, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! LDK background processing
+//!
+//! Runs LDK's background processor, which handles events, timer ticks, channel manager,
+//! network graph and scorer persistence, and network graph pruning, alongside periodic chain
+//! sync, fee estimate refreshes, rebroadcasting of unconfirmed transactions, cancelling of
+//! held payments near their claim deadline, checks of the anchor channel fee bump reserve and
+//! sending of justice transactions to the watchtower.
+
+use lightning::events::Event;
+use lightning_background_processor::{process_events_async, GossipSync};
+use lightning_rapid_gossip_sync::RapidGossipSync;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use ulw_core::{Error, Result};
+
+use crate::anchors::{self, ANCHOR_RESERVE_CHECK_INTERVAL};
+use crate::broadcast::REBROADCAST_INTERVAL;
+use crate::chain::{self, CHAIN_SYNC_INTERVAL};
+use crate::fees::FEE_REFRESH_INTERVAL;
+use crate::hold::{self, HOLD_EXPIRY_CHECK_INTERVAL};
+use crate::node::{LdkNode, NetworkGraph, SimpleLogger};
+use crate::scoring;
+use crate::watchtower::TOWER_FLUSH_INTERVAL;
+
+type RapidSync = RapidGossipSync<Arc<NetworkGraph>, Arc<SimpleLogger>>;
+
+/// Handle to a running background processor
+pub(crate) struct BackgroundTask {
+    stop: watch::Sender<()>,
+    handle: JoinHandle<std::result::Result<(), lightning::io::Error>>,
+    periodic: Vec<JoinHandle<()>>,
+}
+
+/// Run `task` every `period` until `stop` fires
+///
+/// A failed run is logged and retried on the next tick.
+fn spawn_periodic<F, Fut>(stop: &watch::Sender<()>, period: Duration, mut task: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send,
+{
+    let mut stop_rx = stop.subscribe();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = stop_rx.changed() => break,
+                _ = interval.tick() => {}
+            }
+            if let Err(e) = task().await {
+                tracing::warn!("{}", e);
+            }
+        }
+    })
+}
+
+impl LdkNode {
+    /// Start the background processor
+    ///
+    /// Must be called from within a tokio runtime. Events are handled by the background task
+    /// from now on until `stop` is called.
+    pub fn start(&self) -> Result<()> {
+        let mut background = self.background.lock().unwrap();
+        if background.is_some() {
+            return Err(Error::Internal("Node is already running".to_string()));
+        }
+
+        let (stop, stop_rx) = watch::channel(());
+        let event_handler = self.event_handler.clone();
+        let sleeper = move |duration: Duration| {
+            let mut stop_rx = stop_rx.clone();
+            Box::pin(async move {
+                tokio::select! {
+                    _ = stop_rx.changed() => true,
+                    _ = tokio::time::sleep(duration) => false,
+                }
+            })
+        };
+
+        let handle = tokio::spawn(process_events_async(
+            self.persister.clone(),
+            move |event: Event| {
+                let event_handler = event_handler.clone();
+                async move { event_handler.handle_event(event).await }
+            },
+            self.chain_monitor.clone(),
+            self.channel_manager.clone(),
+            Some(self.onion_messenger.clone()),
+            GossipSync::<_, Arc<RapidSync>, _, _, _>::P2P(self.gossip_sync.clone()),
+            self.peer_manager.clone(),
+            self.logger.clone(),
+            Some(self.scorer.clone()),
+            sleeper,
+            false,
+            || SystemTime::now().duration_since(UNIX_EPOCH).ok(),
+        ));
+
+        let chain_source = self.chain_source.clone();
+        let confirmables = self.confirmables();
+        let chain_sync = spawn_periodic(&stop, CHAIN_SYNC_INTERVAL, move || {
+            chain::sync_confirmables(chain_source.clone(), confirmables.clone())
+        });
+
+        let fee_estimates = self.fee_estimator.estimates();
+        let fee_refresh = spawn_periodic(&stop, FEE_REFRESH_INTERVAL, move || {
+            let fee_estimates = fee_estimates.clone();
+            async move { fee_estimates.refresh().await }
+        });
+
+        let broadcaster = self.broadcaster.clone();
+        let rebroadcast = spawn_periodic(&stop, REBROADCAST_INTERVAL, move || {
+            let broadcaster = broadcaster.clone();
+            async move { broadcaster.rebroadcast_unconfirmed().await }
+        });
+
+        let channel_manager = self.channel_manager.clone();
+        let storage = self.storage.clone();
+        let hold_expiry = spawn_periodic(&stop, HOLD_EXPIRY_CHECK_INTERVAL, move || {
+            let channel_manager = channel_manager.clone();
+            let storage = storage.clone();
+            async move {
+                hold::cancel_expiring_holds(&channel_manager, &*storage)
+                    .await
+                    .map(|_| ())
+            }
+        });
+
+        let channel_manager = self.channel_manager.clone();
+        let wallet = self.wallet.clone();
+        let reserve_check = spawn_periodic(&stop, ANCHOR_RESERVE_CHECK_INTERVAL, move || {
+            let channel_manager = channel_manager.clone();
+            let wallet = wallet.clone();
+            async move { anchors::check_anchor_reserve(&channel_manager, &wallet).await }
+        });
+
+        let watchtower = self.watchtower.clone();
+        let tower_flush = spawn_periodic(&stop, TOWER_FLUSH_INTERVAL, move || {
+            let watchtower = watchtower.clone();
+            async move { watchtower.flush().await.map(|_| ()) }
+        });
+
+        let mut periodic = vec![
+            chain_sync,
+            fee_refresh,
+            rebroadcast,
+            hold_expiry,
+            reserve_check,
+            tower_flush,
+        ];
+        if let Some(config) = self.prober.lock().unwrap().clone() {
+            let channel_manager = self.channel_manager.clone();
+            let storage = self.storage.clone();
+            periodic.push(spawn_periodic(&stop, config.interval, move || {
+                let channel_manager = channel_manager.clone();
+                let storage = storage.clone();
+                let config = config.clone();
+                async move {
+                    scoring::probe_frequent_destinations(&channel_manager, &*storage, &config)
+                        .await
+                        .map(|_| ())
+                }
+            }));
+        }
+
+        *background = Some(BackgroundTask {
+            stop,
+            handle,
+            periodic,
+        });
+        tracing::info!("Started Lightning background processor");
+        Ok(())
+    }
+
+    /// Stop the background processor, waiting for it to persist the channel manager
+    ///
+    /// Does nothing if the node is not running.
+    pub async fn stop(&self) -> Result<()> {
+        let task = self.background.lock().unwrap().take();
+        let Some(task) = task else {
+            return Ok(());
+        };
+
+        let _ = task.stop.send(());
+        // In-flight periodic work finishes before its loop sees the stop signal
+        for periodic in task.periodic {
+            let _ = periodic.await;
+        }
+        task.handle
+            .await
+            .map_err(|e| Error::Internal(format!("Background processor panicked: {}", e)))?
+            .map_err(|e| Error::Storage(format!("Background processor failed: {}", e)))?;
+
+        // Give peers a clean disconnect rather than dropping the sockets
+        self.peer_manager.disconnect_all_peers();
+        tracing::info!("Stopped Lightning background processor");
+        Ok(())
+    }
+
+    /// Whether the background processor is running
+    pub fn is_running(&self) -> bool {
+        self.background.lock().unwrap().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::node::tests::test_node;
+    use lightning::util::persist::{
+        KVStore, CHANNEL_MANAGER_PERSISTENCE_KEY, CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+        CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+    };
+
+    #[tokio::test]
+    async fn test_start_and_stop() {
+        let (node, _temp) = test_node([40u8; 32]).await;
+
+        node.start().unwrap();
+        assert!(node.is_running());
+        assert!(node.start().is_err());
+
+        node.stop().await.unwrap();
+        assert!(!node.is_running());
+
+        // The channel manager is persisted on shutdown
+        assert!(node
+            .persister
+            .read(
+                CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+                CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+                CHANNEL_MANAGER_PERSISTENCE_KEY,
+            )
+            .is_ok());
+
+        // Stopping twice is harmless, and the node can be restarted
+        node.stop().await.unwrap();
+        node.start().unwrap();
+        node.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,830 @@
+//! Static channel backups
+//!
+//! The channel state under the node's storage directory changes with every payment, so an old
+//! copy of it is unsafe to restore. A static channel backup instead records just enough to find
+//! our channels again: the peer, where to reach it and the funding outpoint. It only changes
+//! when channels open or close, and is encrypted with a key derived from the node secret.
+//!
+//! To recover, the node reconnects to each peer. LDK answers the peer's `channel_reestablish`
+//! for a channel it does not know with a stale one, which makes the peer force close the
+//! channel. Our balance is then an output of the peer's commitment transaction, locked to keys
+//! derived from the channel's keys ID. The chain monitor no longer knows the channel, so the
+//! node watches the funding output itself and hands that output to the sweeper once the
+//! commitment confirms. Backups made before the keys ID was recorded only get the peer to close.
+
+use bitcoin::absolute::LockTime;
+use bitcoin::block::Header;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, BlockHash, Transaction, TxOut, Txid};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use lightning::chain::transaction::{OutPoint, TransactionData};
+use lightning::chain::{Confirm, WatchedOutput};
+use lightning::io;
+use lightning::ln::chan_utils::{
+    get_counterparty_payment_script, make_funding_redeemscript, ChannelTransactionParameters,
+};
+use lightning::ln::channel_state::ChannelDetails;
+use lightning::routing::gossip::NodeId;
+use lightning::sign::{
+    ChannelSigner, KeysManager, SignerProvider, SpendableOutputDescriptor,
+    StaticPaymentOutputDescriptor,
+};
+use lightning::util::persist::KVStore;
+use lightning::util::ser::{Readable, Writeable};
+use lightning_persister::fs_store::FilesystemStore;
+use std::collections::HashSet;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ulw_core::types::{BackupChannel, ChannelBackup};
+use ulw_core::{Error, Result};
+
+use crate::chain::ChainSource;
+use crate::node::{ChainMonitor, ChannelManager, LdkNode, NetworkGraph, PeerManager};
+use crate::sweep::Sweeper;
+
+/// Marks a file as a static channel backup
+const BACKUP_MAGIC: &[u8; 6] = b"ULWSCB";
+
+/// Version of the backup file format
+const BACKUP_VERSION: u8 = 1;
+
+const NONCE_LEN: usize = 12;
+
+/// How long to stay connected for peers to reestablish their channels and force close
+const RECOVERY_WAIT: Duration = Duration::from_secs(5);
+
+/// Channels recovered from a backup whose funding output is still being watched, by outpoint
+const RECOVERY_PRIMARY_NAMESPACE: &str = "recovery";
+
+/// Outcome of asking a peer to close a channel from a backup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryStatus {
+    /// Connected to the peer, which should now force close the channel
+    CloseRequested,
+    /// The node still has the channel's state, so the channel was left open
+    StillOpen,
+    /// No address is known for the peer
+    NoAddress,
+    /// The peer could not be reached
+    Unreachable(String),
+}
+
+/// Writes the static channel backup whenever the channel set changes
+pub(crate) struct ChannelBackups {
+    key: [u8; 32],
+    node_id: PublicKey,
+    path: Mutex<Option<PathBuf>>,
+    keys_manager: Arc<KeysManager>,
+    channel_manager: Arc<ChannelManager>,
+    chain_monitor: Arc<ChainMonitor>,
+    peer_manager: Arc<PeerManager>,
+    network_graph: Arc<NetworkGraph>,
+}
+
+impl ChannelBackups {
+    pub(crate) fn new(
+        keys_manager: Arc<KeysManager>,
+        channel_manager: Arc<ChannelManager>,
+        chain_monitor: Arc<ChainMonitor>,
+        peer_manager: Arc<PeerManager>,
+        network_graph: Arc<NetworkGraph>,
+    ) -> Self {
+        let secret = keys_manager.get_node_secret_key();
+        let mut engine = sha256::Hash::engine();
+        engine.input(b"ulw static channel backup");
+        engine.input(&secret.secret_bytes());
+
+        Self {
+            key: sha256::Hash::from_engine(engine).to_byte_array(),
+            node_id: PublicKey::from_secret_key(&Secp256k1::new(), &secret),
+            path: Mutex::new(None),
+            keys_manager,
+            channel_manager,
+            chain_monitor,
+            peer_manager,
+            network_graph,
+        }
+    }
+
+    /// Rewrite the backup from the current channel set, if a backup path is set
+    ///
+    /// Channels in the previous backup that the node does not know are kept, so a node that
+    /// lost its channel state does not overwrite the backup needed to recover them. Only
+    /// `closed`, the funding outpoint of a channel that just closed, is dropped.
+    pub(crate) fn write(&self, closed: Option<bitcoin::OutPoint>) -> Result<()> {
+        let Some(path) = self.path.lock().unwrap().clone() else {
+            return Ok(());
+        };
+        let previous = match fs::read(&path) {
+            Ok(bytes) => Some(self.decrypt(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(Error::Storage(format!("Failed to read backup: {}", e))),
+        };
+
+        let mut backup = self.snapshot(previous.as_ref());
+        if let Some(previous) = previous {
+            let live: HashSet<_> = backup.channels.iter().map(|c| c.funding_outpoint).collect();
+            backup
+                .channels
+                .extend(previous.channels.into_iter().filter(|c| {
+                    !live.contains(&c.funding_outpoint) && Some(c.funding_outpoint) != closed
+                }));
+        }
+
+        // Replace the old backup in one step, so a crash never leaves a partial file
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.encrypt(&backup)?)
+            .and_then(|()| fs::rename(&tmp, &path))
+            .map_err(|e| Error::Storage(format!("Failed to write backup: {}", e)))
+    }
+
+    /// Backup of the channels the node currently knows
+    fn snapshot(&self, previous: Option<&ChannelBackup>) -> ChannelBackup {
+        let peers = self.peer_manager.list_peers();
+        let graph = self.network_graph.read_only();
+
+        // Prefer the address we connected to, then the one we knew, then the announced one
+        let peer_address = |node_id: &PublicKey| {
+            let connected = peers
+                .iter()
+                .find(|p| p.counterparty_node_id == *node_id && !p.is_inbound_connection)
+                .and_then(|p| p.socket_address.as_ref())
+                .map(|addr| addr.to_string());
+            let known = previous
+                .into_iter()
+                .flat_map(|backup| &backup.channels)
+                .find(|c| c.peer_node_id == *node_id && c.peer_address.is_some())
+                .and_then(|c| c.peer_address.clone());
+            let announced = graph
+                .node(&NodeId::from_pubkey(node_id))
+                .and_then(|node| node.announcement_info.as_ref())
+                .and_then(|info| info.addresses().first())
+                .map(|addr| addr.to_string());
+            connected.or(known).or(announced)
+        };
+
+        let channels = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .filter_map(|details| {
+                let funding_txo = details.funding_txo?;
+                let (channel_keys_id, channel_parameters) = self.channel_keys(&details).unzip();
+                Some(BackupChannel {
+                    channel_id: details.channel_id.to_string(),
+                    peer_address: peer_address(&details.counterparty.node_id),
+                    peer_node_id: details.counterparty.node_id,
+                    funding_outpoint: funding_txo.into_bitcoin_outpoint(),
+                    capacity_sats: details.channel_value_satoshis,
+                    channel_keys_id,
+                    channel_parameters,
+                })
+            })
+            .collect();
+
+        ChannelBackup {
+            node_id: self.node_id,
+            created_at: chrono::Utc::now(),
+            channels,
+        }
+    }
+
+    /// Hex-encoded keys ID and transaction parameters of a channel
+    fn channel_keys(&self, details: &ChannelDetails) -> Option<(String, String)> {
+        let monitor = self.chain_monitor.get_monitor(details.funding_txo?).ok()?;
+
+        // The monitor only hands these out in descriptors of outputs paying to us, which it
+        // gives for any transaction claimed to confirm at height 0. Have it describe an output
+        // paying to the wallet, which gives the keys ID...
+        let describe = |script_pubkey| {
+            let tx = Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: Vec::new(),
+                output: vec![TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey,
+                }],
+            };
+            monitor.get_spendable_outputs(&tx, 0)
+        };
+        let destination = self.keys_manager.get_destination_script([0; 32]).ok()?;
+        let keys_id =
+            describe(destination)
+                .into_iter()
+                .find_map(|descriptor| match descriptor {
+                    SpendableOutputDescriptor::StaticOutput {
+                        channel_keys_id, ..
+                    } => channel_keys_id,
+                    _ => None,
+                })?;
+
+        // ...then one paying to our side of the peer's commitment, which gives the parameters
+        let payment_point = self
+            .keys_manager
+            .derive_channel_keys(details.channel_value_satoshis, &keys_id)
+            .pubkeys()
+            .payment_point;
+        let script =
+            get_counterparty_payment_script(details.channel_type.as_ref()?, &payment_point);
+        describe(script)
+            .into_iter()
+            .find_map(|descriptor| match descriptor {
+                SpendableOutputDescriptor::StaticPaymentOutput(d) => {
+                    d.channel_transaction_parameters
+                }
+                _ => None,
+            })
+            .map(|parameters| (hex::encode(keys_id), hex::encode(parameters.encode())))
+    }
+
+    fn encrypt(&self, backup: &ChannelBackup) -> Result<Vec<u8>> {
+        let plaintext = serde_json::to_vec(backup).map_err(|e| Error::Internal(e.to_string()))?;
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|e| Error::Internal(format!("Failed to encrypt backup: {}", e)))?;
+
+        let mut bytes = BACKUP_MAGIC.to_vec();
+        bytes.push(BACKUP_VERSION);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(bytes)
+    }
+
+    fn decrypt(&self, bytes: &[u8]) -> Result<ChannelBackup> {
+        let header_len = BACKUP_MAGIC.len() + 1;
+        if bytes.len() < header_len + NONCE_LEN || !bytes.starts_with(BACKUP_MAGIC) {
+            return Err(Error::InvalidConfig(
+                "Not a static channel backup".to_string(),
+            ));
+        }
+        if bytes[BACKUP_MAGIC.len()] != BACKUP_VERSION {
+            return Err(Error::InvalidConfig(format!(
+                "Unsupported backup version {}",
+                bytes[BACKUP_MAGIC.len()]
+            )));
+        }
+
+        let (nonce, ciphertext) = bytes[header_len..].split_at(NONCE_LEN);
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                Error::InvalidConfig(
+                    "Backup could not be decrypted; it was made by another wallet".to_string(),
+                )
+            })?;
+        serde_json::from_slice(&plaintext)
+            .map_err(|e| Error::InvalidConfig(format!("Invalid backup: {}", e)))
+    }
+}
+
+/// A channel from a backup whose closing transaction has not been seen yet
+struct RecoveringChannel {
+    funding_outpoint: bitcoin::OutPoint,
+    channel_keys_id: [u8; 32],
+    capacity_sats: u64,
+    parameters: ChannelTransactionParameters,
+}
+
+impl RecoveringChannel {
+    fn from_backup(channel: &BackupChannel) -> Option<Self> {
+        let channel_keys_id = hex::decode(channel.channel_keys_id.as_ref()?)
+            .ok()?
+            .try_into()
+            .ok()?;
+        let parameters = hex::decode(channel.channel_parameters.as_ref()?).ok()?;
+        let parameters =
+            ChannelTransactionParameters::read(&mut io::Cursor::new(parameters)).ok()?;
+        // Only parameters of a funded channel describe both sides
+        parameters.counterparty_parameters.as_ref()?;
+        Some(Self {
+            funding_outpoint: channel.funding_outpoint,
+            channel_keys_id,
+            capacity_sats: channel.capacity_sats,
+            parameters,
+        })
+    }
+
+    fn funding_output(&self) -> WatchedOutput {
+        let counterparty = self
+            .parameters
+            .counterparty_parameters
+            .as_ref()
+            .expect("checked when read");
+        let script = make_funding_redeemscript(
+            &self.parameters.holder_pubkeys.funding_pubkey,
+            &counterparty.pubkeys.funding_pubkey,
+        );
+        WatchedOutput {
+            block_hash: None,
+            outpoint: OutPoint {
+                txid: self.funding_outpoint.txid,
+                index: self.funding_outpoint.vout as u16,
+            },
+            script_pubkey: script.to_p2wsh(),
+        }
+    }
+
+    /// Our output of the peer's commitment transaction `tx`, if it has one
+    fn our_output(&self, tx: &Transaction) -> Option<SpendableOutputDescriptor> {
+        let script = get_counterparty_payment_script(
+            &self.parameters.channel_type_features,
+            &self.parameters.holder_pubkeys.payment_point,
+        );
+        let (index, output) = tx
+            .output
+            .iter()
+            .enumerate()
+            .find(|(_, output)| output.script_pubkey == script)?;
+        Some(SpendableOutputDescriptor::StaticPaymentOutput(
+            StaticPaymentOutputDescriptor {
+                outpoint: OutPoint {
+                    txid: tx.compute_txid(),
+                    index: index as u16,
+                },
+                output: output.clone(),
+                channel_keys_id: self.channel_keys_id,
+                channel_value_satoshis: self.capacity_sats,
+                channel_transaction_parameters: Some(self.parameters.clone()),
+            },
+        ))
+    }
+}
+
+/// Sweeps our balance of channels recovered from a backup once the peer force closes them
+///
+/// The channels are kept in the node's storage until their funding output is spent, so the
+/// sweep still happens if the peer's commitment confirms after a restart.
+pub(crate) struct ChannelRecovery {
+    persister: Arc<FilesystemStore>,
+    sweeper: Arc<Sweeper>,
+    chain_source: Arc<dyn ChainSource>,
+    channels: Mutex<Vec<RecoveringChannel>>,
+}
+
+impl ChannelRecovery {
+    /// Resume watching the channels recovered before the node last stopped
+    pub(crate) fn load(
+        persister: Arc<FilesystemStore>,
+        sweeper: Arc<Sweeper>,
+        chain_source: Arc<dyn ChainSource>,
+    ) -> Result<Self> {
+        let recovery = Self {
+            persister,
+            sweeper,
+            chain_source,
+            channels: Mutex::new(Vec::new()),
+        };
+
+        let keys = recovery
+            .persister
+            .list(RECOVERY_PRIMARY_NAMESPACE, "")
+            .map_err(|e| Error::Storage(format!("Failed to list recovered channels: {}", e)))?;
+        for key in keys {
+            let bytes = recovery
+                .persister
+                .read(RECOVERY_PRIMARY_NAMESPACE, "", &key)
+                .map_err(|e| Error::Storage(format!("Failed to read recovered channel: {}", e)))?;
+            let channel: BackupChannel = serde_json::from_slice(&bytes)
+                .map_err(|e| Error::Storage(format!("Invalid recovered channel {}: {}", key, e)))?;
+            recovery.start_watching(&channel);
+        }
+        Ok(recovery)
+    }
+
+    /// Sweep our balance of `channel` once its funding output is spent
+    ///
+    /// Returns false if the backup lacks the channel's keys, so the balance cannot be swept.
+    fn watch(&self, channel: &BackupChannel) -> Result<bool> {
+        if !self.start_watching(channel) {
+            return Ok(false);
+        }
+        let bytes = serde_json::to_vec(channel).map_err(|e| Error::Internal(e.to_string()))?;
+        self.persister
+            .write(
+                RECOVERY_PRIMARY_NAMESPACE,
+                "",
+                &outpoint_key(&channel.funding_outpoint),
+                &bytes,
+            )
+            .map_err(|e| Error::Storage(format!("Failed to persist recovered channel: {}", e)))?;
+        Ok(true)
+    }
+
+    fn start_watching(&self, channel: &BackupChannel) -> bool {
+        let mut channels = self.channels.lock().unwrap();
+        if channels
+            .iter()
+            .any(|c| c.funding_outpoint == channel.funding_outpoint)
+        {
+            return true;
+        }
+        let Some(recovering) = RecoveringChannel::from_backup(channel) else {
+            return false;
+        };
+        self.chain_source
+            .register_output(recovering.funding_output());
+        channels.push(recovering);
+        true
+    }
+
+    /// Hand our output of `tx` to the sweeper if it closes a recovered channel
+    fn sweep_closed(&self, channel: &RecoveringChannel, tx: &Transaction) {
+        match channel.our_output(tx) {
+            Some(descriptor) => {
+                tracing::info!(
+                    "Sweeping our balance of recovered channel {} from {}",
+                    channel.funding_outpoint,
+                    tx.compute_txid()
+                );
+                if self
+                    .sweeper
+                    .track_spendable_outputs(vec![descriptor], None, false, None)
+                    .is_err()
+                {
+                    tracing::error!("Failed to persist spendable outputs");
+                    return;
+                }
+            }
+            None => tracing::info!(
+                "Recovered channel {} closed without a balance for us",
+                channel.funding_outpoint
+            ),
+        }
+
+        if let Err(e) = self.persister.remove(
+            RECOVERY_PRIMARY_NAMESPACE,
+            "",
+            &outpoint_key(&channel.funding_outpoint),
+            false,
+        ) {
+            tracing::warn!("Failed to remove recovered channel: {}", e);
+        }
+    }
+}
+
+fn outpoint_key(outpoint: &bitcoin::OutPoint) -> String {
+    format!("{}_{}", outpoint.txid, outpoint.vout)
+}
+
+impl Confirm for ChannelRecovery {
+    fn transactions_confirmed(&self, _header: &Header, txdata: &TransactionData, _height: u32) {
+        let mut channels = self.channels.lock().unwrap();
+        for (_, tx) in txdata {
+            channels.retain(|channel| {
+                let closes = tx
+                    .input
+                    .iter()
+                    .any(|input| input.previous_output == channel.funding_outpoint);
+                if closes {
+                    self.sweep_closed(channel, tx);
+                }
+                !closes
+            });
+        }
+    }
+
+    // The sweeper follows reorgs of the outputs it was handed, and nothing else is tracked
+    fn transaction_unconfirmed(&self, _txid: &Txid) {}
+
+    fn best_block_updated(&self, _header: &Header, _height: u32) {}
+
+    fn get_relevant_txids(&self) -> Vec<(Txid, u32, Option<BlockHash>)> {
+        Vec::new()
+    }
+}
+
+impl LdkNode {
+    /// Keep a static channel backup at `path`, rewritten whenever a channel opens or closes
+    ///
+    /// The backup should live outside the node's storage directory, ideally on another disk.
+    pub fn set_channel_backup_path(&self, path: PathBuf) -> Result<()> {
+        *self.channel_backups.path.lock().unwrap() = Some(path);
+        self.channel_backups.write(None)
+    }
+
+    /// Decrypt a static channel backup made by this wallet
+    pub fn read_channel_backup(&self, bytes: &[u8]) -> Result<ChannelBackup> {
+        let backup = self.channel_backups.decrypt(bytes)?;
+        if backup.node_id != self.get_node_id() {
+            return Err(Error::InvalidConfig(format!(
+                "Backup belongs to node {}",
+                backup.node_id
+            )));
+        }
+        Ok(backup)
+    }
+
+    /// Ask the peers of the channels in `backup` to force close them
+    ///
+    /// Channels the node still has state for are left open. Our balance of the other channels
+    /// is swept to the on-chain wallet once the peer's commitment transaction confirms and the
+    /// node syncs, unless the backup predates recording the channel's keys.
+    pub async fn recover_channels(
+        &self,
+        backup: &ChannelBackup,
+    ) -> Result<Vec<(BackupChannel, RecoveryStatus)>> {
+        self.recover_channels_waiting(backup, RECOVERY_WAIT).await
+    }
+
+    async fn recover_channels_waiting(
+        &self,
+        backup: &ChannelBackup,
+        wait: Duration,
+    ) -> Result<Vec<(BackupChannel, RecoveryStatus)>> {
+        let live: HashSet<_> = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .filter_map(|c| c.funding_txo)
+            .map(|txo| txo.into_bitcoin_outpoint())
+            .collect();
+
+        let mut results = Vec::new();
+        for channel in &backup.channels {
+            if live.contains(&channel.funding_outpoint) {
+                results.push((channel.clone(), RecoveryStatus::StillOpen));
+                continue;
+            }
+
+            // Watch even channels whose peer is unreachable, in case it closes them later
+            if !self.channel_recovery.watch(channel)? {
+                tracing::warn!(
+                    "Backup of channel {} predates sweeping; our balance will not be swept",
+                    channel.channel_id
+                );
+            }
+
+            let status = if let Some(address) = &channel.peer_address {
+                match self.reconnect(&channel.peer_node_id, address).await {
+                    Ok(()) => {
+                        tracing::info!(
+                            "Asked {} to force close channel {}",
+                            channel.peer_node_id,
+                            channel.channel_id
+                        );
+                        RecoveryStatus::CloseRequested
+                    }
+                    Err(e) => RecoveryStatus::Unreachable(e.to_string()),
+                }
+            } else {
+                RecoveryStatus::NoAddress
+            };
+            results.push((channel.clone(), status));
+        }
+
+        if results
+            .iter()
+            .any(|(_, status)| *status == RecoveryStatus::CloseRequested)
+        {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(results)
+    }
+
+    /// Connect to the peer of a channel being recovered
+    ///
+    /// A peer disconnects as soon as it force closes the channel, often before the connection
+    /// is seen to be up, so the peer closing the connection counts as success. The node ID comes
+    /// from an authenticated backup, so it is not a handshake failing on a wrong ID.
+    async fn reconnect(&self, node_id: &PublicKey, address: &str) -> Result<()> {
+        let addr = address
+            .to_socket_addrs()
+            .map_err(|e| Error::Network(format!("Invalid peer address {}: {}", address, e)))?
+            .next()
+            .ok_or_else(|| Error::Network(format!("Could not resolve {}", address)))?;
+        if self.peer_manager.peer_by_node_id(node_id).is_some() {
+            return Ok(());
+        }
+
+        let connection_closed =
+            lightning_net_tokio::connect_outbound(self.peer_manager.clone(), *node_id, addr)
+                .await
+                .ok_or_else(|| Error::Network(format!("Failed to connect to {}", addr)))?;
+        let mut connection_closed = Box::pin(connection_closed);
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        loop {
+            tokio::select! {
+                _ = &mut connection_closed => return Ok(()),
+                _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+            }
+            if self.peer_manager.peer_by_node_id(node_id).is_some() {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() > deadline {
+                return Err(Error::Network(format!(
+                    "Timed out connecting to {}@{}",
+                    node_id, addr
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::tests::MockChainSource;
+    use crate::node::tests::{open_zero_conf_channel, test_node, test_node_with_chain_source};
+    use lightning::events::bump_transaction::BumpTransactionEvent;
+    use lightning::events::Event;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+
+    /// Accept one connection to `peer`, returning the address to connect to
+    async fn listen(peer: &LdkNode) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer_manager = peer.peer_manager.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            lightning_net_tokio::setup_inbound(peer_manager, stream.into_std().unwrap()).await;
+        });
+        addr
+    }
+
+    fn backup_channel(node: &LdkNode, vout: u32, peer_address: Option<String>) -> BackupChannel {
+        BackupChannel {
+            channel_id: format!("{:064x}", vout),
+            peer_node_id: node.get_node_id(),
+            peer_address,
+            funding_outpoint: bitcoin::OutPoint::new(Txid::all_zeros(), vout),
+            capacity_sats: 100_000,
+            channel_keys_id: None,
+            channel_parameters: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backup_is_encrypted_for_the_node() {
+        let (node, _temp) = test_node([130u8; 32]).await;
+        let (other, _other_temp) = test_node([131u8; 32]).await;
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("channels.backup");
+
+        node.set_channel_backup_path(path.clone()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.starts_with(BACKUP_MAGIC));
+
+        let backup = node.read_channel_backup(&bytes).unwrap();
+        assert_eq!(backup.node_id, node.get_node_id());
+        assert!(backup.channels.is_empty());
+
+        assert!(matches!(
+            other.read_channel_backup(&bytes),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            node.read_channel_backup(b"not a backup"),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_backup_keeps_channels_until_closed() {
+        let (node, _temp) = test_node([132u8; 32]).await;
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("channels.backup");
+
+        // A backup from before the channel state was lost
+        let lost = ChannelBackup {
+            node_id: node.get_node_id(),
+            created_at: chrono::Utc::now(),
+            channels: vec![
+                backup_channel(&node, 0, Some("127.0.0.1:9735".to_string())),
+                backup_channel(&node, 1, None),
+            ],
+        };
+        fs::write(&path, node.channel_backups.encrypt(&lost).unwrap()).unwrap();
+
+        node.set_channel_backup_path(path.clone()).unwrap();
+        let backup = node.read_channel_backup(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(backup.channels, lost.channels);
+
+        node.channel_backups
+            .write(Some(lost.channels[0].funding_outpoint))
+            .unwrap();
+        let backup = node.read_channel_backup(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(backup.channels, lost.channels[1..]);
+    }
+
+    #[tokio::test]
+    async fn test_recover_channels_reconnects_to_peers() {
+        let (node, _temp) = test_node([133u8; 32]).await;
+        let (peer, _peer_temp) = test_node([134u8; 32]).await;
+
+        let addr = listen(&peer).await;
+
+        let backup = ChannelBackup {
+            node_id: node.get_node_id(),
+            created_at: chrono::Utc::now(),
+            channels: vec![
+                backup_channel(&peer, 0, Some(addr.to_string())),
+                backup_channel(&peer, 1, None),
+                backup_channel(&node, 2, Some("127.0.0.1:1".to_string())),
+            ],
+        };
+
+        let results = node
+            .recover_channels_waiting(&backup, Duration::ZERO)
+            .await
+            .unwrap();
+        let statuses: Vec<_> = results.into_iter().map(|(_, status)| status).collect();
+        assert_eq!(statuses[0], RecoveryStatus::CloseRequested);
+        assert_eq!(statuses[1], RecoveryStatus::NoAddress);
+        assert!(matches!(statuses[2], RecoveryStatus::Unreachable(_)));
+        assert!(node
+            .peer_manager
+            .peer_by_node_id(&peer.get_node_id())
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_recovered_channel_balance_is_swept() {
+        let chain_source = Arc::new(MockChainSource::new());
+        let (node, _temp) = test_node_with_chain_source([135u8; 32], chain_source.clone()).await;
+        let (peer, _peer_temp) =
+            test_node_with_chain_source([136u8; 32], chain_source.clone()).await;
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("channels.backup");
+        node.set_channel_backup_path(path.clone()).unwrap();
+
+        open_zero_conf_channel(&node, &peer, 40_000_000).await;
+        let mut backup = node.read_channel_backup(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(backup.channels.len(), 1);
+        assert!(backup.channels[0].channel_keys_id.is_some());
+
+        // The node loses its channel state and is restored from the same seed
+        node.peer_manager.disconnect_all_peers();
+        while !peer.peer_manager.list_peers().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let (restored, _restored_temp) =
+            test_node_with_chain_source([135u8; 32], chain_source.clone()).await;
+        backup.channels[0].peer_address = Some(listen(&peer).await.to_string());
+        let results = restored
+            .recover_channels_waiting(&backup, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(results[0].1, RecoveryStatus::CloseRequested);
+
+        // The peer force closes; it has no wallet funds to bump the commitment, so mine it as is
+        let commitment = Mutex::new(None);
+        for _ in 0..200 {
+            restored.peer_manager.process_events();
+            peer.peer_manager.process_events();
+            peer.chain_monitor
+                .process_pending_events_async(|event| async {
+                    if let Event::BumpTransaction(BumpTransactionEvent::ChannelClose {
+                        commitment_tx,
+                        ..
+                    }) = event
+                    {
+                        *commitment.lock().unwrap() = Some(commitment_tx);
+                    }
+                    Ok(())
+                })
+                .await;
+            if commitment.lock().unwrap().is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let commitment = commitment.into_inner().unwrap().unwrap();
+        chain_source.mine_txs(vec![commitment.clone()], 0);
+        restored.sync_chain().await.unwrap();
+
+        // Our balance of 60k sats less the commitment fee is more than the peer's 40k
+        let (vout, _) = commitment
+            .output
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, output)| output.value)
+            .unwrap();
+        let ours = bitcoin::OutPoint::new(commitment.compute_txid(), vout as u32);
+        assert!(restored.broadcaster.find_spending_tx(&ours).is_some());
+        assert_eq!(restored.sweeper.tracked_spendable_outputs().len(), 1);
+
+        // Watching stops once the channel closed, also across restarts
+        assert!(restored
+            .channel_recovery
+            .channels
+            .lock()
+            .unwrap()
+            .is_empty());
+        assert!(restored
+            .persister
+            .list(RECOVERY_PRIMARY_NAMESPACE, "")
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+//! Broadcasting of the Lightning node's transactions
+//!
+//! Funding, closing, justice and sweep transactions from LDK are sent through the chain source.
+//! Every attempt is recorded in wallet storage, failed broadcasts are retried with backoff, and
+//! transactions that have not confirmed yet are rebroadcast periodically.
+
+use bitcoin::consensus::encode;
+use bitcoin::{Transaction, Txid};
+use lightning::chain::chaininterface::BroadcasterInterface;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+use ulw_core::traits::WalletStorage;
+use ulw_core::types::BroadcastRecord;
+use ulw_core::{Error, Result};
+
+use crate::chain::{self, ChainSource};
+
+/// How many times a new transaction is tried before waiting for the next rebroadcast
+const BROADCAST_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for each further retry
+const BROADCAST_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How often unconfirmed transactions are rebroadcast while the node is running
+pub(crate) const REBROADCAST_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Transaction broadcaster backed by the chain source
+///
+/// Also keeps the transactions it was asked to broadcast so that closing transactions can be
+/// matched back to the channel they spend.
+pub struct Broadcaster {
+    chain_source: Arc<dyn ChainSource>,
+    storage: Arc<dyn WalletStorage>,
+    runtime: Handle,
+    broadcasted: Mutex<Vec<Transaction>>,
+}
+
+impl Broadcaster {
+    /// Create a broadcaster that sends on the current tokio runtime
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(chain_source: Arc<dyn ChainSource>, storage: Arc<dyn WalletStorage>) -> Self {
+        Self {
+            chain_source,
+            storage,
+            runtime: Handle::current(),
+            broadcasted: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Find a broadcast transaction spending the given outpoint
+    pub fn find_spending_tx(&self, outpoint: &bitcoin::OutPoint) -> Option<Txid> {
+        self.broadcasted
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|tx| tx.input.iter().any(|i| i.previous_output == *outpoint))
+            .map(|tx| tx.compute_txid())
+    }
+
+    /// Rebroadcast every recorded transaction that has not confirmed yet
+    ///
+    /// Transactions found to be confirmed are marked as such and no longer rebroadcast.
+    pub(crate) async fn rebroadcast_unconfirmed(&self) -> Result<()> {
+        for mut record in self.storage.list_unconfirmed_broadcasts().await? {
+            let tx: Transaction = encode::deserialize_hex(&record.raw_tx).map_err(|e| {
+                Error::Storage(format!("Invalid stored transaction {}: {}", record.txid, e))
+            })?;
+
+            let confirmed_tx = tx.clone();
+            let confirmed = chain::run_blocking(self.chain_source.clone(), move |chain_source| {
+                chain_source.is_confirmed(&confirmed_tx)
+            })
+            .await?;
+            if confirmed {
+                record.confirmed = true;
+                self.storage.save_broadcast(&record).await?;
+                continue;
+            }
+
+            if let Err(e) = attempt(&self.chain_source, &*self.storage, &mut record, &tx).await {
+                tracing::warn!("Rebroadcast failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BroadcasterInterface for Broadcaster {
+    fn broadcast_transactions(&self, txs: &[&Transaction]) {
+        let txs: Vec<Transaction> = txs.iter().map(|tx| (*tx).clone()).collect();
+        self.broadcasted.lock().unwrap().extend(txs.iter().cloned());
+
+        // LDK calls in from synchronous code, so broadcast in the background. Transactions are
+        // sent in order because a package may contain a parent and the child spending it.
+        let chain_source = self.chain_source.clone();
+        let storage = self.storage.clone();
+        self.runtime.spawn(async move {
+            for tx in txs {
+                let txid = tx.compute_txid();
+                if let Err(e) = broadcast_with_retries(&chain_source, &*storage, tx).await {
+                    tracing::error!(
+                        "Giving up on broadcasting {} until the next rebroadcast: {}",
+                        txid,
+                        e
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// Broadcast `tx`, retrying with exponential backoff
+pub(crate) async fn broadcast_with_retries(
+    chain_source: &Arc<dyn ChainSource>,
+    storage: &dyn WalletStorage,
+    tx: Transaction,
+) -> Result<()> {
+    let txid = tx.compute_txid().to_string();
+    tracing::info!("Broadcasting transaction: {}", txid);
+
+    let mut record = match storage.get_broadcast(&txid).await? {
+        Some(record) => record,
+        None => BroadcastRecord {
+            txid,
+            raw_tx: encode::serialize_hex(&tx),
+            attempts: 0,
+            last_error: None,
+            created_at: chrono::Utc::now(),
+            last_attempt_at: None,
+            confirmed: false,
+        },
+    };
+
+    let mut delay = BROADCAST_RETRY_DELAY;
+    let mut remaining = BROADCAST_ATTEMPTS;
+    loop {
+        remaining -= 1;
+        match attempt(chain_source, storage, &mut record, &tx).await {
+            Ok(()) => return Ok(()),
+            Err(e) if remaining == 0 => return Err(e),
+            Err(e) => {
+                tracing::warn!("{}; retrying in {:?}", e, delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+}
+
+/// Make one broadcast attempt and record its outcome
+async fn attempt(
+    chain_source: &Arc<dyn ChainSource>,
+    storage: &dyn WalletStorage,
+    record: &mut BroadcastRecord,
+    tx: &Transaction,
+) -> Result<()> {
+    let sent_tx = tx.clone();
+    let result = chain::run_blocking(chain_source.clone(), move |chain_source| {
+        chain_source.broadcast(&sent_tx)
+    })
+    .await;
+
+    record.attempts += 1;
+    record.last_attempt_at = Some(chrono::Utc::now());
+    record.last_error = result.as_ref().err().map(|e| e.to_string());
+    storage.save_broadcast(record).await?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::tests::MockChainSource;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute::LockTime, transaction::Version, Amount, OutPoint, TxIn, TxOut};
+    use tempfile::TempDir;
+    use ulw_storage::WalletDatabase;
+
+    fn test_tx(vout: u32) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), vout),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        }
+    }
+
+    fn setup() -> (Arc<MockChainSource>, Arc<WalletDatabase>, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Arc::new(WalletDatabase::new(temp_dir.path().join("wallet.db")).unwrap());
+        (Arc::new(MockChainSource::new()), storage, temp_dir)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_broadcast_retries_and_records_attempts() {
+        let (chain, storage, _temp) = setup();
+        let chain_source: Arc<dyn ChainSource> = chain.clone();
+        let tx = test_tx(0);
+
+        chain.fail_broadcasts(2);
+        broadcast_with_retries(&chain_source, &*storage, tx.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(*chain.broadcasts.lock().unwrap(), vec![tx.clone()]);
+        let record = storage
+            .get_broadcast(&tx.compute_txid().to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.attempts, 3);
+        assert!(record.last_error.is_none());
+        assert!(!record.confirmed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_broadcast_gives_up_after_max_attempts() {
+        let (chain, storage, _temp) = setup();
+        let chain_source: Arc<dyn ChainSource> = chain.clone();
+        let tx = test_tx(0);
+
+        chain.fail_broadcasts(usize::MAX);
+        let result = broadcast_with_retries(&chain_source, &*storage, tx.clone()).await;
+        assert!(matches!(result, Err(Error::Network(_))));
+
+        let record = storage
+            .get_broadcast(&tx.compute_txid().to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.attempts, BROADCAST_ATTEMPTS);
+        assert!(record.last_error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_skips_confirmed_transactions() {
+        let (chain, storage, _temp) = setup();
+        let chain_source: Arc<dyn ChainSource> = chain.clone();
+        let broadcaster = Broadcaster::new(chain_source.clone(), storage.clone());
+        let (confirmed_tx, pending_tx) = (test_tx(0), test_tx(1));
+
+        for tx in [&confirmed_tx, &pending_tx] {
+            broadcast_with_retries(&chain_source, &*storage, tx.clone())
+                .await
+                .unwrap();
+        }
+        chain.confirm(confirmed_tx.compute_txid());
+        chain.broadcasts.lock().unwrap().clear();
+
+        broadcaster.rebroadcast_unconfirmed().await.unwrap();
+
+        assert_eq!(*chain.broadcasts.lock().unwrap(), vec![pending_tx.clone()]);
+        let unconfirmed = storage.list_unconfirmed_broadcasts().await.unwrap();
+        assert_eq!(unconfirmed.len(), 1);
+        assert_eq!(unconfirmed[0].txid, pending_tx.compute_txid().to_string());
+        assert_eq!(unconfirmed[0].attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_broadcaster_finds_spending_tx() {
+        let (chain, storage, _temp) = setup();
+        let broadcaster = Broadcaster::new(chain, storage);
+        let closing_tx = test_tx(1);
+
+        broadcaster.broadcast_transactions(&[&closing_tx]);
+
+        assert_eq!(
+            broadcaster.find_spending_tx(&OutPoint::new(Txid::all_zeros(), 1)),
+            Some(closing_tx.compute_txid())
+        );
+        assert_eq!(
+            broadcaster.find_spending_tx(&OutPoint::new(Txid::all_zeros(), 0)),
+            None
+        );
+    }
+}
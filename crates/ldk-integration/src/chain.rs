@@ -0,0 +1,470 @@
+//! Chain synchronisation
+//!
+//! Keeps the channel manager, chain monitor and output sweeper in step with the chain, using
+//! the same Electrum server as the on-chain wallet. Reorgs are handled by the sync: transactions
+//! that drop out of the best chain are reported as unconfirmed before the new tip is applied.
+//! The same server is used to broadcast the node's transactions.
+
+use bitcoin::{ScriptBuf, Transaction, Txid};
+use electrum_client::ElectrumApi;
+use lightning::chain::{Confirm, Filter, WatchedOutput};
+use lightning_transaction_sync::ElectrumSyncClient;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use ulw_core::{Error, Result};
+
+use crate::node::SimpleLogger;
+
+/// How often the chain is synced while the node is running
+pub(crate) const CHAIN_SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A source of chain data for LDK
+///
+/// Learns what to watch through [`Filter`] and reports confirmations, reorged-out transactions
+/// and the new chain tip to the given confirmables on `sync`. All methods block on the network.
+pub trait ChainSource: Filter + Send + Sync {
+    /// Bring `confirmables` up to date with the current best chain
+    fn sync(&self, confirmables: Vec<Arc<dyn Confirm + Send + Sync>>) -> Result<()>;
+
+    /// Hand `tx` to the network
+    fn broadcast(&self, tx: &Transaction) -> Result<()>;
+
+    /// Whether `tx` has confirmed in the best chain
+    fn is_confirmed(&self, tx: &Transaction) -> Result<bool>;
+}
+
+/// Transactions and outputs registered through [`Filter`]
+#[derive(Default)]
+struct Watched {
+    txs: Vec<(Txid, ScriptBuf)>,
+    outputs: Vec<WatchedOutput>,
+}
+
+/// Chain source backed by an Electrum server
+///
+/// Connects on the first sync so the node can start while the server is unreachable.
+/// Registrations made before then are replayed once connected.
+pub struct ElectrumChainSource {
+    server_url: String,
+    logger: Arc<SimpleLogger>,
+    client: OnceLock<ElectrumSyncClient<Arc<SimpleLogger>>>,
+    watched: Mutex<Watched>,
+}
+
+impl ElectrumChainSource {
+    pub fn new(server_url: String, logger: Arc<SimpleLogger>) -> Self {
+        Self {
+            server_url,
+            logger,
+            client: OnceLock::new(),
+            watched: Mutex::new(Watched::default()),
+        }
+    }
+
+    fn client(&self) -> Result<&ElectrumSyncClient<Arc<SimpleLogger>>> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+
+        // Hold the registrations while connecting so none are missed by the new client
+        let watched = self.watched.lock().unwrap();
+        if self.client.get().is_none() {
+            let client = ElectrumSyncClient::new(self.server_url.clone(), self.logger.clone())
+                .map_err(|e| {
+                    Error::Network(format!(
+                        "Failed to connect to Electrum server {}: {}",
+                        self.server_url, e
+                    ))
+                })?;
+            for (txid, script) in &watched.txs {
+                client.register_tx(txid, script);
+            }
+            for output in &watched.outputs {
+                client.register_output(output.clone());
+            }
+            let _ = self.client.set(client);
+        }
+        drop(watched);
+
+        Ok(self.client.get().expect("client was just set"))
+    }
+}
+
+impl Filter for ElectrumChainSource {
+    fn register_tx(&self, txid: &Txid, script_pubkey: &bitcoin::Script) {
+        let mut watched = self.watched.lock().unwrap();
+        watched.txs.push((*txid, script_pubkey.to_owned()));
+        if let Some(client) = self.client.get() {
+            client.register_tx(txid, script_pubkey);
+        }
+    }
+
+    fn register_output(&self, output: WatchedOutput) {
+        let mut watched = self.watched.lock().unwrap();
+        watched.outputs.push(output.clone());
+        if let Some(client) = self.client.get() {
+            client.register_output(output);
+        }
+    }
+}
+
+impl ChainSource for ElectrumChainSource {
+    fn sync(&self, confirmables: Vec<Arc<dyn Confirm + Send + Sync>>) -> Result<()> {
+        self.client()?
+            .sync(confirmables)
+            .map_err(|e| Error::Network(format!("Chain sync failed: {}", e)))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<()> {
+        match self.client()?.client().transaction_broadcast(tx) {
+            Ok(_) => Ok(()),
+            // Rebroadcasting a transaction the server already has is not a failure
+            Err(e) if e.to_string().contains("already") => Ok(()),
+            Err(e) => Err(Error::Network(format!(
+                "Failed to broadcast {}: {}",
+                tx.compute_txid(),
+                e
+            ))),
+        }
+    }
+
+    fn is_confirmed(&self, tx: &Transaction) -> Result<bool> {
+        let Some(output) = tx.output.first() else {
+            return Ok(false);
+        };
+        let txid = tx.compute_txid();
+        let history = self
+            .client()?
+            .client()
+            .script_get_history(&output.script_pubkey)
+            .map_err(|e| Error::Network(format!("Failed to look up {}: {}", txid, e)))?;
+        Ok(history
+            .iter()
+            .any(|entry| entry.tx_hash == txid && entry.height > 0))
+    }
+}
+
+/// Run a blocking call on `chain_source` without blocking the async runtime
+pub(crate) async fn run_blocking<T, F>(chain_source: Arc<dyn ChainSource>, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn ChainSource) -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&*chain_source))
+        .await
+        .map_err(|e| Error::Internal(format!("Chain source task failed: {}", e)))?
+}
+
+/// Sync `confirmables` from `chain_source` without blocking the async runtime
+pub(crate) async fn sync_confirmables(
+    chain_source: Arc<dyn ChainSource>,
+    confirmables: Vec<Arc<dyn Confirm + Send + Sync>>,
+) -> Result<()> {
+    run_blocking(chain_source, move |chain_source| {
+        chain_source.sync(confirmables)
+    })
+    .await
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::node::tests::test_node_with_chain_source;
+    use bitcoin::block::Header;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, Network, TxMerkleNode};
+    use lightning::chain::transaction::TransactionData;
+    use std::collections::{HashMap, HashSet};
+
+    /// In-memory chain of headers that can be extended and reorged
+    ///
+    /// Blocks may carry transactions, which are reported to confirmables on sync when they were
+    /// registered through [`Filter`] or spend a registered output. Also stands in for the network
+    /// when broadcasting: it records what was broadcast and can be told to reject a number of
+    /// broadcasts, or that a transaction has confirmed.
+    pub(crate) struct MockChainSource {
+        headers: Mutex<Vec<Header>>,
+        block_txs: Mutex<HashMap<BlockHash, Vec<Transaction>>>,
+        watched: Mutex<Watched>,
+        pub(crate) broadcasts: Mutex<Vec<Transaction>>,
+        failing_broadcasts: Mutex<usize>,
+        confirmed: Mutex<HashSet<Txid>>,
+    }
+
+    impl MockChainSource {
+        pub(crate) fn new() -> Self {
+            let genesis = bitcoin::constants::genesis_block(Network::Regtest).header;
+            Self {
+                headers: Mutex::new(vec![genesis]),
+                block_txs: Mutex::new(HashMap::new()),
+                watched: Mutex::new(Watched::default()),
+                broadcasts: Mutex::new(Vec::new()),
+                failing_broadcasts: Mutex::new(0),
+                confirmed: Mutex::new(HashSet::new()),
+            }
+        }
+
+        /// Reject the next `count` broadcasts
+        pub(crate) fn fail_broadcasts(&self, count: usize) {
+            *self.failing_broadcasts.lock().unwrap() = count;
+        }
+
+        /// Report `txid` as confirmed from now on
+        pub(crate) fn confirm(&self, txid: Txid) {
+            self.confirmed.lock().unwrap().insert(txid);
+        }
+
+        /// Extend the chain by `count` blocks, using `nonce` to tell competing forks apart
+        pub(crate) fn mine(&self, count: usize, nonce: u32) {
+            let mut headers = self.headers.lock().unwrap();
+            for _ in 0..count {
+                let prev = *headers.last().unwrap();
+                headers.push(Header {
+                    version: prev.version,
+                    prev_blockhash: prev.block_hash(),
+                    merkle_root: TxMerkleNode::all_zeros(),
+                    time: prev.time + 600,
+                    bits: prev.bits,
+                    nonce,
+                });
+            }
+        }
+
+        /// Extend the chain by one block containing `txs`
+        pub(crate) fn mine_txs(&self, txs: Vec<Transaction>, nonce: u32) {
+            self.mine(1, nonce);
+            let (hash, _) = self.tip();
+            self.block_txs.lock().unwrap().insert(hash, txs);
+        }
+
+        /// Disconnect the top `depth` blocks
+        pub(crate) fn disconnect(&self, depth: usize) {
+            let mut headers = self.headers.lock().unwrap();
+            let height = headers.len() - depth;
+            headers.truncate(height);
+        }
+
+        pub(crate) fn tip(&self) -> (bitcoin::BlockHash, u32) {
+            let headers = self.headers.lock().unwrap();
+            let tip = headers.last().unwrap();
+            (tip.block_hash(), headers.len() as u32 - 1)
+        }
+    }
+
+    impl Filter for MockChainSource {
+        fn register_tx(&self, txid: &Txid, script_pubkey: &bitcoin::Script) {
+            let mut watched = self.watched.lock().unwrap();
+            watched.txs.push((*txid, script_pubkey.to_owned()));
+        }
+
+        fn register_output(&self, output: WatchedOutput) {
+            self.watched.lock().unwrap().outputs.push(output);
+        }
+    }
+
+    /// Whether `tx` was registered or spends a registered output
+    fn is_watched(watched: &Watched, tx: &Transaction) -> bool {
+        let txid = tx.compute_txid();
+        watched.txs.iter().any(|(watched, _)| *watched == txid)
+            || tx.input.iter().any(|input| {
+                watched
+                    .outputs
+                    .iter()
+                    .any(|output| output.outpoint.into_bitcoin_outpoint() == input.previous_output)
+            })
+    }
+
+    impl ChainSource for MockChainSource {
+        fn sync(&self, confirmables: Vec<Arc<dyn Confirm + Send + Sync>>) -> Result<()> {
+            let headers = self.headers.lock().unwrap();
+            let block_txs = self.block_txs.lock().unwrap();
+            let tip_height = headers.len() as u32 - 1;
+
+            for confirmable in &confirmables {
+                let relevant = confirmable.get_relevant_txids();
+                for (txid, height, block_hash) in &relevant {
+                    let in_best_chain = headers
+                        .get(*height as usize)
+                        .map(|header| Some(header.block_hash()) == *block_hash)
+                        .unwrap_or(false);
+                    if !in_best_chain {
+                        confirmable.transaction_unconfirmed(txid);
+                    }
+                }
+
+                // Report watched transactions of the best chain not yet known to be in their block
+                for (height, header) in headers.iter().enumerate() {
+                    let block_hash = header.block_hash();
+                    let Some(txs) = block_txs.get(&block_hash) else {
+                        continue;
+                    };
+                    let txdata: Vec<(usize, &Transaction)> = txs
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, tx)| {
+                            let txid = tx.compute_txid();
+                            // Not held while calling out, as confirmables register more
+                            is_watched(&self.watched.lock().unwrap(), tx)
+                                && !relevant.iter().any(|(relevant, _, hash)| {
+                                    *relevant == txid && *hash == Some(block_hash)
+                                })
+                        })
+                        .collect();
+                    if !txdata.is_empty() {
+                        confirmable.transactions_confirmed(header, &txdata, height as u32);
+                    }
+                }
+
+                confirmable.best_block_updated(headers.last().unwrap(), tip_height);
+            }
+            Ok(())
+        }
+
+        fn broadcast(&self, tx: &Transaction) -> Result<()> {
+            let mut failing = self.failing_broadcasts.lock().unwrap();
+            if *failing > 0 {
+                *failing -= 1;
+                return Err(Error::Network("Connection refused".to_string()));
+            }
+            self.broadcasts.lock().unwrap().push(tx.clone());
+            Ok(())
+        }
+
+        fn is_confirmed(&self, tx: &Transaction) -> Result<bool> {
+            Ok(self.confirmed.lock().unwrap().contains(&tx.compute_txid()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_follows_reorg() {
+        let chain = Arc::new(MockChainSource::new());
+        let (node, _temp) = test_node_with_chain_source([50u8; 32], chain.clone()).await;
+
+        chain.mine(10, 0);
+        node.sync_chain().await.unwrap();
+        let best = node.channel_manager.current_best_block();
+        assert_eq!((best.block_hash, best.height), chain.tip());
+
+        // Replace the top three blocks with a longer competing fork
+        let stale_tip = best.block_hash;
+        chain.disconnect(3);
+        chain.mine(4, 1);
+        node.sync_chain().await.unwrap();
+
+        let best = node.channel_manager.current_best_block();
+        assert_eq!((best.block_hash, best.height), chain.tip());
+        assert_eq!(best.height, 11);
+        assert_ne!(best.block_hash, stale_tip);
+    }
+
+    /// Confirmable that remembers what it was told
+    #[derive(Default)]
+    struct RecordingConfirm {
+        confirmed: Mutex<HashMap<Txid, (u32, BlockHash)>>,
+        confirmations: Mutex<usize>,
+        unconfirmed: Mutex<Vec<Txid>>,
+    }
+
+    impl Confirm for RecordingConfirm {
+        fn transactions_confirmed(&self, header: &Header, txdata: &TransactionData, height: u32) {
+            let mut confirmed = self.confirmed.lock().unwrap();
+            for (_, tx) in txdata {
+                confirmed.insert(tx.compute_txid(), (height, header.block_hash()));
+                *self.confirmations.lock().unwrap() += 1;
+            }
+        }
+
+        fn transaction_unconfirmed(&self, txid: &Txid) {
+            self.confirmed.lock().unwrap().remove(txid);
+            self.unconfirmed.lock().unwrap().push(*txid);
+        }
+
+        fn best_block_updated(&self, _header: &Header, _height: u32) {}
+
+        fn get_relevant_txids(&self) -> Vec<(Txid, u32, Option<BlockHash>)> {
+            self.confirmed
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(txid, (height, hash))| (*txid, *height, Some(*hash)))
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_unconfirms_reorged_transactions() {
+        let chain = Arc::new(MockChainSource::new());
+        let recorder = Arc::new(RecordingConfirm::default());
+        let confirmables = || vec![recorder.clone() as Arc<dyn Confirm + Send + Sync>];
+
+        let script = ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros());
+        let funding = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(100_000),
+                script_pubkey: script.clone(),
+            }],
+        };
+        let txid = funding.compute_txid();
+        chain.register_tx(&txid, &script);
+
+        // Unwatched transactions are not reported
+        chain.mine(5, 0);
+        chain.mine_txs(
+            vec![Transaction {
+                output: vec![],
+                ..funding.clone()
+            }],
+            0,
+        );
+        chain.mine_txs(vec![funding.clone()], 0);
+        let (confirmed_in, _) = chain.tip();
+        chain.mine(2, 0);
+        sync_confirmables(chain.clone(), confirmables())
+            .await
+            .unwrap();
+        assert_eq!(
+            *recorder.confirmed.lock().unwrap(),
+            HashMap::from([(txid, (7, confirmed_in))])
+        );
+
+        // Syncing again reports nothing new
+        sync_confirmables(chain.clone(), confirmables())
+            .await
+            .unwrap();
+        assert_eq!(*recorder.confirmations.lock().unwrap(), 1);
+
+        // A fork without the transaction replaces its block
+        chain.disconnect(3);
+        chain.mine(4, 1);
+        sync_confirmables(chain.clone(), confirmables())
+            .await
+            .unwrap();
+        assert_eq!(*recorder.unconfirmed.lock().unwrap(), vec![txid]);
+        assert!(recorder.confirmed.lock().unwrap().is_empty());
+
+        // The transaction confirms again on the new fork
+        chain.mine_txs(vec![funding], 1);
+        let (reconfirmed_in, tip_height) = chain.tip();
+        sync_confirmables(chain.clone(), confirmables())
+            .await
+            .unwrap();
+        assert_eq!(
+            *recorder.confirmed.lock().unwrap(),
+            HashMap::from([(txid, (tip_height, reconfirmed_in))])
+        );
+        assert_eq!(*recorder.unconfirmed.lock().unwrap(), vec![txid]);
+    }
+
+    #[tokio::test]
+    async fn test_electrum_sync_fails_without_server() {
+        let source =
+            ElectrumChainSource::new("tcp://127.0.0.1:1".to_string(), Arc::new(SimpleLogger));
+
+        let result = sync_confirmables(Arc::new(source), vec![]).await;
+        assert!(matches!(result, Err(Error::Network(_))));
+    }
+}
@@ -1,13 +1,275 @@
 //! Lightning channel management
 
-use ulw_core::Result;
+use bitcoin::secp256k1::PublicKey;
+use lightning::chain::channelmonitor::{Balance, BalanceSource};
+use lightning::chain::transaction::OutPoint;
+use lightning::ln::channel_state::ChannelDetails as LdkChannelDetails;
+use lightning::ln::script::ShutdownScript;
+use lightning::ln::types::ChannelId;
+use lightning::sign::EntropySource;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub struct ChannelManager {
-    // Placeholder
+use ulw_core::types::{ChannelInfo, ChannelState, InboundChannelPolicy};
+use ulw_core::{Error, Result};
+
+use crate::anchors::ANCHOR_RESERVE_PER_CHANNEL_SATS;
+use crate::node::{default_user_config, ChainMonitor, LdkNode};
+
+/// How long to wait for the counterparty to accept a channel and for funding to complete
+const CHANNEL_OPEN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long to wait for a close to be negotiated or broadcast
+const CHANNEL_CLOSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Convert LDK channel details into the wallet's channel record
+pub(crate) fn channel_info(details: &LdkChannelDetails, state: ChannelState) -> ChannelInfo {
+    ChannelInfo {
+        channel_id: details.channel_id.to_string(),
+        counterparty_node_id: details.counterparty.node_id.to_string(),
+        capacity_sats: details.channel_value_satoshis,
+        local_balance_msat: details.outbound_capacity_msat,
+        remote_balance_msat: details.inbound_capacity_msat,
+        state,
+        funding_txo: details
+            .funding_txo
+            .map(|txo| txo.into_bitcoin_outpoint().to_string()),
+        closing_txid: None,
+        claimable_at_height: None,
+    }
 }
 
-impl ChannelManager {
-    pub fn new() -> Result<Self> {
-        Ok(Self {})
+/// Height at which our funds from a force-closed channel become spendable
+///
+/// Only known once the commitment transaction has confirmed.
+pub(crate) fn force_close_maturity_height(
+    chain_monitor: &ChainMonitor,
+    funding_txo: &bitcoin::OutPoint,
+) -> Option<u32> {
+    let outpoint = OutPoint {
+        txid: funding_txo.txid,
+        index: funding_txo.vout as u16,
+    };
+    let monitor = chain_monitor.get_monitor(outpoint).ok()?;
+
+    monitor
+        .get_claimable_balances()
+        .iter()
+        .filter_map(|balance| match balance {
+            Balance::ClaimableAwaitingConfirmations {
+                confirmation_height,
+                source: BalanceSource::HolderForceClosed,
+                ..
+            } => Some(*confirmation_height),
+            _ => None,
+        })
+        .max()
+}
+
+/// Derive the wallet channel state from LDK's view of a live channel
+fn live_channel_state(details: &LdkChannelDetails) -> ChannelState {
+    if details.channel_shutdown_state.is_some() {
+        ChannelState::Closing
+    } else if details.is_channel_ready {
+        ChannelState::Active
+    } else {
+        ChannelState::Opening
+    }
+}
+
+impl LdkNode {
+    /// Set which peers may open channels to us
+    ///
+    /// Requests that the policy rejects are refused before any funds are committed.
+    pub fn set_inbound_channel_policy(&self, policy: InboundChannelPolicy) {
+        *self.event_handler.inbound_channel_policy.write().unwrap() = policy;
+    }
+
+    /// Open a channel to a connected peer, funded from the on-chain wallet
+    ///
+    /// Waits until the funding transaction has been built and handed to LDK and returns
+    /// the final channel ID. The channel is tracked as `Opening` until it is ready. The wallet
+    /// must keep `ANCHOR_RESERVE_PER_CHANNEL_SATS` per anchor channel on top of the capacity.
+    ///
+    /// # Arguments
+    /// * `node_id` - Counterparty public key (must already be connected)
+    /// * `amount_sats` - Channel capacity in satoshis
+    /// * `push_msat` - Amount to push to the counterparty on open
+    /// * `announce` - Whether to announce the channel to the network
+    pub async fn open_channel(
+        &self,
+        node_id: PublicKey,
+        amount_sats: u64,
+        push_msat: u64,
+        announce: bool,
+    ) -> Result<ChannelId> {
+        if self.peer_manager.peer_by_node_id(&node_id).is_none() {
+            return Err(Error::Network(format!("Not connected to peer {}", node_id)));
+        }
+
+        // Leave enough behind to fee bump a force close of this and every other anchor channel
+        let reserve = self.anchor_reserve().await?;
+        let required = amount_sats + reserve.required_sats + ANCHOR_RESERVE_PER_CHANNEL_SATS;
+        let available = self.wallet.get_balance().await?.to_sat();
+        if available < required {
+            return Err(Error::InsufficientFunds {
+                required,
+                available,
+            });
+        }
+
+        let mut config = default_user_config();
+        config.channel_handshake_config.announce_for_forwarding = announce;
+
+        let mut id_bytes = [0u8; 16];
+        id_bytes.copy_from_slice(&self.keys_manager.get_secure_random_bytes()[..16]);
+        let user_channel_id = u128::from_be_bytes(id_bytes);
+
+        let funded = self.event_handler.channel_opens.register(user_channel_id);
+
+        let temporary_channel_id = self
+            .channel_manager
+            .create_channel(
+                node_id,
+                amount_sats,
+                push_msat,
+                user_channel_id,
+                None,
+                Some(config),
+            )
+            .map_err(|e| Error::Internal(format!("Failed to open channel: {:?}", e)))?;
+
+        tracing::info!(
+            "Opening channel {} to {} for {} sats",
+            temporary_channel_id,
+            node_id,
+            amount_sats
+        );
+
+        self.wait_for(
+            funded,
+            CHANNEL_OPEN_TIMEOUT,
+            &format!("{} to accept channel {}", node_id, temporary_channel_id),
+        )
+        .await?
+    }
+
+    /// Close a channel
+    ///
+    /// A cooperative close pays our balance to a fresh on-chain wallet address and needs the
+    /// counterparty to be connected. A force close broadcasts our latest commitment
+    /// transaction; our funds then stay timelocked until `claimable_at_height`.
+    ///
+    /// # Arguments
+    /// * `channel_id` - Channel ID as shown by `list_channels`
+    /// * `force` - Unilaterally close by broadcasting the commitment transaction
+    /// * `target_feerate_sat_per_vb` - Target fee rate for a cooperative close
+    pub async fn close_channel(
+        &self,
+        channel_id: &str,
+        force: bool,
+        target_feerate_sat_per_vb: Option<u32>,
+    ) -> Result<ChannelInfo> {
+        let details = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .find(|c| c.channel_id.to_string() == channel_id)
+            .ok_or_else(|| Error::ChannelNotFound(channel_id.to_string()))?;
+        let counterparty = details.counterparty.node_id;
+
+        let closed = self
+            .event_handler
+            .channel_closes
+            .register(details.channel_id);
+
+        if force {
+            tracing::info!("Force closing channel {}", channel_id);
+            self.channel_manager
+                .force_close_broadcasting_latest_txn(
+                    &details.channel_id,
+                    &counterparty,
+                    "Force close requested by user".to_string(),
+                )
+                .map_err(|e| Error::Internal(format!("Failed to force close: {:?}", e)))?;
+        } else {
+            if self.peer_manager.peer_by_node_id(&counterparty).is_none() {
+                return Err(Error::Network(format!(
+                    "Peer {} is not connected; cooperative close needs the peer online",
+                    counterparty
+                )));
+            }
+
+            let address = self.wallet.get_new_address().await?;
+            let shutdown_script = ShutdownScript::try_from(address.script_pubkey())
+                .map_err(|e| Error::InvalidAddress(format!("{:?}", e)))?;
+
+            tracing::info!(
+                "Cooperatively closing channel {} to {}",
+                channel_id,
+                address
+            );
+            // 1 sat/vB is 250 sat per 1000 weight units
+            self.channel_manager
+                .close_channel_with_feerate_and_script(
+                    &details.channel_id,
+                    &counterparty,
+                    target_feerate_sat_per_vb.map(|rate| rate.saturating_mul(250)),
+                    Some(shutdown_script),
+                )
+                .map_err(|e| Error::Internal(format!("Failed to close channel: {:?}", e)))?;
+        }
+
+        let mut closing = channel_info(&details, ChannelState::Closing);
+        if let Some(stored) = self.storage.get_channel(channel_id).await? {
+            closing.closing_txid = stored.closing_txid;
+        }
+        self.storage.save_channel(&closing).await?;
+
+        self.wait_for(
+            closed,
+            CHANNEL_CLOSE_TIMEOUT,
+            &format!("channel {} to close", channel_id),
+        )
+        .await?;
+
+        self.storage
+            .get_channel(channel_id)
+            .await?
+            .ok_or_else(|| Error::ChannelNotFound(channel_id.to_string()))
+    }
+
+    /// List all channels, refreshing live channels in wallet storage first
+    ///
+    /// Closed channels are kept in storage and returned alongside live ones.
+    pub async fn list_channels(&self) -> Result<Vec<ChannelInfo>> {
+        for details in self
+            .channel_manager
+            .list_channels()
+            .iter()
+            .filter(|c| c.funding_txo.is_some())
+        {
+            let info = channel_info(details, live_channel_state(details));
+            self.storage.save_channel(&info).await?;
+        }
+
+        // Pick up the maturity height of force-closed channels once it is known
+        for mut channel in self.storage.list_channels().await? {
+            if channel.state != ChannelState::Closed || channel.claimable_at_height.is_some() {
+                continue;
+            }
+            let funding_txo = channel
+                .funding_txo
+                .as_deref()
+                .and_then(|txo| bitcoin::OutPoint::from_str(txo).ok());
+            if let Some(height) =
+                funding_txo.and_then(|txo| force_close_maturity_height(&self.chain_monitor, &txo))
+            {
+                channel.claimable_at_height = Some(height);
+                self.storage.save_channel(&channel).await?;
+            }
+        }
+
+        self.storage.list_channels().await
     }
 }
@@ -1,17 +1,1450 @@
 //! Lightning event handling
 
-use ulw_core::Result;
+use bitcoin::hashes::{sha256, Hash as _, HashEngine};
+use bitcoin::{Amount, FeeRate};
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use lightning::events::{Event, PaymentPurpose, ReplayEvent};
+use lightning::ln::channelmanager::PaymentId;
+use lightning::ln::types::ChannelId;
+use lightning::ln::PaymentPreimage;
+use lightning::routing::router::Path;
+use lightning::sign::{EntropySource, KeysManager};
+use lightning::util::ser::Writeable;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
 
+use ulw_bdk::BdkWallet;
+use ulw_core::traits::WalletStorage;
+use ulw_core::types::{
+    ChannelInfo, ChannelState, InboundChannelDecision, InboundChannelPolicy, Payment,
+    PaymentDirection, PaymentStatus,
+};
+use ulw_core::{Error, Result};
+
+use crate::anchors::{anchor_reserve, BumpHandler, ANCHOR_RESERVE_PER_CHANNEL_SATS};
+use crate::backup::ChannelBackups;
+use crate::broadcast::Broadcaster;
+use crate::channels::{channel_info, force_close_maturity_height};
+use crate::fees::WalletFeeEstimator;
+use crate::node::{ChainMonitor, ChannelManager, Scorer};
+use crate::scoring::score_event;
+use crate::sweep::Sweeper;
+
+/// Callers waiting on the outcome of an event, keyed by whatever identifies it
+pub(crate) struct EventWaiters<K, V> {
+    senders: Mutex<HashMap<K, oneshot::Sender<V>>>,
+}
+
+impl<K: Eq + Hash, V> EventWaiters<K, V> {
+    fn new() -> Self {
+        Self {
+            senders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register interest in the outcome for `key`
+    pub(crate) fn register(&self, key: K) -> oneshot::Receiver<V> {
+        let (tx, rx) = oneshot::channel();
+        self.senders.lock().unwrap().insert(key, tx);
+        rx
+    }
+
+    fn notify(&self, key: &K, value: V) {
+        if let Some(tx) = self.senders.lock().unwrap().remove(key) {
+            let _ = tx.send(value);
+        }
+    }
+}
+
+/// Handles events emitted by the channel manager and chain monitor
 pub struct EventHandler {
-    // Placeholder
+    keys_manager: Arc<KeysManager>,
+    channel_manager: Arc<ChannelManager>,
+    chain_monitor: Arc<ChainMonitor>,
+    broadcaster: Arc<Broadcaster>,
+    wallet: Arc<BdkWallet>,
+    storage: Arc<dyn WalletStorage>,
+    fee_estimator: Arc<WalletFeeEstimator>,
+    sweeper: Arc<Sweeper>,
+    scorer: Arc<RwLock<Scorer>>,
+    bump_handler: Arc<BumpHandler>,
+    channel_backups: Arc<ChannelBackups>,
+    /// Set when events handled by `process_events` updated the scorer
+    scorer_updated: AtomicBool,
+    /// Whether spontaneous (keysend) payments are claimed rather than failed back
+    pub(crate) accept_keysend: AtomicBool,
+    /// Which peers may open channels to us
+    pub(crate) inbound_channel_policy: RwLock<InboundChannelPolicy>,
+    /// Channel opens by user channel ID, resolved with the final channel ID once funded
+    pub(crate) channel_opens: EventWaiters<u128, Result<ChannelId>>,
+    /// Channel closes, resolved once LDK reports the channel closed
+    pub(crate) channel_closes: EventWaiters<ChannelId, ()>,
+    /// Outbound payments, resolved with the preimage and fee paid or the failure reason
+    pub(crate) payment_results: EventWaiters<PaymentId, Result<(PaymentPreimage, Option<u64>)>>,
 }
 
 impl EventHandler {
-    pub fn new() -> Result<Self> {
-        Ok(Self {})
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        keys_manager: Arc<KeysManager>,
+        channel_manager: Arc<ChannelManager>,
+        chain_monitor: Arc<ChainMonitor>,
+        broadcaster: Arc<Broadcaster>,
+        wallet: Arc<BdkWallet>,
+        storage: Arc<dyn WalletStorage>,
+        fee_estimator: Arc<WalletFeeEstimator>,
+        sweeper: Arc<Sweeper>,
+        scorer: Arc<RwLock<Scorer>>,
+        bump_handler: Arc<BumpHandler>,
+        channel_backups: Arc<ChannelBackups>,
+    ) -> Self {
+        Self {
+            keys_manager,
+            channel_manager,
+            chain_monitor,
+            broadcaster,
+            wallet,
+            storage,
+            fee_estimator,
+            sweeper,
+            scorer,
+            bump_handler,
+            channel_backups,
+            scorer_updated: AtomicBool::new(false),
+            accept_keysend: AtomicBool::new(false),
+            inbound_channel_policy: RwLock::new(InboundChannelPolicy::default()),
+            channel_opens: EventWaiters::new(),
+            channel_closes: EventWaiters::new(),
+            payment_results: EventWaiters::new(),
+        }
     }
 
+    /// Drain and handle all pending channel manager and chain monitor events
+    ///
+    /// For use while the background processor is not running, so payment and probe outcomes
+    /// are fed to the scorer here.
     pub async fn process_events(&self) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        self.channel_manager
+            .process_pending_events_async(|event| {
+                if score_event(&self.scorer, &event, now) {
+                    self.scorer_updated.store(true, Ordering::Release);
+                }
+                self.handle_event(event)
+            })
+            .await;
+        self.chain_monitor
+            .process_pending_events_async(|event| self.handle_event(event))
+            .await;
+        Ok(())
+    }
+
+    /// Whether the scorer was updated since the last call
+    pub(crate) fn take_scorer_updated(&self) -> bool {
+        self.scorer_updated.swap(false, Ordering::AcqRel)
+    }
+
+    /// Handle a single LDK event
+    pub async fn handle_event(&self, event: Event) -> std::result::Result<(), ReplayEvent> {
+        match event {
+            Event::FundingGenerationReady {
+                temporary_channel_id,
+                counterparty_node_id,
+                channel_value_satoshis,
+                output_script,
+                user_channel_id,
+            } => {
+                let sat_per_kw = self
+                    .fee_estimator
+                    .get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+                let fee_rate = FeeRate::from_sat_per_kwu(sat_per_kw as u64);
+
+                let funded = match self
+                    .wallet
+                    .create_funding_transaction(
+                        output_script,
+                        Amount::from_sat(channel_value_satoshis),
+                        fee_rate,
+                    )
+                    .await
+                {
+                    Ok(funding_tx) => self
+                        .channel_manager
+                        .funding_transaction_generated(
+                            temporary_channel_id,
+                            counterparty_node_id,
+                            funding_tx,
+                        )
+                        .map_err(|e| Error::Internal(format!("{:?}", e))),
+                    Err(e) => Err(e),
+                };
+
+                if let Err(e) = funded {
+                    tracing::error!("Failed to fund channel {}: {}", temporary_channel_id, e);
+                    let _ = self.channel_manager.force_close_without_broadcasting_txn(
+                        &temporary_channel_id,
+                        &counterparty_node_id,
+                        "Channel funding failed".to_string(),
+                    );
+                    self.channel_opens.notify(&user_channel_id, Err(e));
+                }
+            }
+            Event::OpenChannelRequest {
+                temporary_channel_id,
+                counterparty_node_id,
+                funding_satoshis,
+                channel_type,
+                ..
+            } => {
+                let anchors = channel_type.supports_anchors_zero_fee_htlc_tx();
+                let mut decision = self.inbound_channel_policy.read().unwrap().evaluate(
+                    &counterparty_node_id,
+                    funding_satoshis,
+                    anchors,
+                    channel_type.requires_zero_conf(),
+                );
+                // We may have to fee bump the channel's force close, unless the peer is trusted
+                if anchors && decision == InboundChannelDecision::Accept {
+                    match anchor_reserve(&self.channel_manager, &self.wallet).await {
+                        Ok(reserve)
+                            if reserve.available_sats
+                                < reserve.required_sats + ANCHOR_RESERVE_PER_CHANNEL_SATS =>
+                        {
+                            decision = InboundChannelDecision::Reject(
+                                "on-chain reserve too low to fee bump another anchor channel"
+                                    .to_string(),
+                            );
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("Failed to check anchor reserve: {}", e),
+                    }
+                }
+                let mut id_bytes = [0u8; 16];
+                id_bytes.copy_from_slice(&self.keys_manager.get_secure_random_bytes()[..16]);
+                let user_channel_id = u128::from_be_bytes(id_bytes);
+                let result = match &decision {
+                    InboundChannelDecision::Accept => self.channel_manager.accept_inbound_channel(
+                        &temporary_channel_id,
+                        &counterparty_node_id,
+                        user_channel_id,
+                    ),
+                    InboundChannelDecision::AcceptZeroConf => self
+                        .channel_manager
+                        .accept_inbound_channel_from_trusted_peer_0conf(
+                            &temporary_channel_id,
+                            &counterparty_node_id,
+                            user_channel_id,
+                        ),
+                    InboundChannelDecision::Reject(reason) => {
+                        tracing::info!(
+                            "Rejecting {} sat channel from {}: {}",
+                            funding_satoshis,
+                            counterparty_node_id,
+                            reason
+                        );
+                        self.channel_manager.force_close_without_broadcasting_txn(
+                            &temporary_channel_id,
+                            &counterparty_node_id,
+                            reason.clone(),
+                        )
+                    }
+                };
+                match result {
+                    Ok(()) => {
+                        if !matches!(decision, InboundChannelDecision::Reject(_)) {
+                            tracing::info!(
+                                "Accepted {} sat channel from {}{}",
+                                funding_satoshis,
+                                counterparty_node_id,
+                                if decision == InboundChannelDecision::AcceptZeroConf {
+                                    " as zero-conf"
+                                } else {
+                                    ""
+                                }
+                            );
+                        }
+                    }
+                    Err(e) => tracing::warn!(
+                        "Failed to respond to channel request {} from {}: {:?}",
+                        temporary_channel_id,
+                        counterparty_node_id,
+                        e
+                    ),
+                }
+            }
+            Event::ChannelPending {
+                channel_id,
+                user_channel_id,
+                funding_txo,
+                ..
+            } => {
+                tracing::info!(
+                    "Channel {} pending, funding outpoint {}",
+                    channel_id,
+                    funding_txo
+                );
+                self.update_channel(&channel_id, ChannelState::Opening)
+                    .await?;
+                self.backup_channels(None);
+                self.channel_opens.notify(&user_channel_id, Ok(channel_id));
+            }
+            Event::ChannelReady { channel_id, .. } => {
+                tracing::info!("Channel {} is ready", channel_id);
+                self.update_channel(&channel_id, ChannelState::Active)
+                    .await?;
+            }
+            Event::ChannelClosed {
+                channel_id,
+                user_channel_id,
+                reason,
+                counterparty_node_id,
+                channel_capacity_sats,
+                channel_funding_txo,
+            } => {
+                tracing::info!("Channel {} closed: {}", channel_id, reason);
+
+                let id = channel_id.to_string();
+                let stored = match self.storage.get_channel(&id).await {
+                    Ok(stored) => stored,
+                    Err(e) => {
+                        tracing::error!("Failed to load channel {}: {}", id, e);
+                        return Err(ReplayEvent());
+                    }
+                };
+
+                // Channels that never got a funding transaction were never recorded
+                if let Some(funding_txo) = channel_funding_txo {
+                    let funding_txo = funding_txo.into_bitcoin_outpoint();
+                    let mut record = stored.unwrap_or_else(|| ChannelInfo {
+                        channel_id: id.clone(),
+                        counterparty_node_id: counterparty_node_id
+                            .map(|n| n.to_string())
+                            .unwrap_or_default(),
+                        capacity_sats: channel_capacity_sats.unwrap_or(0),
+                        local_balance_msat: 0,
+                        remote_balance_msat: 0,
+                        state: ChannelState::Closed,
+                        funding_txo: Some(funding_txo.to_string()),
+                        closing_txid: None,
+                        claimable_at_height: None,
+                    });
+                    record.state = ChannelState::Closed;
+                    if let Some(txid) = self.broadcaster.find_spending_tx(&funding_txo) {
+                        record.closing_txid = Some(txid.to_string());
+                    }
+                    record.claimable_at_height =
+                        force_close_maturity_height(&self.chain_monitor, &funding_txo)
+                            .or(record.claimable_at_height);
+
+                    if let Err(e) = self.storage.save_channel(&record).await {
+                        tracing::error!("Failed to save channel {}: {}", id, e);
+                        return Err(ReplayEvent());
+                    }
+                }
+
+                self.channel_opens.notify(
+                    &user_channel_id,
+                    Err(Error::ChannelNotFound(format!(
+                        "Channel {} closed: {}",
+                        channel_id, reason
+                    ))),
+                );
+                self.backup_channels(channel_funding_txo.map(|txo| txo.into_bitcoin_outpoint()));
+                self.channel_closes.notify(&channel_id, ());
+            }
+            Event::PaymentClaimable {
+                payment_hash,
+                amount_msat,
+                purpose,
+                claim_deadline,
+                ..
+            } => {
+                let hash = hex::encode(payment_hash.0);
+                if matches!(purpose, PaymentPurpose::SpontaneousPayment(_))
+                    && !self.accept_keysend.load(Ordering::Acquire)
+                {
+                    tracing::info!("Keysend is disabled, failing back payment {}", hash);
+                    self.channel_manager.fail_htlc_backwards(&payment_hash);
+                    return Ok(());
+                }
+                match purpose.preimage() {
+                    Some(preimage) => {
+                        tracing::info!("Claiming {} msat for payment {}", amount_msat, hash);
+                        self.channel_manager.claim_funds(preimage);
+                    }
+                    // Only hold invoices are created without a preimage
+                    None => {
+                        let stored = match self.storage.get_payment(&hash).await {
+                            Ok(stored) => stored,
+                            Err(e) => {
+                                tracing::error!("Failed to load payment {}: {}", hash, e);
+                                return Err(ReplayEvent());
+                            }
+                        };
+                        let Some(mut payment) = stored.filter(|payment| {
+                            matches!(payment.status, PaymentStatus::Pending | PaymentStatus::Held)
+                        }) else {
+                            tracing::warn!("No open hold invoice {}, failing it back", hash);
+                            self.channel_manager.fail_htlc_backwards(&payment_hash);
+                            return Ok(());
+                        };
+
+                        tracing::info!(
+                            "Holding {} msat for payment {} until block {:?}",
+                            amount_msat,
+                            hash,
+                            claim_deadline
+                        );
+                        payment.amount_msat = amount_msat;
+                        payment.status = PaymentStatus::Held;
+                        payment.claim_deadline = claim_deadline;
+                        if let Err(e) = self.storage.save_payment(&payment).await {
+                            tracing::error!("Failed to save payment {}: {}", hash, e);
+                            return Err(ReplayEvent());
+                        }
+                    }
+                }
+            }
+            Event::PaymentClaimed {
+                payment_hash,
+                amount_msat,
+                purpose,
+                htlcs,
+                onion_fields,
+                ..
+            } => {
+                let hash = hex::encode(payment_hash.0);
+                tracing::info!("Received {} msat for payment {}", amount_msat, hash);
+
+                let stored = match self.storage.get_payment(&hash).await {
+                    Ok(stored) => stored,
+                    Err(e) => {
+                        tracing::error!("Failed to load payment {}: {}", hash, e);
+                        return Err(ReplayEvent());
+                    }
+                };
+                let mut payment = stored.unwrap_or_else(|| Payment {
+                    payment_hash: hash.clone(),
+                    amount_msat,
+                    direction: PaymentDirection::Inbound,
+                    status: PaymentStatus::Pending,
+                    invoice: None,
+                    created_at: chrono::Utc::now(),
+                    settled_at: None,
+                    preimage: None,
+                    fee_paid_msat: None,
+                    custom_tlvs: Vec::new(),
+                    parts: None,
+                    path_fees_msat: Vec::new(),
+                    path_ids: Vec::new(),
+                    offer_id: None,
+                    claim_deadline: None,
+                });
+                payment.amount_msat = amount_msat;
+                payment.status = PaymentStatus::Succeeded;
+                payment.settled_at = Some(chrono::Utc::now());
+                if let Some(preimage) = purpose.preimage() {
+                    payment.preimage = Some(hex::encode(preimage.0));
+                }
+                if let PaymentPurpose::Bolt12OfferPayment {
+                    payment_context, ..
+                } = &purpose
+                {
+                    payment.offer_id = Some(hex::encode(payment_context.offer_id.0));
+                }
+                if let Some(onion_fields) = onion_fields {
+                    payment.custom_tlvs = onion_fields.custom_tlvs().clone();
+                }
+                // LDK only reports a claim once every part of a multi-path payment arrived
+                if !htlcs.is_empty() {
+                    payment.parts = Some(htlcs.len() as u32);
+                }
+
+                if let Err(e) = self.storage.save_payment(&payment).await {
+                    tracing::error!("Failed to save payment {}: {}", hash, e);
+                    return Err(ReplayEvent());
+                }
+            }
+            Event::InvoiceReceived {
+                payment_id,
+                invoice,
+                context,
+                ..
+            } => {
+                // Invoices for offers and refunds we pay are handled here rather than by LDK,
+                // so the payment is recorded before any HTLC goes out
+                let hash = hex::encode(invoice.payment_hash().0);
+                let offer = match self.storage.get_offer(&hex::encode(payment_id.0)).await {
+                    Ok(offer) => offer,
+                    Err(e) => {
+                        tracing::error!("Failed to load offer for payment {}: {}", payment_id, e);
+                        return Err(ReplayEvent());
+                    }
+                };
+                let mut payment = Payment {
+                    payment_hash: hash.clone(),
+                    amount_msat: invoice.amount_msats(),
+                    direction: PaymentDirection::Outbound,
+                    status: PaymentStatus::Pending,
+                    invoice: offer.as_ref().map(|offer| offer.encoded.clone()),
+                    created_at: chrono::Utc::now(),
+                    settled_at: None,
+                    preimage: None,
+                    fee_paid_msat: None,
+                    custom_tlvs: Vec::new(),
+                    parts: None,
+                    path_fees_msat: Vec::new(),
+                    path_ids: Vec::new(),
+                    offer_id: offer.map(|offer| offer.id),
+                    claim_deadline: None,
+                };
+                if let Err(e) = self.storage.save_payment(&payment).await {
+                    tracing::error!("Failed to save payment {}: {}", hash, e);
+                    return Err(ReplayEvent());
+                }
+
+                tracing::info!(
+                    "Paying BOLT12 invoice for {} msat, payment {}",
+                    invoice.amount_msats(),
+                    hash
+                );
+                if let Err(e) = self
+                    .channel_manager
+                    .send_payment_for_bolt12_invoice(&invoice, context.as_ref())
+                {
+                    tracing::warn!("Failed to pay BOLT12 invoice {}: {:?}", hash, e);
+                    payment.status = PaymentStatus::Failed;
+                    if let Err(e) = self.storage.save_payment(&payment).await {
+                        tracing::error!("Failed to save payment {}: {}", hash, e);
+                    }
+                    self.payment_results
+                        .notify(&payment_id, Err(Error::PaymentFailed(format!("{:?}", e))));
+                }
+            }
+            Event::PaymentSent {
+                payment_id,
+                payment_preimage,
+                payment_hash,
+                fee_paid_msat,
+            } => {
+                let hash = hex::encode(payment_hash.0);
+                tracing::info!(
+                    "Payment {} sent, fee {} msat",
+                    hash,
+                    fee_paid_msat.unwrap_or(0)
+                );
+
+                self.update_payment(&hash, |payment| {
+                    payment.status = PaymentStatus::Succeeded;
+                    payment.settled_at = Some(chrono::Utc::now());
+                    payment.preimage = Some(hex::encode(payment_preimage.0));
+                    payment.fee_paid_msat = fee_paid_msat;
+                })
+                .await?;
+                if let Some(payment_id) = payment_id {
+                    self.payment_results
+                        .notify(&payment_id, Ok((payment_preimage, fee_paid_msat)));
+                }
+            }
+            Event::PaymentFailed {
+                payment_id,
+                payment_hash,
+                reason,
+            } => {
+                tracing::warn!("Payment {} failed: {:?}", payment_id, reason);
+
+                if let Some(payment_hash) = payment_hash {
+                    self.update_payment(&hex::encode(payment_hash.0), |payment| {
+                        payment.status = PaymentStatus::Failed;
+                    })
+                    .await?;
+                }
+                self.payment_results.notify(
+                    &payment_id,
+                    Err(Error::PaymentFailed(match reason {
+                        Some(reason) => format!("{:?}", reason),
+                        None => "unknown reason".to_string(),
+                    })),
+                );
+            }
+            Event::SpendableOutputs {
+                outputs,
+                channel_id,
+            } => {
+                // The sweeper persists the descriptors and spends them to a wallet address
+                // once they mature, rebroadcasting until the sweep confirms
+                tracing::info!(
+                    "Tracking {} spendable output(s) for sweeping",
+                    outputs.len()
+                );
+                if self
+                    .sweeper
+                    .track_spendable_outputs(outputs, channel_id, false, None)
+                    .is_err()
+                {
+                    tracing::error!("Failed to persist spendable outputs");
+                    return Err(ReplayEvent());
+                }
+            }
+            Event::PaymentPathSuccessful {
+                payment_id,
+                payment_hash,
+                path,
+            } => {
+                let fee_msat = path.fee_msat();
+                tracing::debug!(
+                    "Payment {} path of {} hop(s) succeeded, fee {} msat",
+                    payment_id,
+                    path.hops.len(),
+                    fee_msat
+                );
+
+                if let Some(payment_hash) = payment_hash {
+                    let path_id = path_id(&path);
+                    self.update_payment(&hex::encode(payment_hash.0), |payment| {
+                        // Replayed after a restart if the channel manager was not yet persisted
+                        if payment.path_ids.contains(&path_id) {
+                            return;
+                        }
+                        payment.path_ids.push(path_id);
+                        payment.path_fees_msat.push(fee_msat);
+                        payment.parts = Some(payment.path_fees_msat.len() as u32);
+                    })
+                    .await?;
+                }
+            }
+            Event::PaymentPathFailed {
+                payment_hash,
+                payment_failed_permanently,
+                short_channel_id,
+                ..
+            } => {
+                tracing::debug!(
+                    "Payment {} path failed at channel {:?}{}",
+                    hex::encode(payment_hash.0),
+                    short_channel_id,
+                    if payment_failed_permanently {
+                        ", not retrying"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            Event::PendingHTLCsForwardable { time_forwardable } => {
+                // LDK asks for a short random delay to batch forwards and obscure timing
+                let channel_manager = self.channel_manager.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(time_forwardable).await;
+                    channel_manager.process_pending_htlc_forwards();
+                });
+            }
+            Event::PaymentForwarded {
+                total_fee_earned_msat,
+                outbound_amount_forwarded_msat,
+                ..
+            } => {
+                tracing::info!(
+                    "Forwarded {} msat, earned {} msat",
+                    outbound_amount_forwarded_msat.unwrap_or(0),
+                    total_fee_earned_msat.unwrap_or(0)
+                );
+            }
+            Event::HTLCHandlingFailed {
+                prev_channel_id,
+                failed_next_destination,
+            } => {
+                tracing::warn!(
+                    "HTLC from channel {} failed: {:?}",
+                    prev_channel_id,
+                    failed_next_destination
+                );
+            }
+            Event::DiscardFunding { channel_id, .. } => {
+                tracing::info!("Discarding funding transaction of channel {}", channel_id);
+            }
+            Event::BumpTransaction(event) => {
+                // CPFP the commitment or HTLC transactions of a force-closed anchor channel
+                self.bump_handler.handle_event(&event);
+            }
+            other => {
+                tracing::debug!("Unhandled LDK event: {:?}", other);
+            }
+        }
         Ok(())
     }
+
+    /// Rewrite the static channel backup after a channel opened or `closed`
+    fn backup_channels(&self, closed: Option<bitcoin::OutPoint>) {
+        if let Err(e) = self.channel_backups.write(closed) {
+            tracing::error!("Failed to write channel backup: {}", e);
+        }
+    }
+
+    /// Apply `update` to a stored payment record, if there is one
+    async fn update_payment(
+        &self,
+        payment_hash: &str,
+        update: impl FnOnce(&mut Payment),
+    ) -> std::result::Result<(), ReplayEvent> {
+        let mut payment = match self.storage.get_payment(payment_hash).await {
+            Ok(Some(payment)) => payment,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                tracing::error!("Failed to load payment {}: {}", payment_hash, e);
+                return Err(ReplayEvent());
+            }
+        };
+
+        update(&mut payment);
+        if let Err(e) = self.storage.save_payment(&payment).await {
+            tracing::error!("Failed to save payment {}: {}", payment_hash, e);
+            return Err(ReplayEvent());
+        }
+        Ok(())
+    }
+
+    /// Record the new state of a channel, refreshing its balances from the channel manager
+    async fn update_channel(
+        &self,
+        channel_id: &ChannelId,
+        state: ChannelState,
+    ) -> std::result::Result<(), ReplayEvent> {
+        let id = channel_id.to_string();
+        let live = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .find(|c| c.channel_id == *channel_id);
+
+        let record = match (live, self.storage.get_channel(&id).await) {
+            (Some(details), _) => Some(channel_info(&details, state)),
+            (None, Ok(Some(mut stored))) => {
+                stored.state = state;
+                Some(stored)
+            }
+            (None, Ok(None)) => None,
+            (None, Err(e)) => {
+                tracing::error!("Failed to load channel {}: {}", id, e);
+                return Err(ReplayEvent());
+            }
+        };
+
+        if let Some(record) = record {
+            if let Err(e) = self.storage.save_channel(&record).await {
+                tracing::error!("Failed to save channel {}: {}", id, e);
+                return Err(ReplayEvent());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hex-encoded hash identifying `path` across restarts
+fn path_id(path: &Path) -> String {
+    let mut engine = sha256::Hash::engine();
+    for hop in &path.hops {
+        engine.input(&hop.encode());
+    }
+    if let Some(tail) = &path.blinded_tail {
+        engine.input(&tail.encode());
+    }
+    sha256::Hash::from_engine(engine).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+    use crate::node::LdkNode;
+    use crate::payments::InvoiceOptions;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::PublicKey;
+    use bitcoin::{ScriptBuf, Transaction, TxOut, Txid};
+    use lightning::chain::transaction::OutPoint;
+    use lightning::chain::ClaimId;
+    use lightning::events::bump_transaction::{AnchorDescriptor, BumpTransactionEvent};
+    use lightning::events::{
+        ClaimedHTLC, ClosureReason, PathFailure, PaymentFailureReason, PaymentPurpose,
+    };
+    use lightning::ln::chan_utils::{
+        ChannelPublicKeys, ChannelTransactionParameters, CounterpartyChannelTransactionParameters,
+    };
+    use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+    use lightning::ln::{PaymentHash, PaymentSecret};
+    use lightning::routing::router::{Path, RouteHop};
+    use lightning::sign::ChannelDerivationParameters;
+    use lightning::sign::SpendableOutputDescriptor;
+    use lightning::types::features::ChannelTypeFeatures;
+    use lightning_invoice::Bolt11Invoice;
+    use std::time::Duration;
+
+    fn peer_id(node: &LdkNode) -> PublicKey {
+        // Any valid key will do for events that only carry the counterparty ID
+        node.get_node_id()
+    }
+
+    fn channel_record(channel_id: &ChannelId, state: ChannelState) -> ChannelInfo {
+        ChannelInfo {
+            channel_id: channel_id.to_string(),
+            counterparty_node_id: "peer".to_string(),
+            capacity_sats: 100_000,
+            local_balance_msat: 90_000_000,
+            remote_balance_msat: 0,
+            state,
+            funding_txo: Some(format!("{}:0", Txid::all_zeros())),
+            closing_txid: None,
+            claimable_at_height: None,
+        }
+    }
+
+    fn pending_payment(payment_hash: &PaymentHash, direction: PaymentDirection) -> Payment {
+        Payment {
+            payment_hash: hex::encode(payment_hash.0),
+            amount_msat: 10_000,
+            direction,
+            status: PaymentStatus::Pending,
+            invoice: None,
+            created_at: chrono::Utc::now(),
+            settled_at: None,
+            preimage: None,
+            fee_paid_msat: None,
+            custom_tlvs: Vec::new(),
+            parts: None,
+            path_fees_msat: Vec::new(),
+            path_ids: Vec::new(),
+            offer_id: None,
+            claim_deadline: None,
+        }
+    }
+
+    fn empty_path() -> Path {
+        Path {
+            hops: vec![],
+            blinded_tail: None,
+        }
+    }
+
+    /// A two-hop path; the fee of the first hop is what the payer pays for routing
+    fn paid_path(node: &LdkNode, fee_msat: u64) -> Path {
+        let hop = |fee_msat| RouteHop {
+            pubkey: peer_id(node),
+            node_features: NodeFeatures::empty(),
+            short_channel_id: 1,
+            channel_features: ChannelFeatures::empty(),
+            fee_msat,
+            cltv_expiry_delta: 40,
+            maybe_announced_channel: true,
+        };
+        Path {
+            hops: vec![hop(fee_msat), hop(5_000)],
+            blinded_tail: None,
+        }
+    }
+
+    #[test]
+    fn test_inbound_channel_policy() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let key = |n: u8| {
+            let secret = bitcoin::secp256k1::SecretKey::from_slice(&[n; 32]).unwrap();
+            PublicKey::from_secret_key(&secp, &secret)
+        };
+        let (trusted, stranger, banned) = (key(1), key(2), key(3));
+
+        let mut policy = InboundChannelPolicy {
+            max_channel_size_sats: Some(1_000_000),
+            denylist: vec![banned],
+            ..Default::default()
+        };
+        let decide = |policy: &InboundChannelPolicy, node_id, sats, anchors, zero_conf| match policy
+            .evaluate(node_id, sats, anchors, zero_conf)
+        {
+            InboundChannelDecision::Reject(_) => None,
+            decision => Some(decision),
+        };
+
+        assert_eq!(
+            decide(&policy, &stranger, 100_000, false, false),
+            Some(InboundChannelDecision::Accept)
+        );
+        assert_eq!(decide(&policy, &banned, 100_000, false, false), None);
+        assert_eq!(decide(&policy, &stranger, 10_000, false, false), None);
+        assert_eq!(decide(&policy, &stranger, 2_000_000, false, false), None);
+        assert_eq!(decide(&policy, &stranger, 100_000, false, true), None);
+
+        policy.require_anchors = true;
+        assert_eq!(decide(&policy, &stranger, 100_000, false, false), None);
+        assert_eq!(
+            decide(&policy, &stranger, 100_000, true, false),
+            Some(InboundChannelDecision::Accept)
+        );
+
+        // An allowlist shuts out everyone else, and its peers may open zero-conf channels
+        policy.allowlist = vec![trusted];
+        policy.allow_zero_conf = true;
+        assert_eq!(decide(&policy, &stranger, 100_000, true, false), None);
+        assert_eq!(
+            decide(&policy, &trusted, 100_000, true, true),
+            Some(InboundChannelDecision::AcceptZeroConf)
+        );
+        assert_eq!(decide(&policy, &trusted, 10_000, true, true), None);
+
+        policy.allow_zero_conf = false;
+        assert_eq!(
+            decide(&policy, &trusted, 100_000, true, false),
+            Some(InboundChannelDecision::Accept)
+        );
+        assert_eq!(decide(&policy, &trusted, 100_000, true, true), None);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_channel_request_is_not_replayed() {
+        let (node, _temp) = test_node([25u8; 32]).await;
+        node.set_inbound_channel_policy(InboundChannelPolicy {
+            min_channel_size_sats: 50_000,
+            ..Default::default()
+        });
+
+        // The request is unknown to the channel manager, so the rejection itself fails; the
+        // event must still be consumed rather than replayed
+        let result = node
+            .event_handler
+            .handle_event(Event::OpenChannelRequest {
+                temporary_channel_id: ChannelId([7; 32]),
+                counterparty_node_id: peer_id(&node),
+                funding_satoshis: 10_000,
+                push_msat: 0,
+                channel_type: ChannelTypeFeatures::only_static_remote_key(),
+                is_announced: false,
+                params: lightning::ln::msgs::ChannelParameters {
+                    dust_limit_satoshis: 546,
+                    max_htlc_value_in_flight_msat: 10_000_000,
+                    htlc_minimum_msat: 1,
+                    commitment_feerate_sat_per_1000_weight: 253,
+                    to_self_delay: 144,
+                    max_accepted_htlcs: 30,
+                },
+            })
+            .await;
+        assert!(result.is_ok());
+        assert!(node.channel_manager.list_channels().is_empty());
+        assert!(node.storage.list_channels().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_funding_failure_notifies_opener() {
+        let (node, _temp) = test_node([21u8; 32]).await;
+        let opened = node.event_handler.channel_opens.register(1);
+
+        // The test wallet has no funds, so building the funding transaction fails
+        node.event_handler
+            .handle_event(Event::FundingGenerationReady {
+                temporary_channel_id: ChannelId([1; 32]),
+                counterparty_node_id: peer_id(&node),
+                channel_value_satoshis: 100_000,
+                output_script: ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros()),
+                user_channel_id: 1,
+            })
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            opened.await.unwrap(),
+            Err(Error::InsufficientFunds { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_channel_pending_notifies_opener() {
+        let (node, _temp) = test_node([22u8; 32]).await;
+        let opened = node.event_handler.channel_opens.register(2);
+        let channel_id = ChannelId([2; 32]);
+
+        node.event_handler
+            .handle_event(Event::ChannelPending {
+                channel_id,
+                user_channel_id: 2,
+                former_temporary_channel_id: None,
+                counterparty_node_id: peer_id(&node),
+                funding_txo: bitcoin::OutPoint::new(Txid::all_zeros(), 0),
+                channel_type: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(opened.await.unwrap().unwrap(), channel_id);
+    }
+
+    #[tokio::test]
+    async fn test_channel_ready_marks_channel_active() {
+        let (node, _temp) = test_node([23u8; 32]).await;
+        let channel_id = ChannelId([3; 32]);
+        node.storage
+            .save_channel(&channel_record(&channel_id, ChannelState::Opening))
+            .await
+            .unwrap();
+
+        node.event_handler
+            .handle_event(Event::ChannelReady {
+                channel_id,
+                user_channel_id: 3,
+                counterparty_node_id: peer_id(&node),
+                channel_type: ChannelTypeFeatures::only_static_remote_key(),
+            })
+            .await
+            .unwrap();
+
+        let stored = node
+            .storage
+            .get_channel(&channel_id.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.state, ChannelState::Active);
+    }
+
+    #[tokio::test]
+    async fn test_channel_closed_records_close() {
+        let (node, _temp) = test_node([24u8; 32]).await;
+        let channel_id = ChannelId([4; 32]);
+        let closed = node.event_handler.channel_closes.register(channel_id);
+
+        node.event_handler
+            .handle_event(Event::ChannelClosed {
+                channel_id,
+                user_channel_id: 4,
+                reason: ClosureReason::LocallyInitiatedCooperativeClosure,
+                counterparty_node_id: Some(peer_id(&node)),
+                channel_capacity_sats: Some(100_000),
+                channel_funding_txo: Some(OutPoint {
+                    txid: Txid::all_zeros(),
+                    index: 0,
+                }),
+            })
+            .await
+            .unwrap();
+
+        closed.await.unwrap();
+        let stored = node
+            .storage
+            .get_channel(&channel_id.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.state, ChannelState::Closed);
+        assert_eq!(stored.capacity_sats, 100_000);
+    }
+
+    #[tokio::test]
+    async fn test_hold_invoice_claimable_is_held() {
+        let (node, _temp) = test_node([25u8; 32]).await;
+        let payment_hash = PaymentHash([5; 32]);
+        node.storage
+            .save_payment(&pending_payment(&payment_hash, PaymentDirection::Inbound))
+            .await
+            .unwrap();
+
+        // Hold invoices are registered without a preimage, so LDK cannot claim them itself
+        for hash in [payment_hash, PaymentHash([6; 32])] {
+            node.event_handler
+                .handle_event(Event::PaymentClaimable {
+                    receiver_node_id: None,
+                    payment_hash: hash,
+                    onion_fields: None,
+                    amount_msat: 12_000,
+                    counterparty_skimmed_fee_msat: 0,
+                    purpose: PaymentPurpose::Bolt11InvoicePayment {
+                        payment_preimage: None,
+                        payment_secret: PaymentSecret([5; 32]),
+                    },
+                    via_channel_id: None,
+                    via_user_channel_id: None,
+                    claim_deadline: Some(500),
+                })
+                .await
+                .unwrap();
+        }
+
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.status, PaymentStatus::Held);
+        assert_eq!(payment.claim_deadline, Some(500));
+        assert_eq!(payment.amount_msat, 12_000);
+        assert!(payment.preimage.is_none());
+
+        // Without an open hold invoice the HTLCs are failed back, leaving no record
+        assert!(node
+            .storage
+            .get_payment(&hex::encode([6; 32]))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_keysend_not_opted_into_is_failed_back() {
+        let (node, _temp) = test_node([37u8; 32]).await;
+        let preimage = PaymentPreimage([7; 32]);
+        let payment_hash =
+            PaymentHash(bitcoin::hashes::sha256::Hash::hash(&preimage.0).to_byte_array());
+        node.channel_manager.get_and_clear_needs_persistence();
+
+        node.event_handler
+            .handle_event(Event::PaymentClaimable {
+                receiver_node_id: None,
+                payment_hash,
+                onion_fields: None,
+                amount_msat: 2_000,
+                counterparty_skimmed_fee_msat: 0,
+                purpose: PaymentPurpose::SpontaneousPayment(preimage),
+                via_channel_id: None,
+                via_user_channel_id: None,
+                claim_deadline: Some(500),
+            })
+            .await
+            .unwrap();
+
+        // The HTLCs were handed back to the channel manager to fail, and nothing was recorded
+        assert!(node.channel_manager.get_and_clear_needs_persistence());
+        assert!(node.storage.list_payments().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_payment_claimed_settles_invoice() {
+        let (node, _temp) = test_node([20u8; 32]).await;
+
+        let invoice = node
+            .create_invoice(None, "Tip".to_string(), &InvoiceOptions::default())
+            .await
+            .unwrap()
+            .parse::<Bolt11Invoice>()
+            .unwrap();
+        let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
+        let payment_secret = PaymentSecret(invoice.payment_secret().0);
+        let preimage = node
+            .channel_manager
+            .get_payment_preimage(payment_hash, payment_secret)
+            .unwrap();
+
+        node.event_handler
+            .handle_event(Event::PaymentClaimed {
+                receiver_node_id: None,
+                payment_hash,
+                amount_msat: 7_000,
+                purpose: PaymentPurpose::Bolt11InvoicePayment {
+                    payment_preimage: Some(preimage),
+                    payment_secret,
+                },
+                htlcs: [3_000, 4_000]
+                    .into_iter()
+                    .map(|value_msat| ClaimedHTLC {
+                        channel_id: ChannelId([1; 32]),
+                        user_channel_id: 1,
+                        cltv_expiry: 500,
+                        value_msat,
+                        counterparty_skimmed_fee_msat: 0,
+                    })
+                    .collect(),
+                sender_intended_total_msat: None,
+                onion_fields: None,
+            })
+            .await
+            .unwrap();
+
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.status, PaymentStatus::Succeeded);
+        assert_eq!(payment.amount_msat, 7_000);
+        assert_eq!(payment.parts, Some(2));
+        assert!(payment.settled_at.is_some());
+        assert_eq!(payment.preimage, Some(hex::encode(preimage.0)));
+    }
+
+    #[tokio::test]
+    async fn test_payment_sent_records_preimage_and_fee() {
+        let (node, _temp) = test_node([26u8; 32]).await;
+        let payment_hash = PaymentHash([6; 32]);
+        let payment_id = PaymentId(payment_hash.0);
+        node.storage
+            .save_payment(&pending_payment(&payment_hash, PaymentDirection::Outbound))
+            .await
+            .unwrap();
+        let result = node.event_handler.payment_results.register(payment_id);
+
+        node.event_handler
+            .handle_event(Event::PaymentSent {
+                payment_id: Some(payment_id),
+                payment_preimage: PaymentPreimage([7; 32]),
+                payment_hash,
+                fee_paid_msat: Some(15),
+            })
+            .await
+            .unwrap();
+
+        let (preimage, fee) = result.await.unwrap().unwrap();
+        assert_eq!(preimage, PaymentPreimage([7; 32]));
+        assert_eq!(fee, Some(15));
+
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.status, PaymentStatus::Succeeded);
+        assert_eq!(payment.fee_paid_msat, Some(15));
+        assert_eq!(payment.preimage, Some(hex::encode([7; 32])));
+    }
+
+    #[tokio::test]
+    async fn test_payment_failed_marks_payment_failed() {
+        let (node, _temp) = test_node([27u8; 32]).await;
+        let payment_hash = PaymentHash([8; 32]);
+        let payment_id = PaymentId(payment_hash.0);
+        node.storage
+            .save_payment(&pending_payment(&payment_hash, PaymentDirection::Outbound))
+            .await
+            .unwrap();
+        let result = node.event_handler.payment_results.register(payment_id);
+
+        node.event_handler
+            .handle_event(Event::PaymentFailed {
+                payment_id,
+                payment_hash: Some(payment_hash),
+                reason: Some(PaymentFailureReason::RetriesExhausted),
+            })
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            result.await.unwrap(),
+            Err(Error::PaymentFailed(_))
+        ));
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.status, PaymentStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_payment_path_events_update_payment() {
+        let (node, _temp) = test_node([28u8; 32]).await;
+        let payment_hash = PaymentHash([9; 32]);
+        node.storage
+            .save_payment(&pending_payment(&payment_hash, PaymentDirection::Outbound))
+            .await
+            .unwrap();
+
+        // Replayed after a restart, the same path is only counted once
+        for _ in 0..2 {
+            node.event_handler
+                .handle_event(Event::PaymentPathSuccessful {
+                    payment_id: PaymentId(payment_hash.0),
+                    payment_hash: Some(payment_hash),
+                    path: paid_path(&node, 21),
+                })
+                .await
+                .unwrap();
+        }
+        // A failed path is retried by LDK, so the payment itself stays pending
+        node.event_handler
+            .handle_event(Event::PaymentPathFailed {
+                payment_id: Some(PaymentId(payment_hash.0)),
+                payment_hash,
+                payment_failed_permanently: false,
+                failure: PathFailure::OnPath {
+                    network_update: None,
+                },
+                path: empty_path(),
+                short_channel_id: Some(42),
+            })
+            .await
+            .unwrap();
+
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.status, PaymentStatus::Pending);
+        assert_eq!(payment.path_fees_msat, vec![21]);
+        assert_eq!(payment.path_ids.len(), 1);
+        assert_eq!(payment.parts, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_path_successes_record_parts_and_fees() {
+        let (node, _temp) = test_node([36u8; 32]).await;
+        let payment_hash = PaymentHash([10; 32]);
+        node.storage
+            .save_payment(&pending_payment(&payment_hash, PaymentDirection::Outbound))
+            .await
+            .unwrap();
+
+        for fee_msat in [12, 30] {
+            node.event_handler
+                .handle_event(Event::PaymentPathSuccessful {
+                    payment_id: PaymentId(payment_hash.0),
+                    payment_hash: Some(payment_hash),
+                    path: paid_path(&node, fee_msat),
+                })
+                .await
+                .unwrap();
+        }
+
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.parts, Some(2));
+        assert_eq!(payment.path_fees_msat, vec![12, 30]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pending_htlcs_forwardable_processes_forwards() {
+        let (node, _temp) = test_node([29u8; 32]).await;
+        node.channel_manager.get_and_clear_needs_persistence();
+
+        node.event_handler
+            .handle_event(Event::PendingHTLCsForwardable {
+                time_forwardable: Duration::from_millis(50),
+            })
+            .await
+            .unwrap();
+
+        // Forwards are processed after the delay, which leaves the channel manager to persist
+        assert!(!node.channel_manager.get_and_clear_needs_persistence());
+        tokio::time::sleep(Duration::from_millis(49)).await;
+        assert!(!node.channel_manager.get_and_clear_needs_persistence());
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        assert!(node.channel_manager.get_and_clear_needs_persistence());
+    }
+
+    #[tokio::test]
+    async fn test_spendable_outputs_are_tracked() {
+        let (node, _temp) = test_node([30u8; 32]).await;
+
+        node.event_handler
+            .handle_event(Event::SpendableOutputs {
+                outputs: vec![SpendableOutputDescriptor::StaticOutput {
+                    outpoint: OutPoint {
+                        txid: Txid::all_zeros(),
+                        index: 1,
+                    },
+                    output: TxOut {
+                        value: bitcoin::Amount::from_sat(25_000),
+                        script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros()),
+                    },
+                    channel_keys_id: None,
+                }],
+                channel_id: Some(ChannelId([10; 32])),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(node.sweeper.tracked_spendable_outputs().len(), 1);
+        assert_eq!(
+            node.pending_sweep_balance(),
+            bitcoin::Amount::from_sat(25_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bump_without_funds_is_handled() {
+        let (node, _temp) = test_node([31u8; 32]).await;
+        let key = peer_id(&node);
+        let pubkeys = ChannelPublicKeys {
+            funding_pubkey: key,
+            revocation_basepoint: key.into(),
+            payment_point: key,
+            delayed_payment_basepoint: key.into(),
+            htlc_basepoint: key.into(),
+        };
+
+        let funding = bitcoin::OutPoint {
+            txid: Txid::all_zeros(),
+            vout: 0,
+        };
+        let anchor = bitcoin::OutPoint {
+            txid: Txid::from_byte_array([11; 32]),
+            vout: 0,
+        };
+
+        // The wallet has no UTXOs to pay for the anchor spend, so the bump fails. The event is
+        // still consumed rather than replayed, since LDK regenerates it on the next block
+        let result = node
+            .event_handler
+            .handle_event(Event::BumpTransaction(BumpTransactionEvent::ChannelClose {
+                channel_id: ChannelId([11; 32]),
+                counterparty_node_id: key,
+                claim_id: ClaimId([11; 32]),
+                package_target_feerate_sat_per_1000_weight: 2_500,
+                commitment_tx: Transaction {
+                    version: bitcoin::transaction::Version::TWO,
+                    lock_time: bitcoin::absolute::LockTime::ZERO,
+                    input: vec![bitcoin::TxIn {
+                        previous_output: funding,
+                        ..Default::default()
+                    }],
+                    output: vec![],
+                },
+                commitment_tx_fee_satoshis: 300,
+                anchor_descriptor: AnchorDescriptor {
+                    channel_derivation_parameters: ChannelDerivationParameters {
+                        value_satoshis: 100_000,
+                        keys_id: [11; 32],
+                        transaction_parameters: ChannelTransactionParameters {
+                            holder_pubkeys: pubkeys.clone(),
+                            holder_selected_contest_delay: 144,
+                            is_outbound_from_holder: true,
+                            counterparty_parameters: Some(
+                                CounterpartyChannelTransactionParameters {
+                                    pubkeys,
+                                    selected_contest_delay: 144,
+                                },
+                            ),
+                            funding_outpoint: Some(OutPoint {
+                                txid: Txid::all_zeros(),
+                                index: 0,
+                            }),
+                            channel_type_features:
+                                ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies(),
+                        },
+                    },
+                    outpoint: anchor,
+                },
+                pending_htlcs: vec![],
+            }))
+            .await;
+        assert!(result.is_ok());
+
+        // Neither the commitment nor an anchor spend went out
+        assert!(node.broadcaster.find_spending_tx(&funding).is_none());
+        assert!(node.broadcaster.find_spending_tx(&anchor).is_none());
+        assert!(node
+            .storage
+            .list_unconfirmed_broadcasts()
+            .await
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -0,0 +1,94 @@
+//! Fee estimation for LDK
+//!
+//! Maps each LDK [`ConfirmationTarget`] onto the on-chain wallet's cached fee estimates, so the
+//! Lightning node and on-chain sends work from the same numbers.
+
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ulw_bdk::fees::{FeeEstimates, FeeTarget, FEE_RATE_FLOOR};
+
+/// How often fee estimates are refreshed while the node is running
+pub(crate) const FEE_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// LDK fee estimator backed by the wallet's fee estimates
+pub struct WalletFeeEstimator {
+    estimates: Arc<FeeEstimates>,
+}
+
+impl WalletFeeEstimator {
+    pub fn new(estimates: Arc<FeeEstimates>) -> Self {
+        Self { estimates }
+    }
+
+    /// The underlying estimates, for refreshing
+    pub(crate) fn estimates(&self) -> Arc<FeeEstimates> {
+        self.estimates.clone()
+    }
+}
+
+/// Which estimate to use for each of LDK's confirmation targets
+fn fee_target(confirmation_target: ConfirmationTarget) -> FeeTarget {
+    match confirmation_target {
+        // Upper bound on what a peer may ask us to pay, and claims racing a timeout
+        ConfirmationTarget::MaximumFeeEstimate | ConfirmationTarget::UrgentOnChainSweep => {
+            FeeTarget::HighPriority
+        }
+        // Commitments without anchors cannot be bumped, so they must be able to confirm
+        ConfirmationTarget::NonAnchorChannelFee => FeeTarget::Normal,
+        ConfirmationTarget::OutputSpendingFee => FeeTarget::Economy,
+        ConfirmationTarget::AnchorChannelFee
+        | ConfirmationTarget::ChannelCloseMinimum
+        | ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee => FeeTarget::Background,
+        ConfirmationTarget::MinAllowedAnchorChannelRemoteFee => FeeTarget::Minimum,
+    }
+}
+
+impl FeeEstimator for WalletFeeEstimator {
+    fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+        let fee_rate = self
+            .estimates
+            .fee_rate(fee_target(confirmation_target))
+            .max(FEE_RATE_FLOOR);
+        fee_rate.to_sat_per_kwu().try_into().unwrap_or(u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::FeeRate;
+    use ulw_bdk::fees::FeeSource;
+    use ulw_core::Result;
+
+    /// Fee source charging 10 sat/kwu per block of urgency below a week
+    struct LinearFeeSource;
+
+    impl FeeSource for LinearFeeSource {
+        fn estimate(&self, blocks: usize) -> Result<FeeRate> {
+            Ok(FeeRate::from_sat_per_kwu(10 * (1008 - blocks as u64)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_confirmation_targets_follow_estimates() {
+        let estimates = Arc::new(FeeEstimates::new(Arc::new(LinearFeeSource)));
+        estimates.refresh().await.unwrap();
+        let estimator = WalletFeeEstimator::new(estimates);
+        let rate = |target| estimator.get_est_sat_per_1000_weight(target);
+
+        assert_eq!(rate(ConfirmationTarget::UrgentOnChainSweep), 10_070);
+        assert_eq!(rate(ConfirmationTarget::NonAnchorChannelFee), 10_020);
+        assert_eq!(rate(ConfirmationTarget::AnchorChannelFee), 8_640);
+        // The week-long estimate is zero, so the floor applies
+        assert_eq!(
+            rate(ConfirmationTarget::MinAllowedAnchorChannelRemoteFee),
+            253
+        );
+        assert!(
+            rate(ConfirmationTarget::MaximumFeeEstimate)
+                >= rate(ConfirmationTarget::OutputSpendingFee)
+        );
+    }
+}
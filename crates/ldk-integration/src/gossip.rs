@@ -0,0 +1,169 @@
+//! Network graph
+//!
+//! The graph is filled by P2P gossip from connected peers, or from Rapid Gossip Sync snapshots
+//! loaded from a file or URL. It is persisted alongside the channel manager, by the background
+//! processor while running and after every snapshot.
+
+use bitcoin::Network;
+use chrono::{DateTime, Utc};
+use lightning::io;
+use lightning::util::persist::{
+    KVStore, NETWORK_GRAPH_PERSISTENCE_KEY, NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+    NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lightning::util::ser::{ReadableArgs, Writeable};
+use lightning_persister::fs_store::FilesystemStore;
+use lightning_rapid_gossip_sync::RapidGossipSync;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ulw_core::{Error, Result};
+
+use crate::node::{LdkNode, NetworkGraph, SimpleLogger};
+
+/// Size and freshness of the network graph
+#[derive(Debug, Clone)]
+pub struct GraphStats {
+    pub nodes: usize,
+    pub channels: usize,
+    /// Timestamp of the last Rapid Gossip Sync snapshot applied
+    pub last_rapid_sync: Option<DateTime<Utc>>,
+}
+
+/// Restore the network graph from `persister`, or start with an empty one
+pub(crate) fn load_network_graph(
+    persister: &FilesystemStore,
+    network: Network,
+    logger: Arc<SimpleLogger>,
+) -> Result<NetworkGraph> {
+    match persister.read(
+        NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+        NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+        NETWORK_GRAPH_PERSISTENCE_KEY,
+    ) {
+        Ok(bytes) => NetworkGraph::read(&mut io::Cursor::new(bytes), logger)
+            .map_err(|e| Error::Storage(format!("Failed to read network graph: {:?}", e))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(NetworkGraph::new(network, logger)),
+        Err(e) => Err(Error::Storage(format!(
+            "Failed to read network graph: {}",
+            e
+        ))),
+    }
+}
+
+impl LdkNode {
+    /// Node and channel counts of the network graph
+    pub fn graph_stats(&self) -> GraphStats {
+        let graph = self.network_graph.read_only();
+        GraphStats {
+            nodes: graph.nodes().len(),
+            channels: graph.channels().len(),
+            last_rapid_sync: self
+                .network_graph
+                .get_last_rapid_gossip_sync_timestamp()
+                .and_then(|timestamp| DateTime::from_timestamp(timestamp as i64, 0)),
+        }
+    }
+
+    /// Apply a Rapid Gossip Sync snapshot to the network graph
+    ///
+    /// # Arguments
+    /// * `source` - Path of a snapshot file, or an `http(s)://` URL to download it from
+    ///
+    /// Returns the snapshot's timestamp, to request the next incremental snapshot from.
+    pub async fn sync_rapid_gossip(&self, source: &str) -> Result<u32> {
+        let snapshot = if source.starts_with("http://") || source.starts_with("https://") {
+            let response = reqwest::get(source)
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| Error::Network(format!("Failed to download snapshot: {}", e)))?;
+            response
+                .bytes()
+                .await
+                .map_err(|e| Error::Network(format!("Failed to download snapshot: {}", e)))?
+                .to_vec()
+        } else {
+            tokio::fs::read(source)
+                .await
+                .map_err(|e| Error::Storage(format!("Failed to read {}: {}", source, e)))?
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        self.apply_rapid_gossip_snapshot(&snapshot, now.as_secs())
+    }
+
+    /// Apply `snapshot` as of `now` (seconds since the epoch) and persist the graph
+    pub(crate) fn apply_rapid_gossip_snapshot(&self, snapshot: &[u8], now: u64) -> Result<u32> {
+        let rapid_sync = RapidGossipSync::new(self.network_graph.clone(), self.logger.clone());
+        let timestamp = rapid_sync
+            .update_network_graph_no_std(snapshot, Some(now))
+            .map_err(|e| Error::Network(format!("Invalid rapid gossip snapshot: {:?}", e)))?;
+
+        self.persister
+            .write(
+                NETWORK_GRAPH_PERSISTENCE_PRIMARY_NAMESPACE,
+                NETWORK_GRAPH_PERSISTENCE_SECONDARY_NAMESPACE,
+                NETWORK_GRAPH_PERSISTENCE_KEY,
+                &self.network_graph.encode(),
+            )
+            .map_err(|e| Error::Storage(format!("Failed to persist network graph: {}", e)))?;
+
+        let stats = self.graph_stats();
+        tracing::info!(
+            "Applied rapid gossip snapshot: {} nodes, {} channels",
+            stats.nodes,
+            stats.channels
+        );
+        Ok(timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+
+    const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/rgs_regtest.bin");
+
+    /// Timestamp the fixture snapshot was taken at
+    const SNAPSHOT_TIMESTAMP: u32 = 1_642_291_930;
+
+    #[tokio::test]
+    async fn test_rapid_gossip_snapshot_fills_graph() {
+        let (node, _temp) = test_node([60u8; 32]).await;
+        assert_eq!(node.graph_stats().channels, 0);
+
+        let snapshot = std::fs::read(SNAPSHOT_PATH).unwrap();
+        let timestamp = node
+            .apply_rapid_gossip_snapshot(&snapshot, SNAPSHOT_TIMESTAMP as u64)
+            .unwrap();
+        assert_eq!(timestamp, SNAPSHOT_TIMESTAMP);
+
+        let stats = node.graph_stats();
+        assert_eq!(stats.nodes, 4);
+        assert_eq!(stats.channels, 2);
+        assert_eq!(
+            stats.last_rapid_sync.map(|t| t.timestamp()),
+            Some(SNAPSHOT_TIMESTAMP as i64)
+        );
+
+        // The graph survives a restart
+        let restored =
+            load_network_graph(&node.persister, Network::Regtest, node.logger.clone()).unwrap();
+        assert_eq!(restored.read_only().channels().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stale_snapshot_is_rejected() {
+        let (node, _temp) = test_node([61u8; 32]).await;
+
+        let result = node.sync_rapid_gossip(SNAPSHOT_PATH).await;
+        assert!(matches!(result, Err(Error::Network(_))));
+        assert_eq!(node.graph_stats().channels, 0);
+
+        let result = node.sync_rapid_gossip("/nonexistent/snapshot.bin").await;
+        assert!(matches!(result, Err(Error::Storage(_))));
+    }
+}
@@ -0,0 +1,342 @@
+//! Hold invoices
+//!
+//! A hold invoice is created for a payment hash whose preimage the wallet does not know yet.
+//! Incoming HTLCs are held rather than claimed, until the payment is settled with the preimage
+//! or cancelled. Held payments still unresolved shortly before their claim deadline are
+//! cancelled, so the HTLCs are never left to time out on chain.
+
+use bitcoin::hashes::{sha256, Hash};
+use lightning::ln::channelmanager::FailureCode;
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use std::time::Duration;
+
+use ulw_core::traits::WalletStorage;
+use ulw_core::types::{Payment, PaymentDirection, PaymentStatus};
+use ulw_core::{Error, Result};
+
+use crate::node::{ChannelManager, LdkNode};
+use crate::payments::InvoiceOptions;
+
+/// Blocks before the claim deadline at which held payments are cancelled
+pub const HOLD_EXPIRY_SAFETY_MARGIN: u32 = 12;
+
+/// How often held payments are checked against their claim deadline
+pub(crate) const HOLD_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Parse a hex-encoded 32-byte payment hash or preimage
+fn parse_hex32(s: &str, what: &str) -> Result<[u8; 32]> {
+    hex::decode(s.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::InvalidInvoice(format!("Invalid {}: {}", what, s)))
+}
+
+/// Whether a held payment is too close to its claim deadline at `height` to keep holding
+fn hold_expiring(payment: &Payment, height: u32) -> bool {
+    payment.status == PaymentStatus::Held
+        && payment
+            .claim_deadline
+            .is_some_and(|deadline| height + HOLD_EXPIRY_SAFETY_MARGIN >= deadline)
+}
+
+/// Fail back the HTLCs of a held payment and record it as failed
+async fn cancel_held(
+    channel_manager: &ChannelManager,
+    storage: &dyn WalletStorage,
+    mut payment: Payment,
+) -> Result<()> {
+    let payment_hash = PaymentHash(parse_hex32(&payment.payment_hash, "payment hash")?);
+    channel_manager.fail_htlc_backwards_with_reason(
+        &payment_hash,
+        FailureCode::IncorrectOrUnknownPaymentDetails,
+    );
+    payment.status = PaymentStatus::Failed;
+    storage.save_payment(&payment).await
+}
+
+/// Cancel held payments about to reach their claim deadline
+///
+/// Returns the number of payments cancelled.
+pub(crate) async fn cancel_expiring_holds(
+    channel_manager: &ChannelManager,
+    storage: &dyn WalletStorage,
+) -> Result<usize> {
+    let height = channel_manager.current_best_block().height;
+    let mut cancelled = 0;
+    for payment in storage.list_payments().await? {
+        if hold_expiring(&payment, height) {
+            tracing::warn!(
+                "Cancelling held payment {} before its claim deadline",
+                payment.payment_hash
+            );
+            cancel_held(channel_manager, storage, payment).await?;
+            cancelled += 1;
+        }
+    }
+    Ok(cancelled)
+}
+
+impl LdkNode {
+    /// Create a BOLT11 hold invoice for `payment_hash`
+    ///
+    /// Incoming HTLCs are held until `settle_hold_invoice` or `cancel_hold_invoice` is called,
+    /// and are cancelled automatically shortly before their claim deadline.
+    ///
+    /// # Arguments
+    /// * `amount_msats` - Amount in millisatoshis (None for any-amount invoice)
+    /// * `payment_hash` - Hex-encoded SHA256 hash of the preimage that will settle the payment
+    /// * `description` - Invoice description
+    /// * `options` - Expiry, CLTV delta, route hints and fallback address
+    pub async fn create_hold_invoice(
+        &self,
+        amount_msats: Option<u64>,
+        payment_hash: &str,
+        description: String,
+        options: &InvoiceOptions,
+    ) -> Result<String> {
+        let payment_hash = PaymentHash(parse_hex32(payment_hash, "payment hash")?);
+        let hash = hex::encode(payment_hash.0);
+        if self.storage.get_payment(&hash).await?.is_some() {
+            return Err(Error::InvalidInvoice(format!(
+                "A payment with hash {} already exists",
+                hash
+            )));
+        }
+
+        options.validate()?;
+        let payment_secret = self
+            .channel_manager
+            .create_inbound_payment_for_hash(
+                payment_hash,
+                amount_msats,
+                options.expiry_secs,
+                Some(options.min_final_cltv_expiry_delta),
+            )
+            .map_err(|()| Error::InvalidInvoice("Invalid invoice amount or expiry".to_string()))?;
+        let invoice = self
+            .build_invoice(
+                amount_msats,
+                description,
+                payment_hash,
+                payment_secret,
+                options,
+            )
+            .await?;
+
+        self.storage
+            .save_payment(&Payment {
+                payment_hash: hash.clone(),
+                amount_msat: amount_msats.unwrap_or(0),
+                direction: PaymentDirection::Inbound,
+                status: PaymentStatus::Pending,
+                invoice: Some(invoice.to_string()),
+                created_at: chrono::Utc::now(),
+                settled_at: None,
+                preimage: None,
+                fee_paid_msat: None,
+                custom_tlvs: Vec::new(),
+                parts: None,
+                path_fees_msat: Vec::new(),
+                path_ids: Vec::new(),
+                offer_id: None,
+                claim_deadline: None,
+            })
+            .await?;
+
+        tracing::info!("Created hold invoice for payment {}", hash);
+        Ok(invoice.to_string())
+    }
+
+    /// Settle a held payment by claiming its HTLCs with `preimage`
+    ///
+    /// Returns the payment hash. The payment is recorded as succeeded once the claim completes.
+    pub async fn settle_hold_invoice(&self, preimage: &str) -> Result<String> {
+        let preimage = PaymentPreimage(parse_hex32(preimage, "preimage")?);
+        let hash = sha256::Hash::hash(&preimage.0).to_string();
+        let mut payment = self.held_payment(&hash).await?;
+
+        let height = self.channel_manager.current_best_block().height;
+        if hold_expiring(&payment, height) {
+            cancel_held(&self.channel_manager, &*self.storage, payment).await?;
+            return Err(Error::PaymentFailed(format!(
+                "Payment {} was too close to its claim deadline and has been cancelled",
+                hash
+            )));
+        }
+
+        self.channel_manager.claim_funds(preimage);
+        payment.preimage = Some(hex::encode(preimage.0));
+        self.storage.save_payment(&payment).await?;
+
+        tracing::info!("Settling held payment {}", hash);
+        Ok(hash)
+    }
+
+    /// Cancel a hold invoice, failing back any held HTLCs
+    ///
+    /// HTLCs arriving for the invoice later on are failed back as well.
+    pub async fn cancel_hold_invoice(&self, payment_hash: &str) -> Result<()> {
+        let hash = hex::encode(parse_hex32(payment_hash, "payment hash")?);
+        let payment = self
+            .storage
+            .get_payment(&hash)
+            .await?
+            .filter(|payment| {
+                // Invoices created with a preimage are claimed as soon as they are paid
+                payment.direction == PaymentDirection::Inbound
+                    && payment.preimage.is_none()
+                    && matches!(payment.status, PaymentStatus::Pending | PaymentStatus::Held)
+            })
+            .ok_or_else(|| Error::PaymentFailed(format!("No open hold invoice {}", hash)))?;
+
+        cancel_held(&self.channel_manager, &*self.storage, payment).await?;
+        tracing::info!("Cancelled hold invoice {}", hash);
+        Ok(())
+    }
+
+    /// The stored payment for `hash`, if its HTLCs are currently held
+    async fn held_payment(&self, hash: &str) -> Result<Payment> {
+        match self.storage.get_payment(hash).await? {
+            Some(payment) if payment.status == PaymentStatus::Held => Ok(payment),
+            Some(payment) if payment.status == PaymentStatus::Pending => Err(Error::PaymentFailed(
+                format!("No HTLCs have arrived for payment {} yet", hash),
+            )),
+            _ => Err(Error::PaymentFailed(format!("No held payment {}", hash))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+    use lightning::events::{Event, PaymentPurpose};
+    use lightning::ln::PaymentSecret;
+    use lightning_invoice::Bolt11Invoice;
+
+    /// Deliver a `PaymentClaimable` for a hold invoice, as LDK does once all HTLCs arrived
+    async fn receive_held(node: &LdkNode, invoice: &str, claim_deadline: u32) {
+        let invoice = invoice.parse::<Bolt11Invoice>().unwrap();
+        node.event_handler
+            .handle_event(Event::PaymentClaimable {
+                receiver_node_id: None,
+                payment_hash: PaymentHash(invoice.payment_hash().to_byte_array()),
+                onion_fields: None,
+                amount_msat: 25_000,
+                counterparty_skimmed_fee_msat: 0,
+                purpose: PaymentPurpose::Bolt11InvoicePayment {
+                    payment_preimage: None,
+                    payment_secret: PaymentSecret(invoice.payment_secret().0),
+                },
+                via_channel_id: None,
+                via_user_channel_id: None,
+                claim_deadline: Some(claim_deadline),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hold_invoice_lifecycle() {
+        let (node, _temp) = test_node([100u8; 32]).await;
+        let preimage = [5u8; 32];
+        let hash = sha256::Hash::hash(&preimage).to_string();
+        let height = node.channel_manager.current_best_block().height;
+
+        let invoice = node
+            .create_hold_invoice(
+                Some(25_000),
+                &hash,
+                "escrow".to_string(),
+                &InvoiceOptions::default(),
+            )
+            .await
+            .unwrap();
+        let result = node
+            .create_hold_invoice(None, &hash, "again".to_string(), &InvoiceOptions::default())
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInvoice(_))));
+
+        // Nothing to settle until the HTLCs arrive
+        let result = node.settle_hold_invoice(&hex::encode(preimage)).await;
+        assert!(matches!(result, Err(Error::PaymentFailed(_))));
+
+        receive_held(&node, &invoice, height + 100).await;
+        let payment = node.storage.get_payment(&hash).await.unwrap().unwrap();
+        assert_eq!(payment.status, PaymentStatus::Held);
+        assert_eq!(payment.claim_deadline, Some(height + 100));
+        assert!(payment.preimage.is_none());
+
+        assert_eq!(
+            node.settle_hold_invoice(&hex::encode(preimage))
+                .await
+                .unwrap(),
+            hash
+        );
+        let payment = node.storage.get_payment(&hash).await.unwrap().unwrap();
+        assert_eq!(payment.preimage, Some(hex::encode(preimage)));
+
+        // Once settled it can no longer be cancelled
+        let result = node.cancel_hold_invoice(&hash).await;
+        assert!(matches!(result, Err(Error::PaymentFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_and_expiring_holds_fail_back() {
+        let (node, _temp) = test_node([101u8; 32]).await;
+        let height = node.channel_manager.current_best_block().height;
+
+        let cancelled_hash = sha256::Hash::hash(&[1; 32]).to_string();
+        let cancelled = node
+            .create_hold_invoice(
+                None,
+                &cancelled_hash,
+                "cancelled".to_string(),
+                &InvoiceOptions::default(),
+            )
+            .await
+            .unwrap();
+        node.cancel_hold_invoice(&cancelled_hash).await.unwrap();
+        // HTLCs arriving after cancelling are failed back instead of held
+        receive_held(&node, &cancelled, height + 100).await;
+        let payment = node
+            .storage
+            .get_payment(&cancelled_hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.status, PaymentStatus::Failed);
+
+        let mut invoices = Vec::new();
+        for (i, deadline) in [height + HOLD_EXPIRY_SAFETY_MARGIN, height + 100]
+            .into_iter()
+            .enumerate()
+        {
+            let hash = sha256::Hash::hash(&[10 + i as u8; 32]).to_string();
+            let invoice = node
+                .create_hold_invoice(None, &hash, "held".to_string(), &InvoiceOptions::default())
+                .await
+                .unwrap();
+            receive_held(&node, &invoice, deadline).await;
+            invoices.push(hash);
+        }
+
+        let cancelled = cancel_expiring_holds(&node.channel_manager, &*node.storage)
+            .await
+            .unwrap();
+        assert_eq!(cancelled, 1);
+        let status = |payment: Option<Payment>| payment.unwrap().status;
+        assert_eq!(
+            status(node.storage.get_payment(&invoices[0]).await.unwrap()),
+            PaymentStatus::Failed
+        );
+        assert_eq!(
+            status(node.storage.get_payment(&invoices[1]).await.unwrap()),
+            PaymentStatus::Held
+        );
+
+        // A payment cancelled at its deadline can no longer be settled
+        let result = node.settle_hold_invoice(&hex::encode([10u8; 32])).await;
+        assert!(matches!(result, Err(Error::PaymentFailed(_))));
+    }
+}
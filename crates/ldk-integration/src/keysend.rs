@@ -0,0 +1,190 @@
+//! Keysend spontaneous payments
+//!
+//! Keysend pays a node ID directly, without an invoice: the sender picks the preimage and
+//! passes it to the recipient in the onion, optionally together with custom TLV records.
+//! Receiving keysend payments is opt-in; until enabled they are failed back.
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::channelmanager::{PaymentId, RecipientOnionFields, Retry};
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::routing::router::{PaymentParameters, RouteParameters};
+use lightning::sign::EntropySource;
+use std::sync::atomic::Ordering;
+
+use ulw_core::types::{Payment, PaymentDirection, PaymentStatus};
+use ulw_core::{Error, Result};
+
+use crate::node::LdkNode;
+use crate::payments::{SentPayment, PAYMENT_RETRY_TIMEOUT, PAYMENT_TIMEOUT};
+
+/// CLTV delta of the final hop, as LDK uses for its own invoices
+const KEYSEND_FINAL_CLTV_EXPIRY_DELTA: u32 = 144;
+
+impl LdkNode {
+    /// Claim incoming keysend payments instead of failing them back
+    pub fn set_accept_keysend(&self, accept: bool) {
+        self.event_handler
+            .accept_keysend
+            .store(accept, Ordering::Release);
+    }
+
+    /// Send a keysend payment to `node_id`
+    ///
+    /// A fresh preimage is generated for the payment. Waits until the payment succeeds or
+    /// fails and records the result in wallet storage.
+    ///
+    /// # Arguments
+    /// * `node_id` - Node to pay
+    /// * `amount_msat` - Amount to pay
+    /// * `custom_tlvs` - Extra `(type, value)` records for the recipient; types must be in the
+    ///   custom range, at or above 65536
+    pub async fn send_keysend(
+        &self,
+        node_id: PublicKey,
+        amount_msat: u64,
+        custom_tlvs: Vec<(u64, Vec<u8>)>,
+    ) -> Result<SentPayment> {
+        let recipient_onion = RecipientOnionFields::spontaneous_empty()
+            .with_custom_tlvs(custom_tlvs)
+            .map_err(|()| {
+                Error::PaymentFailed(
+                    "Custom TLV types must be unique and at least 65536".to_string(),
+                )
+            })?;
+
+        let preimage = PaymentPreimage(self.keys_manager.get_secure_random_bytes());
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage.0).to_byte_array());
+        let hash_hex = hex::encode(payment_hash.0);
+
+        let mut record = Payment {
+            payment_hash: hash_hex.clone(),
+            amount_msat,
+            direction: PaymentDirection::Outbound,
+            status: PaymentStatus::Pending,
+            invoice: None,
+            created_at: chrono::Utc::now(),
+            settled_at: None,
+            preimage: Some(hex::encode(preimage.0)),
+            fee_paid_msat: None,
+            custom_tlvs: recipient_onion.custom_tlvs().clone(),
+            parts: None,
+            path_fees_msat: Vec::new(),
+            path_ids: Vec::new(),
+            offer_id: None,
+            claim_deadline: None,
+        };
+        self.storage.save_payment(&record).await?;
+
+        let payment_id = PaymentId(payment_hash.0);
+        let result = self.event_handler.payment_results.register(payment_id);
+        let route_params = RouteParameters::from_payment_params_and_value(
+            PaymentParameters::for_keysend(node_id, KEYSEND_FINAL_CLTV_EXPIRY_DELTA, false),
+            amount_msat,
+        );
+
+        tracing::info!("Sending keysend of {} msat to {}", amount_msat, node_id);
+
+        if let Err(e) = self.channel_manager.send_spontaneous_payment_with_retry(
+            Some(preimage),
+            recipient_onion,
+            payment_id,
+            route_params,
+            Retry::Timeout(PAYMENT_RETRY_TIMEOUT),
+        ) {
+            record.status = PaymentStatus::Failed;
+            self.storage.save_payment(&record).await?;
+            return Err(Error::PaymentFailed(format!("{:?}", e)));
+        }
+
+        let (preimage, fee_paid_msat) = self
+            .wait_for(
+                result,
+                PAYMENT_TIMEOUT,
+                &format!("keysend {} to complete", hash_hex),
+            )
+            .await??;
+
+        Ok(SentPayment {
+            payment_hash,
+            preimage,
+            amount_msat,
+            fee_paid_msat,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+    use lightning::events::{Event, PaymentPurpose};
+
+    #[tokio::test]
+    async fn test_keysend_records_custom_tlvs() {
+        let (node, _temp) = test_node([80u8; 32]).await;
+        let (payee, _payee_temp) = test_node([81u8; 32]).await;
+
+        let result = node
+            .send_keysend(payee.get_node_id(), 5_000, vec![(1, vec![1])])
+            .await;
+        assert!(matches!(result, Err(Error::PaymentFailed(_))));
+        assert!(node.storage.list_payments().await.unwrap().is_empty());
+
+        // Without channels there is no route, but the attempt is kept with its records
+        let tlvs = vec![(70_000, b"order-42".to_vec()), (65_537, vec![0xff])];
+        let result = node.send_keysend(payee.get_node_id(), 5_000, tlvs).await;
+        assert!(matches!(result, Err(Error::PaymentFailed(_))));
+
+        let payments = node.storage.list_payments().await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].status, PaymentStatus::Failed);
+        assert!(payments[0].invoice.is_none());
+        assert_eq!(
+            payments[0].custom_tlvs,
+            vec![(65_537, vec![0xff]), (70_000, b"order-42".to_vec())]
+        );
+
+        // The stored preimage unlocks the payment hash
+        let preimage = hex::decode(payments[0].preimage.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            sha256::Hash::hash(&preimage).to_string(),
+            payments[0].payment_hash
+        );
+    }
+
+    #[tokio::test]
+    async fn test_received_keysend_stores_custom_tlvs() {
+        let (node, _temp) = test_node([82u8; 32]).await;
+        node.set_accept_keysend(true);
+        let preimage = PaymentPreimage([7; 32]);
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage.0).to_byte_array());
+        let onion_fields = RecipientOnionFields::spontaneous_empty()
+            .with_custom_tlvs(vec![(65_539, b"hello".to_vec())])
+            .unwrap();
+
+        node.event_handler
+            .handle_event(Event::PaymentClaimed {
+                receiver_node_id: None,
+                payment_hash,
+                amount_msat: 2_000,
+                purpose: PaymentPurpose::SpontaneousPayment(preimage),
+                htlcs: vec![],
+                sender_intended_total_msat: None,
+                onion_fields: Some(onion_fields),
+            })
+            .await
+            .unwrap();
+
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.direction, PaymentDirection::Inbound);
+        assert_eq!(payment.status, PaymentStatus::Succeeded);
+        assert_eq!(payment.custom_tlvs, vec![(65_539, b"hello".to_vec())]);
+        assert_eq!(payment.preimage, Some(hex::encode(preimage.0)));
+    }
+}
@@ -1,8 +1,22 @@
 //! LDK integration for Lightning Network functionality
 
+pub mod anchors;
+pub mod background;
+pub mod backup;
+pub mod broadcast;
+pub mod chain;
 pub mod channels;
 pub mod events;
+pub mod fees;
+pub mod gossip;
+pub mod hold;
+pub mod keysend;
+pub mod lnurl;
 pub mod node;
+pub mod offers;
 pub mod payments;
+pub mod scoring;
+pub mod sweep;
+pub mod watchtower;
 
 pub use node::LdkNode;
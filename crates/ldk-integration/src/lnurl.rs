@@ -0,0 +1,842 @@
+//! LNURL pay, withdraw and login
+//!
+//! LNURLs are bech32-encoded HTTPS URLs (LUD-01), `lnurlp://`/`lnurlw://`/`keyauth://` URLs
+//! (LUD-17) or Lightning Addresses like `alice@example.com` (LUD-16). The URL answers with a pay
+//! request (LUD-06), whose callback returns an invoice for the chosen amount with an optional
+//! comment (LUD-12), or a withdraw request (LUD-03), whose callback takes an invoice for the
+//! service to pay.
+//!
+//! Login URLs (LUD-04) carry their challenge in the query instead. The challenge is signed with
+//! a linking key derived from the seed for the service's domain (LUD-05), so each service sees a
+//! different key and the same service sees the same key on every login.
+
+use bitcoin::bech32;
+use bitcoin::bip32::{ChildNumber, Xpriv};
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::Network;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use ulw_core::types::{LnurlAuthRecord, PaymentConstraints};
+use ulw_core::{Error, Result};
+
+use crate::node::LdkNode;
+use crate::payments::{InvoiceOptions, SentPayment};
+
+/// How long to wait for an LNURL service to answer
+const LNURL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A pay request (LUD-06)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayRequest {
+    pub callback: String,
+    #[serde(rename = "minSendable")]
+    pub min_sendable_msat: u64,
+    #[serde(rename = "maxSendable")]
+    pub max_sendable_msat: u64,
+    /// JSON array of `[mime type, content]` entries describing the payment
+    pub metadata: String,
+    /// Longest comment the service accepts with a payment, 0 if it takes none (LUD-12)
+    #[serde(default)]
+    pub comment_allowed: u16,
+}
+
+impl PayRequest {
+    /// Plain text description from the metadata, if any
+    pub fn description(&self) -> Option<String> {
+        let entries: Vec<(String, serde_json::Value)> =
+            serde_json::from_str(&self.metadata).ok()?;
+        entries
+            .into_iter()
+            .find_map(|(mime, content)| match content {
+                serde_json::Value::String(text) if mime == "text/plain" => Some(text),
+                _ => None,
+            })
+    }
+}
+
+/// A withdraw request (LUD-03)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawRequest {
+    pub callback: String,
+    pub k1: String,
+    #[serde(default)]
+    pub default_description: String,
+    #[serde(rename = "minWithdrawable")]
+    pub min_withdrawable_msat: u64,
+    #[serde(rename = "maxWithdrawable")]
+    pub max_withdrawable_msat: u64,
+}
+
+/// What an LNURL offers
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "tag")]
+pub enum LnurlRequest {
+    #[serde(rename = "payRequest")]
+    Pay(PayRequest),
+    #[serde(rename = "withdrawRequest")]
+    Withdraw(WithdrawRequest),
+}
+
+/// A login challenge (LUD-04)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthRequest {
+    /// URL to call back with the signed challenge
+    pub url: Url,
+    /// Host the service is known by, which picks the linking key
+    pub domain: String,
+    /// Challenge to sign
+    pub k1: [u8; 32],
+    /// What the service asks for: `register`, `login`, `link` or `auth`
+    pub action: Option<String>,
+}
+
+/// Answer to a pay request callback
+#[derive(Debug, Deserialize)]
+struct PayResponse {
+    pr: String,
+}
+
+/// Error answer any LNURL endpoint may give instead
+#[derive(Debug, Deserialize)]
+struct StatusResponse {
+    status: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Whether `host` is reached over plain HTTP: onion services (LUD-16) and this machine
+fn is_plain_http_host(host: &str) -> bool {
+    host.ends_with(".onion")
+        || host == "localhost"
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Host part of a `host[:port]` URL authority
+fn authority_host(authority: &str) -> &str {
+    authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _)| host)
+}
+
+/// URL of a Lightning Address (LUD-16)
+fn lightning_address_url(address: &str) -> Option<Url> {
+    let (user, domain) = address.split_once('@')?;
+    let valid_user = !user.is_empty()
+        && user
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.+".contains(c));
+    if !valid_user || domain.is_empty() || domain.contains(['/', '@']) {
+        return None;
+    }
+
+    let scheme = if is_plain_http_host(authority_host(domain)) {
+        "http"
+    } else {
+        "https"
+    };
+    Url::parse(&format!(
+        "{}://{}/.well-known/lnurlp/{}",
+        scheme, domain, user
+    ))
+    .ok()
+}
+
+/// Resolve an LNURL, `lnurlp://`/`lnurlw://` URL or Lightning Address to the URL to query
+///
+/// A `lightning:` prefix is ignored. Plain HTTP is only accepted for onion and loopback hosts.
+pub fn parse_lnurl(input: &str) -> Result<Url> {
+    let input = input.trim();
+    let input = input
+        .strip_prefix("lightning:")
+        .or_else(|| input.strip_prefix("LIGHTNING:"))
+        .unwrap_or(input);
+    let invalid = || Error::InvalidInvoice(format!("Not an LNURL or Lightning Address: {}", input));
+
+    if input.to_lowercase().starts_with("lnurl1") {
+        let (hrp, data) = bech32::decode(input).map_err(|_| invalid())?;
+        if hrp.to_lowercase() != "lnurl" {
+            return Err(invalid());
+        }
+        let url = String::from_utf8(data).map_err(|_| invalid())?;
+        let url = Url::parse(&url).map_err(|_| invalid())?;
+        // LUD-01: plain HTTP only where the transport is already private
+        let secure = match url.scheme() {
+            "https" => true,
+            "http" => url.host_str().is_some_and(is_plain_http_host),
+            _ => false,
+        };
+        if !secure {
+            return Err(Error::InvalidInvoice(format!(
+                "LNURL must use HTTPS unless it is for an onion or local service: {}",
+                url
+            )));
+        }
+        return Ok(url);
+    }
+
+    for scheme in ["lnurlp", "lnurlw", "keyauth"] {
+        if let Some(rest) = input.strip_prefix(&format!("{}://", scheme)) {
+            let authority = rest.split(['/', '?']).next().unwrap_or_default();
+            let scheme = if is_plain_http_host(authority_host(authority)) {
+                "http"
+            } else {
+                "https"
+            };
+            return Url::parse(&format!("{}://{}", scheme, rest)).map_err(|_| invalid());
+        }
+    }
+
+    lightning_address_url(input).ok_or_else(invalid)
+}
+
+/// Whether `input` looks like something [`parse_lnurl`] resolves
+pub fn is_lnurl(input: &str) -> bool {
+    parse_lnurl(input).is_ok()
+}
+
+/// Whether `url` is a login request rather than one to fetch
+fn is_auth_url(url: &Url) -> bool {
+    url.query_pairs()
+        .any(|(key, value)| key == "tag" && value == "login")
+}
+
+/// Resolve an LNURL to the login request it carries
+pub fn parse_auth(input: &str) -> Result<AuthRequest> {
+    let url = parse_lnurl(input)?;
+    if !is_auth_url(&url) {
+        return Err(Error::InvalidInvoice(
+            "LNURL is not a login request".to_string(),
+        ));
+    }
+
+    let domain = url
+        .host_str()
+        .ok_or_else(|| Error::InvalidInvoice("LNURL login has no host".to_string()))?
+        .to_string();
+    let query = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    let k1 = query("k1")
+        .and_then(|k1| hex::decode(k1).ok())
+        .and_then(|k1| <[u8; 32]>::try_from(k1).ok())
+        .ok_or_else(|| Error::InvalidInvoice("LNURL login needs a 32-byte k1".to_string()))?;
+    let action = query("action");
+
+    Ok(AuthRequest {
+        url,
+        domain,
+        k1,
+        action,
+    })
+}
+
+/// Key m/138' that LNURL-auth linking keys are derived from
+pub(crate) fn auth_root(network: Network, seed: &[u8]) -> Result<Xpriv> {
+    Xpriv::new_master(network, seed)
+        .and_then(|master| {
+            master.derive_priv(&Secp256k1::new(), &[ChildNumber::Hardened { index: 138 }])
+        })
+        .map_err(|e| Error::Internal(format!("Failed to derive LNURL-auth key: {}", e)))
+}
+
+/// Path of the linking key for `domain` below m/138', chosen by the hashing key m/138'/0
+fn linking_key_path(hashing_key: &SecretKey, domain: &str) -> [ChildNumber; 4] {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&hashing_key.secret_bytes());
+    engine.input(domain.as_bytes());
+    let material = hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+    std::array::from_fn(|i| {
+        let index = u32::from_be_bytes(material[i * 4..i * 4 + 4].try_into().unwrap());
+        ChildNumber::from(index)
+    })
+}
+
+/// Linking key for `domain` (LUD-05)
+pub(crate) fn linking_key(root: &Xpriv, domain: &str) -> Result<SecretKey> {
+    let secp = Secp256k1::new();
+    let derive = |path: &[ChildNumber]| {
+        root.derive_priv(&secp, &path)
+            .map(|key| key.private_key)
+            .map_err(|e| Error::Internal(format!("Failed to derive linking key: {}", e)))
+    };
+    let hashing_key = derive(&[ChildNumber::Normal { index: 0 }])?;
+    derive(&linking_key_path(&hashing_key, domain))
+}
+
+/// GET `url` and decode the JSON answer, turning an LNURL error status into an error
+pub(crate) async fn get_json<T: DeserializeOwned>(url: Url) -> Result<T> {
+    let client = reqwest::Client::builder()
+        .timeout(LNURL_TIMEOUT)
+        .build()
+        .map_err(|e| Error::Network(e.to_string()))?;
+    let host = url.host_str().unwrap_or_default().to_string();
+    let body = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error::Network(format!("LNURL request to {} failed: {}", host, e)))?
+        .text()
+        .await
+        .map_err(|e| Error::Network(format!("LNURL request to {} failed: {}", host, e)))?;
+
+    if let Ok(status) = serde_json::from_str::<StatusResponse>(&body) {
+        if status.status.eq_ignore_ascii_case("ERROR") {
+            return Err(Error::PaymentFailed(format!(
+                "{} refused: {}",
+                host,
+                status.reason.unwrap_or_default()
+            )));
+        }
+    }
+    serde_json::from_str(&body)
+        .map_err(|e| Error::Network(format!("Invalid LNURL response from {}: {}", host, e)))
+}
+
+/// Fetch what an LNURL or Lightning Address offers
+pub async fn fetch_lnurl(input: &str) -> Result<LnurlRequest> {
+    let url = parse_lnurl(input)?;
+    if is_auth_url(&url) {
+        return Err(Error::InvalidInvoice(
+            "LNURL is a login request, not a pay or withdraw request".to_string(),
+        ));
+    }
+    get_json(url).await
+}
+
+/// Ask the service behind `request` for an invoice of `amount_msat`, and check it
+pub async fn request_invoice(
+    request: &PayRequest,
+    amount_msat: u64,
+    comment: Option<&str>,
+) -> Result<Bolt11Invoice> {
+    if amount_msat < request.min_sendable_msat || amount_msat > request.max_sendable_msat {
+        return Err(Error::InvalidInvoice(format!(
+            "Amount must be between {} and {} msat",
+            request.min_sendable_msat, request.max_sendable_msat
+        )));
+    }
+
+    let mut callback = Url::parse(&request.callback)
+        .map_err(|e| Error::InvalidInvoice(format!("Invalid LNURL callback: {}", e)))?;
+    callback
+        .query_pairs_mut()
+        .append_pair("amount", &amount_msat.to_string());
+    if let Some(comment) = comment.filter(|comment| !comment.is_empty()) {
+        let length = comment.chars().count();
+        if length > request.comment_allowed as usize {
+            return Err(Error::InvalidInvoice(format!(
+                "Comment of {} characters is over the limit of {}",
+                length, request.comment_allowed
+            )));
+        }
+        callback.query_pairs_mut().append_pair("comment", comment);
+    }
+
+    let response: PayResponse = get_json(callback).await?;
+    let invoice = response
+        .pr
+        .parse::<Bolt11Invoice>()
+        .map_err(|e| Error::InvalidInvoice(e.to_string()))?;
+
+    // The invoice must be for what we asked and commit to the metadata we were shown
+    if invoice.amount_milli_satoshis() != Some(amount_msat) {
+        return Err(Error::InvalidInvoice(format!(
+            "Service returned an invoice for {:?} msat instead of {} msat",
+            invoice.amount_milli_satoshis(),
+            amount_msat
+        )));
+    }
+    let metadata_hash = sha256::Hash::hash(request.metadata.as_bytes());
+    match invoice.description() {
+        Bolt11InvoiceDescription::Hash(hash) if hash.0 == metadata_hash => Ok(invoice),
+        _ => Err(Error::InvalidInvoice(
+            "Invoice does not commit to the LNURL metadata".to_string(),
+        )),
+    }
+}
+
+impl LdkNode {
+    /// Pay an LNURL pay request or Lightning Address
+    ///
+    /// `amount_msat` may be left out when the service only accepts one amount. The comment is
+    /// passed to the service if it accepts comments.
+    pub async fn pay_lnurl(
+        &self,
+        lnurl: &str,
+        amount_msat: Option<u64>,
+        comment: Option<&str>,
+        constraints: &PaymentConstraints,
+    ) -> Result<SentPayment> {
+        let LnurlRequest::Pay(request) = fetch_lnurl(lnurl).await? else {
+            return Err(Error::InvalidInvoice(
+                "LNURL is a withdraw request, not a pay request".to_string(),
+            ));
+        };
+        self.pay_lnurl_request(&request, amount_msat, comment, constraints)
+            .await
+    }
+
+    /// Pay a pay request already fetched with [`fetch_lnurl`], see [`LdkNode::pay_lnurl`]
+    pub async fn pay_lnurl_request(
+        &self,
+        request: &PayRequest,
+        amount_msat: Option<u64>,
+        comment: Option<&str>,
+        constraints: &PaymentConstraints,
+    ) -> Result<SentPayment> {
+        let amount_msat = match amount_msat {
+            Some(amount) => amount,
+            None if request.min_sendable_msat == request.max_sendable_msat => {
+                request.min_sendable_msat
+            }
+            None => {
+                return Err(Error::InvalidInvoice(format!(
+                    "Specify an amount between {} and {} msat",
+                    request.min_sendable_msat, request.max_sendable_msat
+                )))
+            }
+        };
+
+        let invoice = request_invoice(request, amount_msat, comment).await?;
+        self.pay_invoice(&invoice.to_string(), Some(amount_msat), constraints)
+            .await
+    }
+
+    /// Ask the service behind an LNURL withdraw request to pay us
+    ///
+    /// Creates an invoice for `amount_msat`, or the most the service allows, and hands it to the
+    /// service. Returns the invoice's payment hash once the service has accepted it; the payment
+    /// itself arrives shortly after.
+    pub async fn withdraw_lnurl(&self, lnurl: &str, amount_msat: Option<u64>) -> Result<String> {
+        let LnurlRequest::Withdraw(request) = fetch_lnurl(lnurl).await? else {
+            return Err(Error::InvalidInvoice(
+                "LNURL is a pay request, not a withdraw request".to_string(),
+            ));
+        };
+        self.withdraw_lnurl_request(&request, amount_msat).await
+    }
+
+    /// Withdraw from a request already fetched with [`fetch_lnurl`], see
+    /// [`LdkNode::withdraw_lnurl`]
+    pub async fn withdraw_lnurl_request(
+        &self,
+        request: &WithdrawRequest,
+        amount_msat: Option<u64>,
+    ) -> Result<String> {
+        let amount_msat = amount_msat.unwrap_or(request.max_withdrawable_msat);
+        if amount_msat < request.min_withdrawable_msat
+            || amount_msat > request.max_withdrawable_msat
+            || amount_msat == 0
+        {
+            return Err(Error::InvalidInvoice(format!(
+                "Amount must be between {} and {} msat",
+                request.min_withdrawable_msat.max(1),
+                request.max_withdrawable_msat
+            )));
+        }
+
+        let invoice = self
+            .create_invoice(
+                Some(amount_msat),
+                request.default_description.clone(),
+                &InvoiceOptions::default(),
+            )
+            .await?;
+        let payment_hash = invoice
+            .parse::<Bolt11Invoice>()
+            .map(|invoice| hex::encode(invoice.payment_hash().to_byte_array()))
+            .map_err(|e| Error::Internal(format!("Created an invalid invoice: {}", e)))?;
+
+        let mut callback = Url::parse(&request.callback)
+            .map_err(|e| Error::InvalidInvoice(format!("Invalid LNURL callback: {}", e)))?;
+        callback
+            .query_pairs_mut()
+            .append_pair("k1", &request.k1)
+            .append_pair("pr", &invoice);
+        let _: StatusResponse = get_json(callback).await?;
+
+        tracing::info!("LNURL withdraw of {} msat accepted", amount_msat);
+        Ok(payment_hash)
+    }
+
+    /// Log in to the service behind an LNURL-auth login request
+    pub async fn lnurl_auth(&self, lnurl: &str) -> Result<LnurlAuthRecord> {
+        self.lnurl_auth_request(&parse_auth(lnurl)?).await
+    }
+
+    /// Log in with a request already parsed with [`parse_auth`], see [`LdkNode::lnurl_auth`]
+    ///
+    /// Signs the challenge with the domain's linking key, hands both to the service and
+    /// remembers the domain once the service has accepted them.
+    pub async fn lnurl_auth_request(&self, request: &AuthRequest) -> Result<LnurlAuthRecord> {
+        let secp = Secp256k1::new();
+        let key = linking_key(&self.lnurl_auth_root, &request.domain)?;
+        let linking_key = PublicKey::from_secret_key(&secp, &key).to_string();
+        let signature = secp.sign_ecdsa(&Message::from_digest(request.k1), &key);
+
+        let mut callback = request.url.clone();
+        callback
+            .query_pairs_mut()
+            .append_pair("sig", &hex::encode(signature.serialize_der()))
+            .append_pair("key", &linking_key);
+        let _: StatusResponse = get_json(callback).await?;
+
+        let now = chrono::Utc::now();
+        let record = match self.storage.get_lnurl_auth(&request.domain).await? {
+            Some(previous) => LnurlAuthRecord {
+                linking_key,
+                last_login_at: now,
+                ..previous
+            },
+            None => LnurlAuthRecord {
+                domain: request.domain.clone(),
+                linking_key,
+                first_login_at: now,
+                last_login_at: now,
+            },
+        };
+        self.storage.save_lnurl_auth(&record).await?;
+
+        tracing::info!("Logged in to {} with LNURL-auth", request.domain);
+        Ok(record)
+    }
+
+    /// Services logged in to with LNURL-auth, most recent login first
+    pub async fn list_lnurl_auths(&self) -> Result<Vec<LnurlAuthRecord>> {
+        self.storage.list_lnurl_auths().await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+    use lightning::ln::PaymentSecret;
+    use lightning_invoice::{Currency, InvoiceBuilder};
+    use std::sync::{Arc, Mutex};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Requests seen by a [`mock_server`], as path and query
+    pub(crate) type Requests = Arc<Mutex<Vec<String>>>;
+
+    /// Local HTTP server answering each request with the JSON `respond` gives for its path
+    ///
+    /// Returns the server's base URL and the requests it has seen.
+    pub(crate) async fn mock_server<F>(respond: F) -> (String, Requests)
+    where
+        F: Fn(&Url) -> String + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+
+        let seen = requests.clone();
+        let server_base = base.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or("/").to_string();
+                let url = Url::parse(&format!("{}{}", server_base, path)).unwrap();
+                seen.lock().unwrap().push(path);
+
+                let body = respond(&url);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base, requests)
+    }
+
+    fn query(url: &Url, key: &str) -> Option<String> {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    /// Regtest invoice for `amount_msat` committing to `description_hash`
+    fn hashed_invoice(amount_msat: u64, description_hash: sha256::Hash) -> Bolt11Invoice {
+        let key = SecretKey::from_slice(&[7u8; 32]).unwrap();
+        InvoiceBuilder::new(Currency::Regtest)
+            .description_hash(description_hash)
+            .payment_hash(sha256::Hash::hash(&[1u8; 32]))
+            .payment_secret(PaymentSecret([2u8; 32]))
+            .duration_since_epoch(SystemTime::now().duration_since(UNIX_EPOCH).unwrap())
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(amount_msat)
+            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &key))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_lnurl() {
+        let url = "https://service.com/api?q=3fc3645b439ce8e7";
+        let lnurl =
+            bech32::encode::<bech32::Bech32>(bech32::Hrp::parse("lnurl").unwrap(), url.as_bytes())
+                .unwrap();
+        assert_eq!(parse_lnurl(&lnurl).unwrap().as_str(), url);
+        assert_eq!(
+            parse_lnurl(&format!("lightning:{}", lnurl.to_uppercase()))
+                .unwrap()
+                .as_str(),
+            url
+        );
+
+        assert_eq!(
+            parse_lnurl("alice@example.com").unwrap().as_str(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        assert_eq!(
+            parse_lnurl("bob@127.0.0.1:8080").unwrap().as_str(),
+            "http://127.0.0.1:8080/.well-known/lnurlp/bob"
+        );
+        assert_eq!(
+            parse_lnurl("lnurlw://example.onion/withdraw?k1=ab")
+                .unwrap()
+                .as_str(),
+            "http://example.onion/withdraw?k1=ab"
+        );
+
+        // Plain HTTP is only allowed for onion and local services
+        let encode = |url: &str| {
+            bech32::encode::<bech32::Bech32>(bech32::Hrp::parse("lnurl").unwrap(), url.as_bytes())
+                .unwrap()
+        };
+        assert!(matches!(
+            parse_lnurl(&encode("http://service.com/api?tag=login&k1=00")),
+            Err(Error::InvalidInvoice(_))
+        ));
+        assert!(!is_lnurl(&encode("ftp://service.com/api")));
+        assert!(is_lnurl(&encode("http://example.onion/api")));
+        assert!(is_lnurl(&encode("http://[::1]:8080/api")));
+
+        assert!(!is_lnurl("Alice@example.com"));
+        assert!(!is_lnurl("lnbcrt1pjunk"));
+        assert!(!is_lnurl(&lnurl[..lnurl.len() - 1]));
+    }
+
+    #[tokio::test]
+    async fn test_lightning_address_pay_request() {
+        let metadata = r#"[["text/plain","Coffee"],["text/identifier","alice@127.0.0.1"]]"#;
+        let (base, requests) = mock_server(move |url| match url.path() {
+            "/.well-known/lnurlp/alice" => serde_json::json!({
+                "tag": "payRequest",
+                "callback": format!("{}/pay?user=alice", url.origin().ascii_serialization()),
+                "minSendable": 1_000,
+                "maxSendable": 50_000_000,
+                "metadata": metadata,
+                "commentAllowed": 12,
+            })
+            .to_string(),
+            "/pay" => {
+                let amount: u64 = query(url, "amount").unwrap().parse().unwrap();
+                // Overcharges for amounts over 20 sats and ignores the metadata for 10 sats
+                let invoice = match amount {
+                    10_000 => hashed_invoice(amount, sha256::Hash::hash(b"other")),
+                    a if a > 20_000 => {
+                        hashed_invoice(a + 1, sha256::Hash::hash(metadata.as_bytes()))
+                    }
+                    a => hashed_invoice(a, sha256::Hash::hash(metadata.as_bytes())),
+                };
+                serde_json::json!({ "pr": invoice.to_string(), "routes": [] }).to_string()
+            }
+            _ => r#"{"status":"ERROR","reason":"not found"}"#.to_string(),
+        })
+        .await;
+        let address = format!("alice@{}", base.trim_start_matches("http://"));
+
+        let LnurlRequest::Pay(request) = fetch_lnurl(&address).await.unwrap() else {
+            panic!("Expected a pay request");
+        };
+        assert_eq!(request.description().as_deref(), Some("Coffee"));
+        assert_eq!(request.comment_allowed, 12);
+
+        let invoice = request_invoice(&request, 5_000, Some("Thanks!"))
+            .await
+            .unwrap();
+        assert_eq!(invoice.amount_milli_satoshis(), Some(5_000));
+        let callback = Url::parse(&format!("{}{}", base, requests.lock().unwrap()[1])).unwrap();
+        assert_eq!(query(&callback, "user").as_deref(), Some("alice"));
+        assert_eq!(query(&callback, "comment").as_deref(), Some("Thanks!"));
+
+        assert!(request_invoice(&request, 500, None).await.is_err());
+        assert!(
+            request_invoice(&request, 5_000, Some("Far too long a comment"))
+                .await
+                .is_err()
+        );
+        assert!(request_invoice(&request, 10_000, None).await.is_err());
+        assert!(request_invoice(&request, 30_000, None).await.is_err());
+
+        let missing = format!("bob@{}", base.trim_start_matches("http://"));
+        match fetch_lnurl(&missing).await {
+            Err(Error::PaymentFailed(reason)) => assert!(reason.contains("not found")),
+            other => panic!("Expected the service's error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pay_lnurl_needs_amount_in_range() {
+        let (node, _temp) = test_node([140u8; 32]).await;
+        let (base, _requests) = mock_server(|url| {
+            serde_json::json!({
+                "tag": "payRequest",
+                "callback": format!("{}/pay", url.origin().ascii_serialization()),
+                "minSendable": 1_000,
+                "maxSendable": 2_000,
+                "metadata": "[]",
+            })
+            .to_string()
+        })
+        .await;
+        let lnurl = bech32::encode::<bech32::Bech32>(
+            bech32::Hrp::parse("lnurl").unwrap(),
+            format!("{}/lnurlp", base).as_bytes(),
+        )
+        .unwrap();
+
+        let constraints = PaymentConstraints::default();
+        assert!(matches!(
+            node.pay_lnurl(&lnurl, None, None, &constraints).await,
+            Err(Error::InvalidInvoice(_))
+        ));
+        assert!(node.withdraw_lnurl(&lnurl, Some(1_000)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_lnurl_hands_over_invoice() {
+        let (node, _temp) = test_node([141u8; 32]).await;
+        let (base, requests) = mock_server(|url| match url.path() {
+            "/withdraw" => serde_json::json!({
+                "tag": "withdrawRequest",
+                "callback": format!("{}/withdraw/cb", url.origin().ascii_serialization()),
+                "k1": "f00d",
+                "defaultDescription": "Faucet",
+                "minWithdrawable": 1_000,
+                "maxWithdrawable": 21_000,
+            })
+            .to_string(),
+            _ if query(url, "k1").as_deref() == Some("f00d") => r#"{"status":"OK"}"#.to_string(),
+            _ => r#"{"status":"ERROR","reason":"bad k1"}"#.to_string(),
+        })
+        .await;
+        let lnurl = format!("lnurlw://{}/withdraw", base.trim_start_matches("http://"));
+
+        let payment_hash = node.withdraw_lnurl(&lnurl, None).await.unwrap();
+
+        let callback = Url::parse(&format!("{}{}", base, requests.lock().unwrap()[1])).unwrap();
+        let invoice: Bolt11Invoice = query(&callback, "pr").unwrap().parse().unwrap();
+        assert_eq!(invoice.amount_milli_satoshis(), Some(21_000));
+        assert_eq!(
+            hex::encode(invoice.payment_hash().to_byte_array()),
+            payment_hash
+        );
+        assert_eq!(invoice.recover_payee_pub_key(), node.get_node_id());
+
+        assert!(node.withdraw_lnurl(&lnurl, Some(50_000)).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_auth() {
+        let k1 = "e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e";
+        let request = parse_auth(&format!(
+            "keyauth://site.com/login?tag=login&k1={}&action=register",
+            k1
+        ))
+        .unwrap();
+        assert_eq!(request.domain, "site.com");
+        assert_eq!(hex::encode(request.k1), k1);
+        assert_eq!(request.action.as_deref(), Some("register"));
+        assert_eq!(request.url.scheme(), "https");
+
+        assert!(parse_auth("keyauth://site.com/login?tag=login&k1=f00d").is_err());
+        assert!(parse_auth(&format!("lnurlw://site.com/w?k1={}", k1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lnurl_auth_signs_with_domain_key() {
+        let (node, _temp) = test_node([142u8; 32]).await;
+        let k1 = [9u8; 32];
+        let (base, requests) = mock_server(move |url| {
+            let secp = Secp256k1::new();
+            let verified = query(url, "key")
+                .and_then(|key| key.parse::<PublicKey>().ok())
+                .zip(query(url, "sig").and_then(|sig| hex::decode(sig).ok()))
+                .and_then(|(key, sig)| {
+                    let sig = bitcoin::secp256k1::ecdsa::Signature::from_der(&sig).ok()?;
+                    secp.verify_ecdsa(&Message::from_digest(k1), &sig, &key)
+                        .ok()
+                });
+            match verified {
+                Some(()) => r#"{"status":"OK"}"#.to_string(),
+                None => r#"{"status":"ERROR","reason":"bad signature"}"#.to_string(),
+            }
+        })
+        .await;
+        let lnurl = format!(
+            "keyauth://{}/auth?tag=login&k1={}",
+            base.trim_start_matches("http://"),
+            hex::encode(k1)
+        );
+
+        let first = node.lnurl_auth(&lnurl).await.unwrap();
+        assert_eq!(first.domain, "127.0.0.1");
+        let second = node.lnurl_auth(&lnurl).await.unwrap();
+        assert_eq!(second.linking_key, first.linking_key);
+        assert_eq!(second.first_login_at, first.first_login_at);
+        assert_eq!(node.list_lnurl_auths().await.unwrap().len(), 1);
+
+        let callback = Url::parse(&format!("{}{}", base, requests.lock().unwrap()[0])).unwrap();
+        assert_eq!(query(&callback, "key"), Some(first.linking_key.clone()));
+
+        // Other services and other seeds see other keys
+        let other_domain = linking_key(&node.lnurl_auth_root, "site.com").unwrap();
+        let other_seed = linking_key(
+            &auth_root(Network::Regtest, &[143u8; 32]).unwrap(),
+            "127.0.0.1",
+        )
+        .unwrap();
+        for key in [other_domain, other_seed] {
+            assert_ne!(
+                PublicKey::from_secret_key(&Secp256k1::new(), &key).to_string(),
+                first.linking_key
+            );
+        }
+
+        let bad_k1 = lnurl.replace(&hex::encode(k1), &hex::encode([8u8; 32]));
+        assert!(matches!(
+            node.lnurl_auth(&bad_k1).await,
+            Err(Error::PaymentFailed(_))
+        ));
+    }
+}
@@ -3,22 +3,90 @@
 //! This module implements a complete Lightning Network node using LDK (Lightning Dev Kit).
 //! It handles channel management, payments, peer connections, and event processing.
 
-use bitcoin::secp256k1::{PublicKey, Secp256k1};
-use bitcoin::{hashes::Hash as BitcoinHash, Network, Transaction};
-use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
-use lightning::ln::{PaymentHash, PaymentSecret};
-use lightning::sign::{EntropySource, KeysManager, NodeSigner};
+use bitcoin::bip32::Xpriv;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{BlockHash, Network};
+use lightning::chain::chainmonitor;
+use lightning::chain::{BestBlock, Confirm, Watch};
+use lightning::io;
+use lightning::ln::channelmanager::{ChainParameters, ChannelManagerReadArgs};
+use lightning::ln::peer_handler::{IgnoringMessageHandler, MessageHandler};
+use lightning::onion_message::messenger::{DefaultMessageRouter, SimpleArcOnionMessenger};
+use lightning::routing::gossip::{self, P2PGossipSync};
+use lightning::routing::router::DefaultRouter;
+use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringFeeParameters};
+use lightning::routing::utxo::UtxoLookup;
+use lightning::sign::{EntropySource, InMemorySigner, KeysManager, NodeSigner};
+use lightning::util::config::UserConfig;
 use lightning::util::logger::{Logger, Record};
-use lightning_invoice::{Bolt11Invoice, Currency};
-use std::collections::HashMap;
+use lightning::util::persist::{
+    read_channel_monitors, KVStore, CHANNEL_MANAGER_PERSISTENCE_KEY,
+    CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE, CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lightning::util::ser::{ReadableArgs, Writeable};
+use lightning_net_tokio::SocketDescriptor;
+use lightning_persister::fs_store::FilesystemStore;
 use std::fs;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::sync::RwLock;
+use tokio::sync::oneshot::{self, error::TryRecvError};
 
+use ulw_bdk::BdkWallet;
+use ulw_core::traits::WalletStorage;
+use ulw_core::types::Balance;
 use ulw_core::{Error, Result};
 
+use crate::anchors;
+use crate::background::BackgroundTask;
+use crate::backup::{ChannelBackups, ChannelRecovery};
+use crate::broadcast::Broadcaster;
+use crate::chain::{self, ChainSource, ElectrumChainSource};
+use crate::events::EventHandler;
+use crate::fees::WalletFeeEstimator;
+use crate::gossip::load_network_graph;
+use crate::lnurl;
+use crate::payments::MppConfig;
+use crate::scoring::{load_scorer, persist_scorer, ProberConfig};
+use crate::sweep::{self, Sweeper};
+use crate::watchtower::WatchtowerPersister;
+
+pub(crate) type ChainMonitor = chainmonitor::ChainMonitor<
+    InMemorySigner,
+    Arc<dyn ChainSource>,
+    Arc<Broadcaster>,
+    Arc<WalletFeeEstimator>,
+    Arc<SimpleLogger>,
+    Arc<WatchtowerPersister>,
+>;
+
+pub(crate) type ChannelManager = lightning::ln::channelmanager::SimpleArcChannelManager<
+    ChainMonitor,
+    Broadcaster,
+    WalletFeeEstimator,
+    SimpleLogger,
+>;
+
+pub(crate) type NetworkGraph = gossip::NetworkGraph<Arc<SimpleLogger>>;
+
+pub(crate) type Scorer = ProbabilisticScorer<Arc<NetworkGraph>, Arc<SimpleLogger>>;
+
+pub(crate) type OnionMessenger =
+    SimpleArcOnionMessenger<ChainMonitor, Broadcaster, WalletFeeEstimator, SimpleLogger>;
+
+pub(crate) type GossipSync =
+    P2PGossipSync<Arc<NetworkGraph>, Arc<dyn UtxoLookup + Send + Sync>, Arc<SimpleLogger>>;
+
+pub(crate) type PeerManager = lightning::ln::peer_handler::SimpleArcPeerManager<
+    SocketDescriptor,
+    ChainMonitor,
+    Broadcaster,
+    WalletFeeEstimator,
+    Arc<dyn UtxoLookup + Send + Sync>,
+    SimpleLogger,
+>;
+
 /// Simple logger implementation for LDK
 pub struct SimpleLogger;
 
@@ -36,34 +104,6 @@ impl Logger for SimpleLogger {
     }
 }
 
-/// Simple fee estimator implementation
-pub struct SimpleFeeEstimator;
-
-impl FeeEstimator for SimpleFeeEstimator {
-    fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
-        match confirmation_target {
-            ConfirmationTarget::MinAllowedAnchorChannelRemoteFee => 500,
-            ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee => 253,
-            ConfirmationTarget::AnchorChannelFee => 500,
-            ConfirmationTarget::NonAnchorChannelFee => 2000,
-            ConfirmationTarget::ChannelCloseMinimum => 500,
-            _ => 1000,
-        }
-    }
-}
-
-/// Simple transaction broadcaster
-pub struct SimpleBroadcaster;
-
-impl BroadcasterInterface for SimpleBroadcaster {
-    fn broadcast_transactions(&self, txs: &[&Transaction]) {
-        for tx in txs {
-            tracing::info!("Broadcasting transaction: {}", tx.compute_txid());
-            // In production, broadcast to Bitcoin network via BDK or Electrum
-        }
-    }
-}
-
 /// Channel details returned to user
 #[derive(Debug, Clone)]
 pub struct ChannelDetails {
@@ -75,43 +115,81 @@ pub struct ChannelDetails {
     pub is_public: bool,
 }
 
-/// Payment information
-#[derive(Debug, Clone)]
-pub struct PaymentInfo {
-    pub payment_hash: [u8; 32],
-    pub amount_msat: Option<u64>,
-    pub status: PaymentStatus,
-}
-
-#[derive(Debug, Clone)]
-pub enum PaymentStatus {
-    Pending,
-    Succeeded,
-    Failed,
-}
-
 /// Main Lightning Network node
 pub struct LdkNode {
-    keys_manager: Arc<KeysManager>,
-    network: Network,
+    pub(crate) keys_manager: Arc<KeysManager>,
+    pub(crate) network: Network,
     _storage_path: PathBuf,
-    _logger: Arc<SimpleLogger>,
-    _fee_estimator: Arc<SimpleFeeEstimator>,
-    _broadcaster: Arc<SimpleBroadcaster>,
-    payments: Arc<RwLock<HashMap<PaymentHash, PaymentInfo>>>,
+    pub(crate) logger: Arc<SimpleLogger>,
+    pub(crate) fee_estimator: Arc<WalletFeeEstimator>,
+    pub(crate) broadcaster: Arc<Broadcaster>,
+    pub(crate) persister: Arc<FilesystemStore>,
+    pub(crate) watchtower: Arc<WatchtowerPersister>,
+    pub(crate) chain_source: Arc<dyn ChainSource>,
+    pub(crate) chain_monitor: Arc<ChainMonitor>,
+    pub(crate) channel_manager: Arc<ChannelManager>,
+    pub(crate) network_graph: Arc<NetworkGraph>,
+    pub(crate) scorer: Arc<std::sync::RwLock<Scorer>>,
+    pub(crate) onion_messenger: Arc<OnionMessenger>,
+    pub(crate) gossip_sync: Arc<GossipSync>,
+    pub(crate) peer_manager: Arc<PeerManager>,
+    pub(crate) sweeper: Arc<Sweeper>,
+    pub(crate) channel_backups: Arc<ChannelBackups>,
+    pub(crate) channel_recovery: Arc<ChannelRecovery>,
+    pub(crate) event_handler: Arc<EventHandler>,
+    pub(crate) wallet: Arc<BdkWallet>,
+    pub(crate) storage: Arc<dyn WalletStorage>,
+    /// LNURL-auth key m/138' that per-domain linking keys are derived from
+    pub(crate) lnurl_auth_root: Xpriv,
+    pub(crate) prober: Mutex<Option<ProberConfig>>,
+    pub(crate) mpp_config: Mutex<MppConfig>,
+    pub(crate) background: Mutex<Option<BackgroundTask>>,
 }
 
 impl LdkNode {
     /// Create a new Lightning node
     ///
+    /// Channel monitors and the channel manager are restored from `storage_path` if a
+    /// previous run left them there, otherwise a fresh node is started.
+    ///
     /// # Arguments
     /// * `network` - Bitcoin network (testnet/regtest/mainnet)
     /// * `storage_path` - Path to store channel and node data
     /// * `entropy_seed` - 32 bytes of entropy for key derivation
+    /// * `wallet` - On-chain wallet used to fund channels
+    /// * `storage` - Wallet storage for channel and payment records
+    ///
+    /// The chain is synced from the wallet's Electrum server.
     pub async fn new(
         network: Network,
         storage_path: PathBuf,
         entropy_seed: [u8; 32],
+        wallet: Arc<BdkWallet>,
+        storage: Arc<dyn WalletStorage>,
+    ) -> Result<Self> {
+        let chain_source = Arc::new(ElectrumChainSource::new(
+            wallet.electrum_url().to_string(),
+            Arc::new(SimpleLogger),
+        ));
+        Self::with_chain_source(
+            network,
+            storage_path,
+            entropy_seed,
+            wallet,
+            storage,
+            chain_source,
+        )
+        .await
+    }
+
+    /// Create a new Lightning node that syncs the chain from `chain_source`
+    pub(crate) async fn with_chain_source(
+        network: Network,
+        storage_path: PathBuf,
+        entropy_seed: [u8; 32],
+        wallet: Arc<BdkWallet>,
+        storage: Arc<dyn WalletStorage>,
+        chain_source: Arc<dyn ChainSource>,
     ) -> Result<Self> {
         // Create storage directory
         fs::create_dir_all(&storage_path)
@@ -128,9 +206,180 @@ impl LdkNode {
             cur_time.subsec_nanos(),
         ));
 
+        let lnurl_auth_root = lnurl::auth_root(network, &entropy_seed)?;
+
         let logger = Arc::new(SimpleLogger);
-        let fee_estimator = Arc::new(SimpleFeeEstimator);
-        let broadcaster = Arc::new(SimpleBroadcaster);
+        let fee_estimator = Arc::new(WalletFeeEstimator::new(wallet.fee_estimates()));
+        let broadcaster = Arc::new(Broadcaster::new(chain_source.clone(), storage.clone()));
+        let persister = Arc::new(FilesystemStore::new(storage_path.clone()));
+        let watchtower = Arc::new(WatchtowerPersister::new(
+            persister.clone(),
+            fee_estimator.clone(),
+        ));
+
+        let chain_monitor: Arc<ChainMonitor> = Arc::new(chainmonitor::ChainMonitor::new(
+            Some(chain_source.clone()),
+            broadcaster.clone(),
+            logger.clone(),
+            fee_estimator.clone(),
+            watchtower.clone(),
+        ));
+
+        let mut channel_monitors = read_channel_monitors(
+            persister.clone(),
+            keys_manager.clone(),
+            keys_manager.clone(),
+        )
+        .map_err(|e| Error::Storage(format!("Failed to read channel monitors: {}", e)))?;
+
+        let network_graph = Arc::new(load_network_graph(&persister, network, logger.clone())?);
+        let scorer = Arc::new(std::sync::RwLock::new(load_scorer(
+            &persister,
+            network_graph.clone(),
+            logger.clone(),
+        )?));
+        let router = Arc::new(DefaultRouter::new(
+            network_graph.clone(),
+            logger.clone(),
+            keys_manager.clone(),
+            scorer.clone(),
+            ProbabilisticScoringFeeParameters::default(),
+        ));
+
+        let channel_manager = match persister.read(
+            CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+            CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+            CHANNEL_MANAGER_PERSISTENCE_KEY,
+        ) {
+            Ok(bytes) => {
+                let read_args = ChannelManagerReadArgs::new(
+                    keys_manager.clone(),
+                    keys_manager.clone(),
+                    keys_manager.clone(),
+                    fee_estimator.clone(),
+                    chain_monitor.clone(),
+                    broadcaster.clone(),
+                    router,
+                    logger.clone(),
+                    default_user_config(),
+                    channel_monitors.iter_mut().map(|(_, m)| m).collect(),
+                );
+                let (_, channel_manager) =
+                    <(BlockHash, ChannelManager)>::read(&mut io::Cursor::new(bytes), read_args)
+                        .map_err(|e| {
+                            Error::Storage(format!("Failed to read channel manager: {:?}", e))
+                        })?;
+                channel_manager
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ChannelManager::new(
+                fee_estimator.clone(),
+                chain_monitor.clone(),
+                broadcaster.clone(),
+                router,
+                logger.clone(),
+                keys_manager.clone(),
+                keys_manager.clone(),
+                keys_manager.clone(),
+                default_user_config(),
+                ChainParameters {
+                    network,
+                    best_block: BestBlock::from_network(network),
+                },
+                cur_time.as_secs() as u32,
+            ),
+            Err(e) => {
+                return Err(Error::Storage(format!(
+                    "Failed to read channel manager: {}",
+                    e
+                )))
+            }
+        };
+        let channel_manager = Arc::new(channel_manager);
+
+        // Hand the restored monitors over to the chain monitor
+        for (_, monitor) in channel_monitors {
+            let funding_outpoint = monitor.get_funding_txo().0;
+            chain_monitor
+                .watch_channel(funding_outpoint, monitor)
+                .map_err(|_| Error::Internal("Failed to restore channel monitor".to_string()))?;
+        }
+
+        let onion_messenger: Arc<OnionMessenger> = Arc::new(OnionMessenger::new(
+            keys_manager.clone(),
+            keys_manager.clone(),
+            logger.clone(),
+            channel_manager.clone(),
+            Arc::new(DefaultMessageRouter::new(
+                network_graph.clone(),
+                keys_manager.clone(),
+            )),
+            channel_manager.clone(),
+            channel_manager.clone(),
+            IgnoringMessageHandler {},
+        ));
+
+        let gossip_sync: Arc<GossipSync> = Arc::new(P2PGossipSync::new(
+            network_graph.clone(),
+            None,
+            logger.clone(),
+        ));
+
+        let peer_manager: Arc<PeerManager> = Arc::new(PeerManager::new(
+            MessageHandler {
+                chan_handler: channel_manager.clone(),
+                route_handler: gossip_sync.clone(),
+                onion_message_handler: onion_messenger.clone(),
+                custom_message_handler: IgnoringMessageHandler {},
+            },
+            cur_time.as_secs() as u32,
+            &keys_manager.get_secure_random_bytes(),
+            logger.clone(),
+            keys_manager.clone(),
+        ));
+
+        let sweeper = Arc::new(sweep::load_sweeper(
+            persister.clone(),
+            channel_manager.current_best_block(),
+            broadcaster.clone(),
+            fee_estimator.clone(),
+            keys_manager.clone(),
+            wallet.clone(),
+            chain_source.clone(),
+            logger.clone(),
+        )?);
+
+        let channel_backups = Arc::new(ChannelBackups::new(
+            keys_manager.clone(),
+            channel_manager.clone(),
+            chain_monitor.clone(),
+            peer_manager.clone(),
+            network_graph.clone(),
+        ));
+
+        let channel_recovery = Arc::new(ChannelRecovery::load(
+            persister.clone(),
+            sweeper.clone(),
+            chain_source.clone(),
+        )?);
+
+        let event_handler = Arc::new(EventHandler::new(
+            keys_manager.clone(),
+            channel_manager.clone(),
+            chain_monitor.clone(),
+            broadcaster.clone(),
+            wallet.clone(),
+            storage.clone(),
+            fee_estimator.clone(),
+            sweeper.clone(),
+            scorer.clone(),
+            Arc::new(anchors::bump_handler(
+                broadcaster.clone(),
+                wallet.clone(),
+                keys_manager.clone(),
+                logger.clone(),
+            )),
+            channel_backups.clone(),
+        ));
 
         tracing::info!("Initialized Lightning node on {:?} network", network);
 
@@ -138,137 +387,184 @@ impl LdkNode {
             keys_manager,
             network,
             _storage_path: storage_path,
-            _logger: logger,
-            _fee_estimator: fee_estimator,
-            _broadcaster: broadcaster,
-            payments: Arc::new(RwLock::new(HashMap::new())),
+            logger,
+            fee_estimator,
+            broadcaster,
+            persister,
+            watchtower,
+            chain_source,
+            chain_monitor,
+            channel_manager,
+            network_graph,
+            scorer,
+            onion_messenger,
+            gossip_sync,
+            peer_manager,
+            sweeper,
+            channel_backups,
+            channel_recovery,
+            event_handler,
+            wallet,
+            storage,
+            lnurl_auth_root,
+            prober: Mutex::new(None),
+            mpp_config: Mutex::new(MppConfig::default()),
+            background: Mutex::new(None),
         })
     }
 
-    /// Get the node's public key
-    pub fn get_node_id(&self) -> PublicKey {
-        self.keys_manager
-            .get_node_id(lightning::sign::Recipient::Node)
-            .unwrap()
+    /// Connect to a Lightning peer
+    pub async fn connect_peer(&self, node_id: PublicKey, addr: SocketAddr) -> Result<()> {
+        if self.peer_manager.peer_by_node_id(&node_id).is_some() {
+            return Ok(());
+        }
+
+        let connection_closed =
+            lightning_net_tokio::connect_outbound(self.peer_manager.clone(), node_id, addr)
+                .await
+                .ok_or_else(|| Error::Network(format!("Failed to connect to {}", addr)))?;
+        let mut connection_closed = Box::pin(connection_closed);
+
+        // Wait for the noise handshake to complete
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        loop {
+            tokio::select! {
+                _ = &mut connection_closed => {
+                    return Err(Error::Network(format!("Peer {} disconnected", node_id)));
+                }
+                _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+            }
+            if self.peer_manager.peer_by_node_id(&node_id).is_some() {
+                tracing::info!("Connected to peer {}@{}", node_id, addr);
+                return Ok(());
+            }
+            if tokio::time::Instant::now() > deadline {
+                return Err(Error::Network(format!(
+                    "Timed out connecting to {}@{}",
+                    node_id, addr
+                )));
+            }
+        }
     }
 
-    /// Create a BOLT11 Lightning invoice
+    /// Process pending LDK events, flush peer messages and persist the channel manager
+    pub async fn process_events(&self) -> Result<()> {
+        self.event_handler.process_events().await?;
+        self.peer_manager.process_events();
+
+        if self.event_handler.take_scorer_updated() {
+            persist_scorer(&self.persister, &self.scorer)?;
+        }
+
+        if self.channel_manager.get_and_clear_needs_persistence() {
+            self.persist_channel_manager()?;
+        }
+        Ok(())
+    }
+
+    /// Wait until `rx` resolves or `timeout` elapses
     ///
-    /// # Arguments
-    /// * `amount_msats` - Amount in millisatoshis (None for any-amount invoice)
-    /// * `description` - Invoice description
-    /// * `expiry_secs` - Invoice expiry time in seconds (default: 3600)
-    pub async fn create_invoice(
+    /// Events are processed here unless the background processor is running.
+    pub(crate) async fn wait_for<T>(
         &self,
-        amount_msats: Option<u64>,
-        description: String,
-        expiry_secs: u32,
-    ) -> Result<String> {
-        let duration = Duration::from_secs(expiry_secs as u64);
-        let payment_hash = PaymentHash(self.keys_manager.get_secure_random_bytes());
-        let payment_secret = PaymentSecret(self.keys_manager.get_secure_random_bytes());
-
-        // Convert network to invoice currency
-        let currency = match self.network {
-            Network::Bitcoin => Currency::Bitcoin,
-            Network::Testnet => Currency::BitcoinTestnet,
-            Network::Regtest => Currency::Regtest,
-            Network::Signet => Currency::Signet,
-            _ => Currency::Regtest,
-        };
+        mut rx: oneshot::Receiver<T>,
+        timeout: Duration,
+        what: &str,
+    ) -> Result<T> {
+        if self.is_running() {
+            return match tokio::time::timeout(timeout, rx).await {
+                Ok(Ok(value)) => Ok(value),
+                Ok(Err(_)) => Err(Error::Internal(format!("Stopped waiting for {}", what))),
+                Err(_) => Err(Error::Network(format!("Timed out waiting for {}", what))),
+            };
+        }
 
-        // Get payment hash as Sha256 for invoice
-        use bitcoin::hashes::sha256;
-        let payment_hash_sha = sha256::Hash::from_slice(&payment_hash.0)
-            .map_err(|e| Error::Internal(format!("Failed to create SHA256: {}", e)))?;
-
-        // Build invoice
-        let mut invoice_builder = lightning_invoice::InvoiceBuilder::new(currency)
-            .description(description)
-            .payment_hash(payment_hash_sha)
-            .payment_secret(payment_secret)
-            .duration_since_epoch(
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .map_err(|e| Error::Internal(e.to_string()))?,
-            )
-            .min_final_cltv_expiry_delta(144)
-            .expiry_time(duration);
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            self.process_events().await?;
 
-        if let Some(amt) = amount_msats {
-            invoice_builder = invoice_builder.amount_milli_satoshis(amt);
-        }
+            match rx.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(TryRecvError::Closed) => {
+                    return Err(Error::Internal(format!("Stopped waiting for {}", what)))
+                }
+                Err(TryRecvError::Empty) => {}
+            }
 
-        let node_secret = self.keys_manager.get_node_secret_key();
-        let invoice = invoice_builder
-            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, &node_secret))
-            .map_err(|e| Error::Internal(format!("Failed to build invoice: {:?}", e)))?;
-
-        // Store payment info
-        let mut payments = self.payments.write().await;
-        payments.insert(
-            payment_hash,
-            PaymentInfo {
-                payment_hash: payment_hash.0,
-                amount_msat: amount_msats,
-                status: PaymentStatus::Pending,
-            },
-        );
+            if tokio::time::Instant::now() > deadline {
+                return Err(Error::Network(format!("Timed out waiting for {}", what)));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
 
-        tracing::info!(
-            "Created invoice for {} msats: {}",
-            amount_msats.unwrap_or(0),
-            invoice
-        );
+    /// Sync the channel manager, chain monitor and output sweeper with the chain
+    pub async fn sync_chain(&self) -> Result<()> {
+        chain::sync_confirmables(self.chain_source.clone(), self.confirmables()).await?;
 
-        Ok(invoice.to_string())
+        // While running, the background processor persists the channel manager
+        if !self.is_running() && self.channel_manager.get_and_clear_needs_persistence() {
+            self.persist_channel_manager()?;
+        }
+        Ok(())
     }
 
-    /// Pay a BOLT11 invoice
-    ///
-    /// This is a simplified implementation. In production, you would:
-    /// 1. Parse the invoice
-    /// 2. Find a route through the network
-    /// 3. Send HTLC payments
-    /// 4. Wait for payment confirmation
-    pub async fn pay_invoice(&self, invoice_str: String) -> Result<PaymentHash> {
-        let invoice = invoice_str
-            .parse::<Bolt11Invoice>()
-            .map_err(|e| Error::InvalidInvoice(e.to_string()))?;
-
-        let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
-        let amount_msats = invoice
-            .amount_milli_satoshis()
-            .ok_or_else(|| Error::InvalidInvoice("Invoice has no amount".to_string()))?;
-
-        tracing::info!("Attempting to pay invoice for {} msats", amount_msats);
-
-        // Store payment info
-        let mut payments = self.payments.write().await;
-        payments.insert(
-            payment_hash,
-            PaymentInfo {
-                payment_hash: payment_hash.0,
-                amount_msat: Some(amount_msats),
-                status: PaymentStatus::Pending,
-            },
-        );
+    /// Everything that needs to hear about confirmations and the chain tip
+    pub(crate) fn confirmables(&self) -> Vec<Arc<dyn Confirm + Send + Sync>> {
+        vec![
+            self.channel_manager.clone(),
+            self.chain_monitor.clone(),
+            // Ahead of the sweeper, which then hears about the blocks after a recovered close
+            self.channel_recovery.clone(),
+            self.sweeper.clone(),
+        ]
+    }
 
-        // In a full implementation, this would:
-        // 1. Find a route using the gossip network graph
-        // 2. Send the payment via HTLCs
-        // 3. Wait for preimage or failure
+    fn persist_channel_manager(&self) -> Result<()> {
+        self.persister
+            .write(
+                CHANNEL_MANAGER_PERSISTENCE_PRIMARY_NAMESPACE,
+                CHANNEL_MANAGER_PERSISTENCE_SECONDARY_NAMESPACE,
+                CHANNEL_MANAGER_PERSISTENCE_KEY,
+                &self.channel_manager.encode(),
+            )
+            .map_err(|e| Error::Storage(format!("Failed to persist channel manager: {}", e)))
+    }
 
-        // For now, return the payment hash
-        tracing::warn!("Payment sending not fully implemented - requires channel manager");
+    /// Get the node's public key
+    pub fn get_node_id(&self) -> PublicKey {
+        self.keys_manager
+            .get_node_id(lightning::sign::Recipient::Node)
+            .unwrap()
+    }
 
-        Ok(payment_hash)
+    /// Get the unified balance across the on-chain wallet, channels and pending sweeps
+    pub async fn get_unified_balance(&self) -> Result<Balance> {
+        let (confirmed, unconfirmed) = self.wallet.get_balance_details().await?;
+
+        let channels = self.channel_manager.list_channels();
+        let lightning_available = channels
+            .iter()
+            .filter(|c| c.is_usable)
+            .map(|c| c.outbound_capacity_msat)
+            .sum::<u64>();
+        let lightning_total = channels
+            .iter()
+            .map(|c| c.outbound_capacity_msat)
+            .sum::<u64>();
+
+        Ok(Balance {
+            confirmed,
+            unconfirmed,
+            lightning_available: bitcoin::Amount::from_sat(lightning_available / 1000),
+            lightning_total: bitcoin::Amount::from_sat(lightning_total / 1000),
+            pending_sweep: self.pending_sweep_balance(),
+        })
     }
 
-    /// List all payment history
-    pub async fn list_payments(&self) -> Result<Vec<PaymentInfo>> {
-        let payments = self.payments.read().await;
-        Ok(payments.values().cloned().collect())
+    /// Value of closed-channel outputs that have not been swept into the wallet yet
+    pub fn pending_sweep_balance(&self) -> bitcoin::Amount {
+        sweep::pending_sweep_balance(&self.sweeper.tracked_spendable_outputs())
     }
 
     /// Get node info
@@ -281,6 +577,24 @@ impl LdkNode {
     }
 }
 
+/// LDK configuration shared by new and restored channel managers
+pub(crate) fn default_user_config() -> UserConfig {
+    let mut config = UserConfig::default();
+    config
+        .channel_handshake_limits
+        .force_announced_channel_preference = false;
+    // Record BOLT12 payments before paying their invoices, see `Event::InvoiceReceived`
+    config.manually_handle_bolt12_invoices = true;
+    // Anchor channels keep commitment fees low and are fee bumped when they need to confirm,
+    // see `Event::BumpTransaction`
+    config
+        .channel_handshake_config
+        .negotiate_anchors_zero_fee_htlc_tx = true;
+    // Check inbound channels against the wallet's policy, see `Event::OpenChannelRequest`
+    config.manually_accept_inbound_channels = true;
+    config
+}
+
 /// Node information
 #[derive(Debug, Clone)]
 pub struct NodeInfo {
@@ -290,29 +604,137 @@ pub struct NodeInfo {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::chain::tests::MockChainSource;
+    use crate::offers::tests::connect;
+    use crate::payments::InvoiceOptions;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+    use lightning::events::Event;
+    use tempfile::TempDir;
+    use ulw_core::types::InboundChannelPolicy;
+    use ulw_storage::WalletDatabase;
+
+    /// Build a regtest node backed by a fresh wallet and database in a temp dir
+    pub(crate) async fn test_node(entropy: [u8; 32]) -> (LdkNode, TempDir) {
+        test_node_with_chain_source(entropy, Arc::new(MockChainSource::new())).await
+    }
+
+    pub(crate) async fn test_node_with_chain_source(
+        entropy: [u8; 32],
+        chain_source: Arc<dyn ChainSource>,
+    ) -> (LdkNode, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let wallet = Arc::new(
+            BdkWallet::new(
+                Network::Regtest,
+                "wpkh(tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m/84'/1'/0'/0/*)".to_string(),
+                "wpkh(tprv8ZgxMBicQKsPeZRHk4rTG6orPS2CRNFX3njhUXx5vj9qGog5ZMH4uGReDWN5kCkY3jmWEtWause41CDvBRXD1shKknAMKxT99o9qUTRVC6m/84'/1'/0'/1/*)".to_string(),
+                "tcp://localhost:50001".to_string(),
+            )
+            .unwrap(),
+        );
+        let storage = Arc::new(WalletDatabase::new(temp_dir.path().join("wallet.db")).unwrap());
+
+        let node = LdkNode::with_chain_source(
+            Network::Regtest,
+            temp_dir.path().join("lightning"),
+            entropy,
+            wallet,
+            storage,
+            chain_source,
+        )
+        .await
+        .unwrap();
+
+        (node, temp_dir)
+    }
+
+    /// Open a zero-conf channel of 100k sats from `node` to `peer`, with a funding transaction
+    /// that does not need the wallet, and wait until it is usable
+    pub(crate) async fn open_zero_conf_channel(
+        node: &LdkNode,
+        peer: &LdkNode,
+        push_msat: u64,
+    ) -> lightning::ln::channel_state::ChannelDetails {
+        peer.set_inbound_channel_policy(InboundChannelPolicy {
+            allowlist: vec![node.get_node_id()],
+            allow_zero_conf: true,
+            ..Default::default()
+        });
+        connect(node, peer).await;
+        node.channel_manager
+            .create_channel(peer.get_node_id(), 100_000, push_msat, 1, None, None)
+            .unwrap();
+        for _ in 0..200 {
+            if node.channel_manager.list_usable_channels().len() == 1 {
+                break;
+            }
+            node.channel_manager
+                .process_pending_events_async(|event| async {
+                    match event {
+                        Event::FundingGenerationReady {
+                            temporary_channel_id,
+                            counterparty_node_id,
+                            channel_value_satoshis,
+                            output_script,
+                            ..
+                        } => {
+                            let funding_tx = Transaction {
+                                version: Version::TWO,
+                                lock_time: LockTime::ZERO,
+                                input: vec![TxIn {
+                                    previous_output: bitcoin::OutPoint::new(Txid::all_zeros(), 0),
+                                    script_sig: ScriptBuf::new(),
+                                    sequence: Sequence::MAX,
+                                    witness: Witness::from_slice(&[[1]]),
+                                }],
+                                output: vec![TxOut {
+                                    value: Amount::from_sat(channel_value_satoshis),
+                                    script_pubkey: output_script,
+                                }],
+                            };
+                            node.channel_manager
+                                .funding_transaction_generated(
+                                    temporary_channel_id,
+                                    counterparty_node_id,
+                                    funding_tx,
+                                )
+                                .unwrap();
+                            Ok(())
+                        }
+                        event => node.event_handler.handle_event(event).await,
+                    }
+                })
+                .await;
+            node.peer_manager.process_events();
+            peer.process_events().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // The channel may become usable before its pending and ready events are handled
+        node.process_events().await.unwrap();
+        node.channel_manager.list_usable_channels().remove(0)
+    }
 
     #[tokio::test]
     async fn test_node_creation() {
-        let storage = PathBuf::from("/tmp/ldk-test-node");
-        let entropy = [42u8; 32];
-
-        let node = LdkNode::new(Network::Regtest, storage, entropy).await;
-        assert!(node.is_ok());
+        let (node, _temp) = test_node([42u8; 32]).await;
+        assert_eq!(node.get_info().network, Network::Regtest);
     }
 
     #[tokio::test]
     async fn test_invoice_creation() {
-        let storage = PathBuf::from("/tmp/ldk-test-invoice");
-        let entropy = [1u8; 32];
-
-        let node = LdkNode::new(Network::Regtest, storage, entropy)
-            .await
-            .unwrap();
+        let (node, _temp) = test_node([1u8; 32]).await;
 
         let invoice = node
-            .create_invoice(Some(10_000), "Test payment".to_string(), 3600)
+            .create_invoice(
+                Some(10_000),
+                "Test payment".to_string(),
+                &InvoiceOptions::default(),
+            )
             .await;
 
         assert!(invoice.is_ok());
@@ -321,15 +743,40 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_get_node_id() {
-        let storage = PathBuf::from("/tmp/ldk-test-nodeid");
-        let entropy = [2u8; 32];
+    async fn test_unified_balance_of_new_node() {
+        let (node, _temp) = test_node([6u8; 32]).await;
 
-        let node = LdkNode::new(Network::Regtest, storage, entropy)
-            .await
-            .unwrap();
+        let balance = node.get_unified_balance().await.unwrap();
+        assert_eq!(balance.total(), bitcoin::Amount::ZERO);
+        assert_eq!(node.pending_sweep_balance(), bitcoin::Amount::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_get_node_id() {
+        let (node, _temp) = test_node([2u8; 32]).await;
 
         let node_id = node.get_node_id();
         assert_eq!(node_id.serialize().len(), 33); // Compressed pubkey is 33 bytes
     }
+
+    #[tokio::test]
+    async fn test_open_channel_requires_connected_peer() {
+        let (node, _temp) = test_node([3u8; 32]).await;
+        let (peer, _peer_temp) = test_node([4u8; 32]).await;
+
+        let result = node
+            .open_channel(peer.get_node_id(), 100_000, 0, false)
+            .await;
+
+        assert!(matches!(result, Err(Error::Network(_))));
+        assert!(node.list_channels().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_close_unknown_channel() {
+        let (node, _temp) = test_node([5u8; 32]).await;
+
+        let result = node.close_channel(&"00".repeat(32), false, None).await;
+        assert!(matches!(result, Err(Error::ChannelNotFound(_))));
+    }
 }
@@ -0,0 +1,411 @@
+//! BOLT12 offers and refunds
+//!
+//! Offers are reusable payment requests: the payer fetches a fresh invoice from the recipient
+//! over onion messages, and both sides stay hidden behind blinded paths. Refunds work the other
+//! way around: their creator is the one who pays, once the recipient answers with an invoice.
+//!
+//! Invoices for the offers and refunds we pay are handled by the event handler, which records
+//! the payment before paying it. Answering invoice requests and paying refunds needs the node
+//! to be running and connected to at least one peer.
+
+use bitcoin::constants::ChainHash;
+use bitcoin::hashes::{sha256, Hash};
+use lightning::ln::channelmanager::{PaymentId, Retry};
+use lightning::ln::PaymentHash;
+use lightning::offers::offer::{Amount, Offer, Quantity};
+use lightning::offers::parse::Bolt12SemanticError;
+use lightning::offers::refund::Refund;
+use lightning::sign::EntropySource;
+use std::num::NonZeroU64;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ulw_core::types::{OfferKind, OfferRecord, Payment, PaymentDirection, PaymentStatus};
+use ulw_core::{Error, Result};
+
+use crate::node::LdkNode;
+use crate::payments::{SentPayment, PAYMENT_RETRY_TIMEOUT, PAYMENT_TIMEOUT};
+
+/// Map an error from building or paying an offer or refund
+fn semantic_error(e: Bolt12SemanticError) -> Error {
+    match e {
+        Bolt12SemanticError::MissingPaths => Error::Network(
+            "No blinded paths available: offers need a connected peer, and receiving a payment \
+             needs a channel"
+                .to_string(),
+        ),
+        e => Error::InvalidInvoice(format!("{:?}", e)),
+    }
+}
+
+impl LdkNode {
+    /// Create a BOLT12 offer and record it
+    ///
+    /// # Arguments
+    /// * `amount_msat` - Price per item in millisatoshis (None lets the payer choose)
+    /// * `description` - Offer description
+    /// * `quantity_max` - Most items that can be paid for at once (None for a single item)
+    ///
+    /// Returns the bech32-encoded offer.
+    pub async fn create_offer(
+        &self,
+        amount_msat: Option<u64>,
+        description: String,
+        quantity_max: Option<u64>,
+    ) -> Result<String> {
+        let mut builder = self
+            .channel_manager
+            .create_offer_builder(None)
+            .map_err(semantic_error)?
+            .description(description.clone());
+        if let Some(amount_msat) = amount_msat {
+            builder = builder.amount_msats(amount_msat);
+        }
+        if let Some(max) = quantity_max.filter(|max| *max > 1) {
+            builder = builder.supported_quantity(Quantity::Bounded(NonZeroU64::new(max).unwrap()));
+        }
+        let offer = builder.build().map_err(semantic_error)?;
+        let encoded = offer.to_string();
+
+        self.storage
+            .save_offer(&OfferRecord {
+                id: hex::encode(offer.id().0),
+                kind: OfferKind::Offer,
+                direction: PaymentDirection::Inbound,
+                encoded: encoded.clone(),
+                amount_msat,
+                description: Some(description),
+                quantity_max: quantity_max.filter(|max| *max > 1),
+                created_at: chrono::Utc::now(),
+            })
+            .await?;
+
+        tracing::info!("Created offer {}", hex::encode(offer.id().0));
+        Ok(encoded)
+    }
+
+    /// List offers and refunds created or paid by this wallet, newest first
+    pub async fn list_offers(&self) -> Result<Vec<OfferRecord>> {
+        self.storage.list_offers().await
+    }
+
+    /// Pay a BOLT12 offer
+    ///
+    /// Requests an invoice from the offer's issuer, then waits until the payment succeeds or
+    /// fails. The payment is recorded once the invoice arrives.
+    ///
+    /// # Arguments
+    /// * `offer_str` - Bech32-encoded offer
+    /// * `amount_msat` - Amount to pay, required if the offer does not specify one
+    /// * `quantity` - Number of items, for offers that support more than one
+    /// * `payer_note` - Note for the recipient, included in the invoice request
+    pub async fn pay_offer(
+        &self,
+        offer_str: &str,
+        amount_msat: Option<u64>,
+        quantity: Option<u64>,
+        payer_note: Option<String>,
+    ) -> Result<SentPayment> {
+        let offer = offer_str
+            .trim()
+            .parse::<Offer>()
+            .map_err(|e| Error::InvalidInvoice(format!("Invalid offer: {:?}", e)))?;
+        if !offer.supports_chain(ChainHash::using_genesis_block(self.network)) {
+            return Err(Error::InvalidInvoice(format!(
+                "Offer is not for {}",
+                self.network
+            )));
+        }
+        let offer_amount_msat = match offer.amount() {
+            Some(Amount::Bitcoin { amount_msats }) => Some(amount_msats),
+            Some(Amount::Currency { .. }) => {
+                return Err(Error::InvalidInvoice(
+                    "Offers priced in fiat currency are not supported".to_string(),
+                ))
+            }
+            None => None,
+        };
+
+        let payment_id = PaymentId(self.keys_manager.get_secure_random_bytes());
+        let result = self.event_handler.payment_results.register(payment_id);
+        tracing::info!("Requesting invoice for offer {}", hex::encode(offer.id().0));
+        self.channel_manager
+            .pay_for_offer(
+                &offer,
+                quantity,
+                amount_msat,
+                payer_note,
+                payment_id,
+                Retry::Timeout(PAYMENT_RETRY_TIMEOUT),
+                None,
+            )
+            .map_err(semantic_error)?;
+
+        self.storage
+            .save_offer(&OfferRecord {
+                id: hex::encode(payment_id.0),
+                kind: OfferKind::Offer,
+                direction: PaymentDirection::Outbound,
+                encoded: offer.to_string(),
+                amount_msat: amount_msat.or(offer_amount_msat),
+                description: offer.description().map(|d| d.to_string()),
+                quantity_max: match offer.supported_quantity() {
+                    Quantity::Bounded(max) => Some(max.get()),
+                    Quantity::Unbounded => Some(u64::MAX),
+                    Quantity::One => None,
+                },
+                created_at: chrono::Utc::now(),
+            })
+            .await?;
+
+        let (preimage, fee_paid_msat) = self
+            .wait_for(
+                result,
+                PAYMENT_TIMEOUT,
+                &format!("payment {} for offer to complete", payment_id),
+            )
+            .await??;
+
+        // The invoice decides the final amount, so take it from the recorded payment
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage.0).to_byte_array());
+        let amount_msat = self
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await?
+            .map(|payment| payment.amount_msat)
+            .unwrap_or_default();
+        Ok(SentPayment {
+            payment_hash,
+            preimage,
+            amount_msat,
+            fee_paid_msat,
+        })
+    }
+
+    /// Create a BOLT12 refund and record it
+    ///
+    /// Whoever holds the refund can claim `amount_msat` from us by answering with an invoice,
+    /// which is paid automatically while the node is running.
+    ///
+    /// # Arguments
+    /// * `amount_msat` - Amount to pay out
+    /// * `description` - Refund description
+    /// * `expiry_secs` - How long the refund can be claimed for
+    ///
+    /// Returns the bech32-encoded refund.
+    pub async fn create_refund(
+        &self,
+        amount_msat: u64,
+        description: String,
+        expiry_secs: u64,
+    ) -> Result<String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        let payment_id = PaymentId(self.keys_manager.get_secure_random_bytes());
+        let refund = self
+            .channel_manager
+            .create_refund_builder(
+                amount_msat,
+                now + Duration::from_secs(expiry_secs),
+                payment_id,
+                Retry::Timeout(PAYMENT_RETRY_TIMEOUT),
+                None,
+            )
+            .map_err(semantic_error)?
+            .description(description)
+            .build()
+            .map_err(semantic_error)?;
+        let encoded = refund.to_string();
+
+        self.storage
+            .save_offer(&OfferRecord {
+                id: hex::encode(payment_id.0),
+                kind: OfferKind::Refund,
+                direction: PaymentDirection::Outbound,
+                encoded: encoded.clone(),
+                amount_msat: Some(amount_msat),
+                description: Some(refund.description().to_string()),
+                quantity_max: None,
+                created_at: chrono::Utc::now(),
+            })
+            .await?;
+        tracing::info!("Created refund of {} msat", amount_msat);
+        Ok(encoded)
+    }
+
+    /// Claim a BOLT12 refund by sending its creator an invoice
+    ///
+    /// The payment is recorded as pending and completes when the refund's creator pays.
+    ///
+    /// Returns the payment hash.
+    pub async fn request_refund_payment(&self, refund_str: &str) -> Result<String> {
+        let refund = refund_str
+            .trim()
+            .parse::<Refund>()
+            .map_err(|e| Error::InvalidInvoice(format!("Invalid refund: {:?}", e)))?;
+        let invoice = self
+            .channel_manager
+            .request_refund_payment(&refund)
+            .map_err(semantic_error)?;
+
+        let payment_hash = hex::encode(invoice.payment_hash().0);
+        self.storage
+            .save_payment(&Payment {
+                payment_hash: payment_hash.clone(),
+                amount_msat: invoice.amount_msats(),
+                direction: PaymentDirection::Inbound,
+                status: PaymentStatus::Pending,
+                invoice: Some(refund_str.trim().to_string()),
+                created_at: chrono::Utc::now(),
+                settled_at: None,
+                preimage: None,
+                fee_paid_msat: None,
+                custom_tlvs: Vec::new(),
+                parts: None,
+                path_fees_msat: Vec::new(),
+                path_ids: Vec::new(),
+                offer_id: None,
+                claim_deadline: None,
+            })
+            .await?;
+
+        tracing::info!(
+            "Requested {} msat for refund, payment {}",
+            invoice.amount_msats(),
+            payment_hash
+        );
+        Ok(payment_hash)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+    use lightning::blinded_path::payment::Bolt12OfferContext;
+    use lightning::events::{Event, PaymentPurpose};
+    use lightning::ln::{PaymentPreimage, PaymentSecret};
+    use lightning::offers::invoice_request::InvoiceRequestFields;
+    use lightning::offers::offer::OfferId;
+    use tokio::net::TcpListener;
+
+    /// Connect `node` to `peer` over a local TCP socket
+    pub(crate) async fn connect(node: &LdkNode, peer: &LdkNode) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer_manager = peer.peer_manager.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            lightning_net_tokio::setup_inbound(peer_manager, stream.into_std().unwrap()).await;
+        });
+        node.connect_peer(peer.get_node_id(), addr).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_offers_need_a_peer() {
+        let (node, _temp) = test_node([90u8; 32]).await;
+
+        let result = node
+            .create_offer(Some(1_000), "coffee".to_string(), None)
+            .await;
+        assert!(matches!(result, Err(Error::Network(_))));
+        let result = node.create_refund(1_000, "refund".to_string(), 3600).await;
+        assert!(matches!(result, Err(Error::Network(_))));
+        let result = node.pay_offer("lno1invalid", None, None, None).await;
+        assert!(matches!(result, Err(Error::InvalidInvoice(_))));
+
+        assert!(node.storage.list_offers().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_offer_and_refund_records() {
+        let (node, _temp) = test_node([91u8; 32]).await;
+        let (peer, _peer_temp) = test_node([92u8; 32]).await;
+        connect(&node, &peer).await;
+
+        let encoded = node
+            .create_offer(Some(5_000), "coffee".to_string(), Some(3))
+            .await
+            .unwrap();
+        let offer = encoded.parse::<Offer>().unwrap();
+        assert_eq!(
+            offer.amount(),
+            Some(Amount::Bitcoin {
+                amount_msats: 5_000
+            })
+        );
+        assert_eq!(
+            offer.supported_quantity(),
+            Quantity::Bounded(NonZeroU64::new(3).unwrap())
+        );
+
+        let record = node
+            .storage
+            .get_offer(&hex::encode(offer.id().0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.kind, OfferKind::Offer);
+        assert_eq!(record.direction, PaymentDirection::Inbound);
+        assert_eq!(record.encoded, encoded);
+        assert_eq!(record.quantity_max, Some(3));
+
+        let refund = node
+            .create_refund(2_000, "returned item".to_string(), 3600)
+            .await
+            .unwrap();
+        let offers = node.storage.list_offers().await.unwrap();
+        assert_eq!(offers.len(), 2);
+        let record = offers.iter().find(|o| o.kind == OfferKind::Refund).unwrap();
+        assert_eq!(record.direction, PaymentDirection::Outbound);
+        assert_eq!(record.encoded, refund);
+        assert_eq!(record.amount_msat, Some(2_000));
+        assert_eq!(record.description.as_deref(), Some("returned item"));
+
+        // The peer could reach us, but without a channel there is no way to pay it
+        let result = peer.request_refund_payment(&refund).await;
+        assert!(matches!(result, Err(Error::Network(_))));
+        assert!(peer.storage.list_payments().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_received_offer_payment_records_offer_id() {
+        let (node, _temp) = test_node([93u8; 32]).await;
+        let preimage = PaymentPreimage([9; 32]);
+        let payment_hash = PaymentHash(sha256::Hash::hash(&preimage.0).to_byte_array());
+
+        node.event_handler
+            .handle_event(Event::PaymentClaimed {
+                receiver_node_id: None,
+                payment_hash,
+                amount_msat: 15_000,
+                purpose: PaymentPurpose::Bolt12OfferPayment {
+                    payment_preimage: Some(preimage),
+                    payment_secret: PaymentSecret([1; 32]),
+                    payment_context: Bolt12OfferContext {
+                        offer_id: OfferId([4; 32]),
+                        invoice_request: InvoiceRequestFields {
+                            payer_id: node.get_node_id(),
+                            quantity: Some(3),
+                            payer_note_truncated: None,
+                        },
+                    },
+                },
+                htlcs: vec![],
+                sender_intended_total_msat: None,
+                onion_fields: None,
+            })
+            .await
+            .unwrap();
+
+        let payment = node
+            .storage
+            .get_payment(&hex::encode(payment_hash.0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.direction, PaymentDirection::Inbound);
+        assert_eq!(payment.status, PaymentStatus::Succeeded);
+        assert_eq!(payment.offer_id, Some(hex::encode([4; 32])));
+    }
+}
@@ -1,21 +1,734 @@
 //! Lightning payment handling
 
-use ulw_core::Result;
+use bitcoin::address::AddressData;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Address;
+use lightning::ln::bolt11_payment::{
+    payment_parameters_from_invoice, payment_parameters_from_zero_amount_invoice,
+};
+use lightning::ln::channel_state::ChannelDetails;
+use lightning::ln::channelmanager::{PaymentId, Retry, MIN_FINAL_CLTV_EXPIRY_DELTA};
+use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
+use lightning::routing::gossip::{NodeId, RoutingFees};
+use lightning::routing::router::{find_route, RouteHint, RouteHintHop, RouteParameters};
+use lightning::routing::scoring::ProbabilisticScoringFeeParameters;
+use lightning::sign::{EntropySource, NodeSigner, Recipient};
+use lightning_invoice::{Bolt11Invoice, Currency, Fallback, InvoiceBuilder};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-pub struct PaymentHandler {
-    // Placeholder
+use ulw_core::types::{Payment, PaymentConstraints, PaymentDirection, PaymentStatus};
+use ulw_core::{Error, Result};
+
+use crate::node::LdkNode;
+
+/// How long to wait for an outbound payment to succeed or fail
+pub(crate) const PAYMENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long LDK keeps retrying failed paths of an outbound payment
+pub(crate) const PAYMENT_RETRY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How outbound payments may be split over several paths
+#[derive(Debug, Clone)]
+pub struct MppConfig {
+    /// Most parts to split a payment into
+    pub max_parts: u8,
+    /// Smallest part worth sending on its own path, in millisatoshis
+    pub min_part_msat: u64,
+}
+
+impl Default for MppConfig {
+    fn default() -> Self {
+        Self {
+            max_parts: 10,
+            min_part_msat: 1_000_000,
+        }
+    }
 }
 
-impl PaymentHandler {
-    pub fn new() -> Result<Self> {
-        Ok(Self {})
+impl MppConfig {
+    /// Most parts a payment of `amount_msat` may be split into
+    ///
+    /// LDK requires every path to carry at least an equal share of the amount, so capping the
+    /// number of parts keeps each of them above the minimum size.
+    pub fn max_parts_for(&self, amount_msat: u64) -> u8 {
+        let parts = amount_msat / self.min_part_msat.max(1);
+        parts.clamp(1, self.max_parts.max(1) as u64) as u8
     }
+}
+
+/// Blocks added to the CLTV delta advertised in invoices, for blocks found while the payment
+/// is in flight
+const CLTV_DELTA_BUFFER: u16 = 3;
 
-    pub async fn create_invoice(&self, amount_msat: u64, _description: String) -> Result<String> {
-        Ok(format!("lnbc{}...", amount_msat))
+/// Settings for invoices we create
+#[derive(Debug, Clone)]
+pub struct InvoiceOptions {
+    /// Seconds until the invoice expires
+    pub expiry_secs: u32,
+    /// Blocks the final HTLC must have left before expiring when it reaches us
+    pub min_final_cltv_expiry_delta: u16,
+    /// Add a fresh on-chain address the payer can fall back to
+    pub fallback_address: bool,
+    /// Most private channels to add route hints for
+    pub max_route_hints: usize,
+}
+
+impl Default for InvoiceOptions {
+    fn default() -> Self {
+        Self {
+            expiry_secs: 3600,
+            min_final_cltv_expiry_delta: 144,
+            fallback_address: false,
+            max_route_hints: 3,
+        }
     }
+}
 
-    pub async fn pay_invoice(&self, _invoice: String) -> Result<()> {
+impl InvoiceOptions {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.min_final_cltv_expiry_delta < MIN_FINAL_CLTV_EXPIRY_DELTA {
+            return Err(Error::InvalidInvoice(format!(
+                "CLTV delta must be at least {} blocks",
+                MIN_FINAL_CLTV_EXPIRY_DELTA
+            )));
+        }
         Ok(())
     }
 }
+
+/// Route hints for our usable unannounced channels, so payers can reach us over them
+///
+/// One channel per peer is hinted, the one with the most inbound capacity, and peers with the
+/// most inbound capacity come first.
+fn route_hints(channels: &[ChannelDetails], max: usize) -> Vec<RouteHint> {
+    let candidates = channels
+        .iter()
+        .filter(|channel| channel.is_usable && !channel.is_announced)
+        .filter_map(|channel| {
+            let forwarding = channel.counterparty.forwarding_info.as_ref()?;
+            let hop = RouteHintHop {
+                src_node_id: channel.counterparty.node_id,
+                short_channel_id: channel.get_inbound_payment_scid()?,
+                fees: RoutingFees {
+                    base_msat: forwarding.fee_base_msat,
+                    proportional_millionths: forwarding.fee_proportional_millionths,
+                },
+                cltv_expiry_delta: forwarding.cltv_expiry_delta,
+                htlc_minimum_msat: channel.inbound_htlc_minimum_msat,
+                htlc_maximum_msat: channel.inbound_htlc_maximum_msat,
+            };
+            Some((channel.inbound_capacity_msat, hop))
+        })
+        .collect();
+    select_route_hints(candidates, max)
+}
+
+/// Pick route hints from `(inbound capacity, hop)` candidates, see [`route_hints`]
+fn select_route_hints(candidates: Vec<(u64, RouteHintHop)>, max: usize) -> Vec<RouteHint> {
+    let mut best: HashMap<PublicKey, (u64, RouteHintHop)> = HashMap::new();
+    for (inbound_msat, hop) in candidates {
+        match best.get(&hop.src_node_id) {
+            Some((best_inbound, _)) if *best_inbound >= inbound_msat => {}
+            _ => {
+                best.insert(hop.src_node_id, (inbound_msat, hop));
+            }
+        }
+    }
+
+    let mut hints: Vec<_> = best.into_values().collect();
+    hints.sort_by(|(a, a_hop), (b, b_hop)| {
+        b.cmp(a)
+            .then(a_hop.short_channel_id.cmp(&b_hop.short_channel_id))
+    });
+    hints
+        .into_iter()
+        .take(max)
+        .map(|(_, hop)| RouteHint(vec![hop]))
+        .collect()
+}
+
+/// Invoice fallback for an on-chain address
+fn fallback(address: &Address) -> Result<Fallback> {
+    match address.to_address_data() {
+        AddressData::P2pkh { pubkey_hash } => Ok(Fallback::PubKeyHash(pubkey_hash)),
+        AddressData::P2sh { script_hash } => Ok(Fallback::ScriptHash(script_hash)),
+        AddressData::Segwit { witness_program } => Ok(Fallback::SegWitProgram {
+            version: witness_program.version(),
+            program: witness_program.program().as_bytes().to_vec(),
+        }),
+        _ => Err(Error::InvalidAddress(format!(
+            "{} cannot be used as an invoice fallback",
+            address
+        ))),
+    }
+}
+
+/// Result of a completed outbound payment
+#[derive(Debug, Clone)]
+pub struct SentPayment {
+    pub payment_hash: PaymentHash,
+    pub preimage: PaymentPreimage,
+    pub amount_msat: u64,
+    /// Routing fees paid, if reported by LDK
+    pub fee_paid_msat: Option<u64>,
+}
+
+impl LdkNode {
+    /// Create a BOLT11 Lightning invoice
+    ///
+    /// The payment is registered with the channel manager so incoming HTLCs can be claimed,
+    /// and recorded as a pending inbound payment together with its preimage.
+    ///
+    /// # Arguments
+    /// * `amount_msats` - Amount in millisatoshis (None for any-amount invoice)
+    /// * `description` - Invoice description
+    /// * `options` - Expiry, CLTV delta, route hints and fallback address
+    pub async fn create_invoice(
+        &self,
+        amount_msats: Option<u64>,
+        description: String,
+        options: &InvoiceOptions,
+    ) -> Result<String> {
+        options.validate()?;
+        let (payment_hash, payment_secret) = self
+            .channel_manager
+            .create_inbound_payment(
+                amount_msats,
+                options.expiry_secs,
+                Some(options.min_final_cltv_expiry_delta),
+            )
+            .map_err(|()| Error::InvalidInvoice("Invalid invoice amount or expiry".to_string()))?;
+        let invoice = self
+            .build_invoice(
+                amount_msats,
+                description,
+                payment_hash,
+                payment_secret,
+                options,
+            )
+            .await?;
+
+        let preimage = self
+            .channel_manager
+            .get_payment_preimage(payment_hash, payment_secret)
+            .map_err(|e| Error::Internal(format!("Failed to derive preimage: {:?}", e)))?;
+
+        self.storage
+            .save_payment(&Payment {
+                payment_hash: hex::encode(payment_hash.0),
+                amount_msat: amount_msats.unwrap_or(0),
+                direction: PaymentDirection::Inbound,
+                status: PaymentStatus::Pending,
+                invoice: Some(invoice.to_string()),
+                created_at: chrono::Utc::now(),
+                settled_at: None,
+                preimage: Some(hex::encode(preimage.0)),
+                fee_paid_msat: None,
+                custom_tlvs: Vec::new(),
+                parts: None,
+                path_fees_msat: Vec::new(),
+                path_ids: Vec::new(),
+                offer_id: None,
+                claim_deadline: None,
+            })
+            .await?;
+
+        tracing::info!(
+            "Created invoice for {} msats with {} route hint(s): {}",
+            amount_msats.unwrap_or(0),
+            invoice.private_routes().len(),
+            invoice
+        );
+
+        Ok(invoice.to_string())
+    }
+
+    /// Build and sign an invoice for a payment already registered with the channel manager
+    pub(crate) async fn build_invoice(
+        &self,
+        amount_msats: Option<u64>,
+        description: String,
+        payment_hash: PaymentHash,
+        payment_secret: PaymentSecret,
+        options: &InvoiceOptions,
+    ) -> Result<Bolt11Invoice> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+
+        let mut builder = InvoiceBuilder::new(Currency::from(self.network))
+            .description(description)
+            .duration_since_epoch(now)
+            .payee_pub_key(self.get_node_id())
+            .payment_hash(sha256::Hash::from_byte_array(payment_hash.0))
+            .payment_secret(payment_secret)
+            .basic_mpp()
+            .min_final_cltv_expiry_delta(
+                (options.min_final_cltv_expiry_delta + CLTV_DELTA_BUFFER) as u64,
+            )
+            .expiry_time(Duration::from_secs(options.expiry_secs as u64));
+        if let Some(amount_msats) = amount_msats {
+            builder = builder.amount_milli_satoshis(amount_msats);
+        }
+        for hint in route_hints(
+            &self.channel_manager.list_usable_channels(),
+            options.max_route_hints,
+        ) {
+            builder = builder.private_route(hint);
+        }
+        if options.fallback_address {
+            let address = self.wallet.get_new_address().await?;
+            builder = builder.fallback(fallback(&address)?);
+        }
+
+        let raw = builder
+            .build_raw()
+            .map_err(|e| Error::InvalidInvoice(format!("Failed to build invoice: {}", e)))?;
+        let signature = self.keys_manager.sign_invoice(&raw, Recipient::Node);
+        let signed = raw
+            .sign(|_| signature)
+            .map_err(|()| Error::Internal("Failed to sign invoice".to_string()))?;
+        Bolt11Invoice::from_signed(signed)
+            .map_err(|e| Error::Internal(format!("Invalid signed invoice: {}", e)))
+    }
+
+    /// Set how outbound payments may be split over several paths
+    pub fn set_mpp_config(&self, config: MppConfig) {
+        *self.mpp_config.lock().unwrap() = config;
+    }
+
+    /// List all payment history, newest first
+    pub async fn list_payments(&self) -> Result<Vec<Payment>> {
+        self.storage.list_payments().await
+    }
+
+    /// Pay a BOLT11 invoice
+    ///
+    /// Finds a route over the network graph and sends through the channel manager, retrying
+    /// failed paths for a while. Waits until the payment succeeds or fails and records the
+    /// result in wallet storage.
+    ///
+    /// # Arguments
+    /// * `invoice_str` - BOLT11 invoice
+    /// * `amount_msat` - Amount to pay; required for zero-amount invoices
+    /// * `constraints` - Limits on fees, timelocks and the route taken
+    ///
+    /// Fails with [`Error::RouteConstraints`] if the payee can be reached, but not within
+    /// `constraints`.
+    pub async fn pay_invoice(
+        &self,
+        invoice_str: &str,
+        amount_msat: Option<u64>,
+        constraints: &PaymentConstraints,
+    ) -> Result<SentPayment> {
+        let invoice = invoice_str
+            .trim()
+            .parse::<Bolt11Invoice>()
+            .map_err(|e| Error::InvalidInvoice(e.to_string()))?;
+
+        if invoice.network() != self.network {
+            return Err(Error::InvalidInvoice(format!(
+                "Invoice is for {}, node is on {}",
+                invoice.network(),
+                self.network
+            )));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+        if invoice.would_expire(now) {
+            return Err(Error::InvalidInvoice("Invoice has expired".to_string()));
+        }
+
+        let (payment_hash, recipient_onion, mut route_params) =
+            match (invoice.amount_milli_satoshis(), amount_msat) {
+                (Some(invoice_amount), Some(amount)) if invoice_amount != amount => {
+                    return Err(Error::InvalidInvoice(format!(
+                        "Invoice is for {} msat, not {} msat",
+                        invoice_amount, amount
+                    )))
+                }
+                (Some(_), _) => payment_parameters_from_invoice(&invoice),
+                (None, Some(amount)) => {
+                    payment_parameters_from_zero_amount_invoice(&invoice, amount)
+                }
+                (None, None) => {
+                    return Err(Error::InvalidInvoice(
+                        "Invoice has no amount; specify the amount to pay".to_string(),
+                    ))
+                }
+            }
+            .map_err(|_| Error::InvalidInvoice("Unusable invoice amount".to_string()))?;
+
+        let hash_hex = hex::encode(payment_hash.0);
+        let amount_msat = route_params.final_value_msat;
+        // Large payments may be split over several paths if the invoice allows it
+        route_params.payment_params.max_path_count =
+            self.mpp_config.lock().unwrap().max_parts_for(amount_msat);
+
+        if !constraints.is_unconstrained() {
+            let mut constrained = route_params.clone();
+            self.constrain_route(
+                &mut constrained,
+                &invoice.recover_payee_pub_key(),
+                constraints,
+            )?;
+            // Only blame the constraints if they are what stands in the way
+            if let Err(e) = self.find_route(&constrained) {
+                if self.find_route(&route_params).is_ok() {
+                    return Err(Error::RouteConstraints(e));
+                }
+            }
+            route_params = constrained;
+        }
+
+        if let Some(existing) = self.storage.get_payment(&hash_hex).await? {
+            if existing.status != PaymentStatus::Failed {
+                return Err(Error::PaymentFailed(format!(
+                    "Invoice {} is already {:?}",
+                    hash_hex, existing.status
+                )));
+            }
+        }
+
+        let mut record = Payment {
+            payment_hash: hash_hex.clone(),
+            amount_msat,
+            direction: PaymentDirection::Outbound,
+            status: PaymentStatus::Pending,
+            invoice: Some(invoice.to_string()),
+            created_at: chrono::Utc::now(),
+            settled_at: None,
+            preimage: None,
+            fee_paid_msat: None,
+            custom_tlvs: Vec::new(),
+            parts: None,
+            path_fees_msat: Vec::new(),
+            path_ids: Vec::new(),
+            offer_id: None,
+            claim_deadline: None,
+        };
+        self.storage.save_payment(&record).await?;
+
+        // BOLT11 payments are identified by their payment hash
+        let payment_id = PaymentId(payment_hash.0);
+        let result = self.event_handler.payment_results.register(payment_id);
+
+        tracing::info!(
+            "Paying {} msat to {}",
+            amount_msat,
+            invoice.recover_payee_pub_key()
+        );
+
+        if let Err(e) = self.channel_manager.send_payment(
+            payment_hash,
+            recipient_onion,
+            payment_id,
+            route_params,
+            Retry::Timeout(PAYMENT_RETRY_TIMEOUT),
+        ) {
+            record.status = PaymentStatus::Failed;
+            self.storage.save_payment(&record).await?;
+            return Err(Error::PaymentFailed(format!("{:?}", e)));
+        }
+
+        let (preimage, fee_paid_msat) = self
+            .wait_for(
+                result,
+                PAYMENT_TIMEOUT,
+                &format!("payment {} to complete", hash_hex),
+            )
+            .await??;
+
+        Ok(SentPayment {
+            payment_hash,
+            preimage,
+            amount_msat,
+            fee_paid_msat,
+        })
+    }
+
+    /// Narrow `route_params` for a payment to `payee` down to `constraints`
+    ///
+    /// Avoided nodes are kept off the route by treating all of their channels as failed.
+    fn constrain_route(
+        &self,
+        route_params: &mut RouteParameters,
+        payee: &PublicKey,
+        constraints: &PaymentConstraints,
+    ) -> Result<()> {
+        if constraints.avoid_nodes.contains(payee) {
+            return Err(Error::RouteConstraints(format!(
+                "Payee {} is on the avoid list",
+                payee
+            )));
+        }
+        if constraints.max_paths == Some(0) {
+            return Err(Error::RouteConstraints(
+                "At least one path is needed".to_string(),
+            ));
+        }
+
+        if let Some(max_fee_msat) = constraints.max_fee_msat(route_params.final_value_msat) {
+            route_params.max_total_routing_fee_msat = Some(max_fee_msat);
+        }
+        let payment_params = &mut route_params.payment_params;
+        if let Some(max_cltv) = constraints.max_cltv_expiry_delta {
+            payment_params.max_total_cltv_expiry_delta = max_cltv;
+        }
+        if let Some(max_paths) = constraints.max_paths {
+            payment_params.max_path_count = payment_params.max_path_count.min(max_paths);
+        }
+
+        let graph = self.network_graph.read_only();
+        for node_id in &constraints.avoid_nodes {
+            if let Some(node) = graph.node(&NodeId::from_pubkey(node_id)) {
+                payment_params
+                    .previously_failed_channels
+                    .extend(node.channels.iter().copied());
+            }
+            payment_params.previously_failed_channels.extend(
+                self.channel_manager
+                    .list_channels_with_counterparty(node_id)
+                    .iter()
+                    .filter_map(|channel| channel.get_outbound_payment_scid()),
+            );
+        }
+        Ok(())
+    }
+
+    /// Look for a route the way the channel manager would, without sending anything
+    fn find_route(&self, route_params: &RouteParameters) -> std::result::Result<(), String> {
+        let first_hops = self.channel_manager.list_usable_channels();
+        let first_hops: Vec<_> = first_hops.iter().collect();
+        let scorer = self.scorer.read().unwrap();
+        find_route(
+            &self.channel_manager.get_our_node_id(),
+            route_params,
+            &self.network_graph,
+            Some(&first_hops),
+            self.logger.clone(),
+            &*scorer,
+            &ProbabilisticScoringFeeParameters::default(),
+            &self.keys_manager.get_secure_random_bytes(),
+        )
+        .map(|_| ())
+        .map_err(|e| e.err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    #[tokio::test]
+    async fn test_invoice_records_preimage() {
+        let (node, _temp) = test_node([14u8; 32]).await;
+
+        let options = InvoiceOptions {
+            expiry_secs: 600,
+            min_final_cltv_expiry_delta: 80,
+            fallback_address: true,
+            ..Default::default()
+        };
+        let invoice = node
+            .create_invoice(Some(21_000), "Coffee".to_string(), &options)
+            .await
+            .unwrap();
+
+        let parsed = invoice.parse::<Bolt11Invoice>().unwrap();
+        assert_eq!(parsed.expiry_time(), Duration::from_secs(600));
+        assert_eq!(parsed.min_final_cltv_expiry_delta(), 83);
+        assert_eq!(parsed.fallback_addresses().len(), 1);
+        // No channels, so nothing to hint
+        assert!(parsed.route_hints().is_empty());
+
+        let too_short = InvoiceOptions {
+            min_final_cltv_expiry_delta: 6,
+            ..Default::default()
+        };
+        let result = node
+            .create_invoice(None, "Too short".to_string(), &too_short)
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInvoice(_))));
+
+        let payments = node.list_payments().await.unwrap();
+        assert_eq!(payments.len(), 1);
+        let payment = &payments[0];
+        assert_eq!(payment.direction, PaymentDirection::Inbound);
+        assert_eq!(payment.status, PaymentStatus::Pending);
+        assert_eq!(payment.invoice.as_deref(), Some(invoice.as_str()));
+
+        // The stored preimage must unlock the invoice's payment hash
+        let preimage = hex::decode(payment.preimage.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            sha256::Hash::hash(&preimage).to_string(),
+            payment.payment_hash
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zero_amount_invoice_requires_amount() {
+        let (node, _temp) = test_node([10u8; 32]).await;
+        let (payee, _payee_temp) = test_node([11u8; 32]).await;
+
+        let invoice = payee
+            .create_invoice(None, "Anything".to_string(), &InvoiceOptions::default())
+            .await
+            .unwrap();
+
+        let result = node
+            .pay_invoice(&invoice, None, &PaymentConstraints::default())
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInvoice(_))));
+    }
+
+    #[tokio::test]
+    async fn test_pay_without_channels_fails() {
+        let (node, _temp) = test_node([12u8; 32]).await;
+        let (payee, _payee_temp) = test_node([13u8; 32]).await;
+
+        let invoice = payee
+            .create_invoice(None, "Anything".to_string(), &InvoiceOptions::default())
+            .await
+            .unwrap();
+
+        let result = node
+            .pay_invoice(&invoice, Some(5_000), &PaymentConstraints::default())
+            .await;
+        assert!(matches!(result, Err(Error::PaymentFailed(_))));
+
+        // The failed attempt is kept in the payment history
+        let payments = node.storage.list_payments().await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].amount_msat, 5_000);
+        assert_eq!(payments[0].status, ulw_core::types::PaymentStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_avoiding_payee_is_a_constraint_error() {
+        let (node, _temp) = test_node([15u8; 32]).await;
+        let (payee, _payee_temp) = test_node([16u8; 32]).await;
+
+        let invoice = payee
+            .create_invoice(
+                Some(5_000),
+                "Avoided".to_string(),
+                &InvoiceOptions::default(),
+            )
+            .await
+            .unwrap();
+        let constraints = PaymentConstraints {
+            avoid_nodes: vec![payee.get_node_id()],
+            ..Default::default()
+        };
+
+        let result = node.pay_invoice(&invoice, None, &constraints).await;
+        assert!(matches!(result, Err(Error::RouteConstraints(_))));
+        // Nothing was attempted
+        assert!(node.storage.list_payments().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_constraints_narrow_route_params() {
+        let (node, _temp) = test_node([17u8; 32]).await;
+        let (payee, _payee_temp) = test_node([18u8; 32]).await;
+        node.apply_rapid_gossip_snapshot(
+            include_bytes!("../fixtures/rgs_regtest.bin"),
+            1_642_291_930,
+        )
+        .unwrap();
+        let (avoided, avoided_channels) = {
+            let graph = node.network_graph.read_only();
+            let (node_id, info) = graph.nodes().unordered_iter().next().unwrap();
+            (node_id.as_pubkey().unwrap(), info.channels.clone())
+        };
+
+        let invoice = payee
+            .create_invoice(
+                Some(1_000_000),
+                "Constrained".to_string(),
+                &InvoiceOptions::default(),
+            )
+            .await
+            .unwrap()
+            .parse()
+            .unwrap();
+        let (_, _, mut route_params) = payment_parameters_from_invoice(&invoice).unwrap();
+        let constraints = PaymentConstraints {
+            max_fee_msat: Some(20_000),
+            max_fee_percent: Some(1.0),
+            max_cltv_expiry_delta: Some(500),
+            avoid_nodes: vec![avoided],
+            max_paths: Some(1),
+        };
+        node.constrain_route(&mut route_params, &payee.get_node_id(), &constraints)
+            .unwrap();
+
+        // The percentage limit is the tighter one here
+        assert_eq!(route_params.max_total_routing_fee_msat, Some(10_000));
+        assert_eq!(route_params.payment_params.max_total_cltv_expiry_delta, 500);
+        assert_eq!(route_params.payment_params.max_path_count, 1);
+        assert!(!avoided_channels.is_empty());
+        assert_eq!(
+            route_params.payment_params.previously_failed_channels,
+            avoided_channels
+        );
+    }
+
+    #[test]
+    fn test_route_hints_prefer_inbound_capacity() {
+        let secp = Secp256k1::new();
+        let peer =
+            |i: u8| PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[i; 32]).unwrap());
+        let hop = |peer: PublicKey, scid: u64| RouteHintHop {
+            src_node_id: peer,
+            short_channel_id: scid,
+            fees: RoutingFees {
+                base_msat: 1_000,
+                proportional_millionths: 100,
+            },
+            cltv_expiry_delta: 40,
+            htlc_minimum_msat: Some(1),
+            htlc_maximum_msat: None,
+        };
+        let candidates = vec![
+            (5_000_000, hop(peer(1), 1)),
+            // Only the larger of two channels with the same peer is hinted
+            (9_000_000, hop(peer(1), 2)),
+            (2_000_000, hop(peer(2), 3)),
+            (7_000_000, hop(peer(3), 4)),
+        ];
+        let scids = |hints: Vec<RouteHint>| -> Vec<u64> {
+            hints
+                .iter()
+                .map(|hint| hint.0[0].short_channel_id)
+                .collect()
+        };
+
+        assert_eq!(
+            scids(select_route_hints(candidates.clone(), 3)),
+            vec![2, 4, 3]
+        );
+        assert_eq!(scids(select_route_hints(candidates.clone(), 2)), vec![2, 4]);
+        assert!(select_route_hints(candidates, 0).is_empty());
+    }
+
+    #[test]
+    fn test_mpp_parts_respect_minimum_size() {
+        let config = MppConfig {
+            max_parts: 4,
+            min_part_msat: 100_000,
+        };
+        // Too small to split
+        assert_eq!(config.max_parts_for(50_000), 1);
+        assert_eq!(config.max_parts_for(199_999), 1);
+        // Every part still carries at least the minimum
+        assert_eq!(config.max_parts_for(350_000), 3);
+        assert_eq!(config.max_parts_for(10_000_000), 4);
+    }
+}
@@ -0,0 +1,353 @@
+//! Channel liquidity scoring and probing
+//!
+//! The probabilistic scorer learns channel liquidity from payment and probe outcomes and is
+//! persisted next to the network graph. While the background processor runs it updates,
+//! decays and persists the scorer itself; events handled outside of it are scored here.
+//!
+//! An optional prober periodically sends probes towards the destinations paid most often, so
+//! the scorer knows their routes before the next real payment.
+
+use bitcoin::secp256k1::PublicKey;
+use lightning::events::Event;
+use lightning::io;
+use lightning::routing::scoring::{ProbabilisticScoringDecayParameters, ScoreUpdate};
+use lightning::util::persist::{
+    KVStore, SCORER_PERSISTENCE_KEY, SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+    SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lightning::util::ser::{ReadableArgs, Writeable};
+use lightning_invoice::Bolt11Invoice;
+use lightning_persister::fs_store::FilesystemStore;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use ulw_core::traits::WalletStorage;
+use ulw_core::types::{Payment, PaymentDirection};
+use ulw_core::{Error, Result};
+
+use crate::node::{ChannelManager, LdkNode, NetworkGraph, Scorer, SimpleLogger};
+
+/// CLTV delta of the final hop of a probe
+const PROBE_FINAL_CLTV_EXPIRY_DELTA: u32 = 144;
+
+/// Settings for the background prober
+#[derive(Debug, Clone)]
+pub struct ProberConfig {
+    /// How often to probe
+    pub interval: Duration,
+    /// Amount to probe each destination with, in millisatoshis
+    pub probe_amount_msat: u64,
+    /// Most liquidity to lock up in probes per round, in millisatoshis
+    pub budget_msat: u64,
+    /// How many of the most frequently paid destinations to probe
+    pub max_destinations: usize,
+}
+
+impl Default for ProberConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(900),
+            probe_amount_msat: 10_000_000,
+            budget_msat: 50_000_000,
+            max_destinations: 5,
+        }
+    }
+}
+
+/// Restore the scorer from `persister`, or start with no knowledge of the network
+pub(crate) fn load_scorer(
+    persister: &FilesystemStore,
+    network_graph: Arc<NetworkGraph>,
+    logger: Arc<SimpleLogger>,
+) -> Result<Scorer> {
+    let decay_params = ProbabilisticScoringDecayParameters::default();
+    match persister.read(
+        SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+        SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+        SCORER_PERSISTENCE_KEY,
+    ) {
+        Ok(bytes) => Scorer::read(
+            &mut io::Cursor::new(bytes),
+            (decay_params, network_graph, logger),
+        )
+        .map_err(|e| Error::Storage(format!("Failed to read scorer: {:?}", e))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Ok(Scorer::new(decay_params, network_graph, logger))
+        }
+        Err(e) => Err(Error::Storage(format!("Failed to read scorer: {}", e))),
+    }
+}
+
+/// Write the scorer to `persister`
+pub(crate) fn persist_scorer(persister: &FilesystemStore, scorer: &RwLock<Scorer>) -> Result<()> {
+    persister
+        .write(
+            SCORER_PERSISTENCE_PRIMARY_NAMESPACE,
+            SCORER_PERSISTENCE_SECONDARY_NAMESPACE,
+            SCORER_PERSISTENCE_KEY,
+            &scorer.read().unwrap().encode(),
+        )
+        .map_err(|e| Error::Storage(format!("Failed to persist scorer: {}", e)))
+}
+
+/// Update the scorer from a payment or probe outcome
+///
+/// Mirrors the background processor. Returns whether the event told the scorer anything.
+pub(crate) fn score_event(scorer: &RwLock<Scorer>, event: &Event, now: Duration) -> bool {
+    let mut scorer = scorer.write().unwrap();
+    match event {
+        Event::PaymentPathFailed {
+            path,
+            short_channel_id: Some(scid),
+            ..
+        } => scorer.payment_path_failed(path, *scid, now),
+        // The destination failed it back, so the whole path had enough liquidity
+        Event::PaymentPathFailed {
+            path,
+            payment_failed_permanently: true,
+            ..
+        } => scorer.probe_successful(path, now),
+        Event::PaymentPathSuccessful { path, .. } => scorer.payment_path_successful(path, now),
+        Event::ProbeSuccessful { path, .. } => scorer.probe_successful(path, now),
+        Event::ProbeFailed {
+            path,
+            short_channel_id: Some(scid),
+            ..
+        } => scorer.probe_failed(path, *scid, now),
+        _ => return false,
+    }
+    true
+}
+
+/// Payees of outbound payments, most frequently paid first
+fn frequent_destinations(payments: &[Payment], max: usize) -> Vec<PublicKey> {
+    let mut counts: HashMap<PublicKey, usize> = HashMap::new();
+    for payment in payments {
+        if payment.direction != PaymentDirection::Outbound {
+            continue;
+        }
+        let Some(invoice) = payment
+            .invoice
+            .as_deref()
+            .and_then(|invoice| invoice.parse::<Bolt11Invoice>().ok())
+        else {
+            continue;
+        };
+        *counts.entry(invoice.recover_payee_pub_key()).or_default() += 1;
+    }
+
+    let mut destinations: Vec<_> = counts.into_iter().collect();
+    destinations.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    destinations
+        .into_iter()
+        .take(max)
+        .map(|(node_id, _)| node_id)
+        .collect()
+}
+
+/// Send one round of probes within the configured budget
+///
+/// Returns the number of probes sent.
+pub(crate) async fn probe_frequent_destinations(
+    channel_manager: &ChannelManager,
+    storage: &dyn WalletStorage,
+    config: &ProberConfig,
+) -> Result<usize> {
+    let payments = storage.list_payments().await?;
+    let mut budget_msat = config.budget_msat;
+    let mut sent = 0;
+
+    for node_id in frequent_destinations(&payments, config.max_destinations) {
+        if budget_msat < config.probe_amount_msat {
+            break;
+        }
+        match channel_manager.send_spontaneous_preflight_probes(
+            node_id,
+            config.probe_amount_msat,
+            PROBE_FINAL_CLTV_EXPIRY_DELTA,
+            None,
+        ) {
+            Ok(probes) => {
+                budget_msat -= config.probe_amount_msat;
+                sent += probes.len();
+            }
+            Err(e) => tracing::debug!("Could not probe {}: {:?}", node_id, e),
+        }
+    }
+
+    tracing::debug!("Sent {} probe(s)", sent);
+    Ok(sent)
+}
+
+impl LdkNode {
+    /// Probe the most frequently paid destinations while the node is running
+    ///
+    /// Takes effect on the next `start`.
+    pub fn enable_probing(&self, config: ProberConfig) {
+        *self.prober.lock().unwrap() = Some(config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::tests::test_node;
+    use crate::payments::InvoiceOptions;
+    use bitcoin::constants::ChainHash;
+    use bitcoin::Network;
+    use lightning::events::PathFailure;
+    use lightning::ln::channelmanager::PaymentId;
+    use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+    use lightning::ln::msgs::UnsignedChannelUpdate;
+    use lightning::ln::PaymentHash;
+    use lightning::routing::gossip::NodeId;
+    use lightning::routing::router::{Path, RouteHop};
+    use ulw_core::types::PaymentStatus;
+
+    const SNAPSHOT: &[u8] = include_bytes!("../fixtures/rgs_regtest.bin");
+    const SNAPSHOT_TIMESTAMP: u64 = 1_642_291_930;
+
+    /// A single-hop path over a snapshot channel, with fresh updates for both directions
+    fn announced_path(node: &LdkNode) -> (Path, u64, NodeId) {
+        let (scid, target) = {
+            let graph = node.network_graph.read_only();
+            let (scid, channel) = graph.channels().unordered_iter().next().unwrap();
+            (*scid, channel.node_two)
+        };
+        for channel_flags in [0, 1] {
+            node.network_graph
+                .update_channel_unsigned(&UnsignedChannelUpdate {
+                    chain_hash: ChainHash::using_genesis_block(Network::Regtest),
+                    short_channel_id: scid,
+                    timestamp: chrono::Utc::now().timestamp() as u32,
+                    message_flags: 1,
+                    channel_flags,
+                    cltv_expiry_delta: 40,
+                    htlc_minimum_msat: 1,
+                    htlc_maximum_msat: 5_000_000,
+                    fee_base_msat: 0,
+                    fee_proportional_millionths: 0,
+                    excess_data: Vec::new(),
+                })
+                .unwrap();
+        }
+        let path = Path {
+            hops: vec![RouteHop {
+                pubkey: target.as_pubkey().unwrap(),
+                node_features: NodeFeatures::empty(),
+                short_channel_id: scid,
+                channel_features: ChannelFeatures::empty(),
+                fee_msat: 1_000_000,
+                cltv_expiry_delta: 40,
+                maybe_announced_channel: true,
+            }],
+            blinded_tail: None,
+        };
+        (path, scid, target)
+    }
+
+    #[tokio::test]
+    async fn test_failed_path_updates_persisted_scorer() {
+        let (node, _temp) = test_node([70u8; 32]).await;
+        node.apply_rapid_gossip_snapshot(SNAPSHOT, SNAPSHOT_TIMESTAMP)
+            .unwrap();
+        let (path, scid, target) = announced_path(&node);
+        assert!(node
+            .scorer
+            .read()
+            .unwrap()
+            .estimated_channel_liquidity_range(scid, &target)
+            .is_none());
+
+        let event = Event::PaymentPathFailed {
+            payment_id: Some(PaymentId([1; 32])),
+            payment_hash: PaymentHash([1; 32]),
+            payment_failed_permanently: false,
+            failure: PathFailure::OnPath {
+                network_update: None,
+            },
+            path,
+            short_channel_id: Some(scid),
+        };
+        let now = Duration::from_secs(SNAPSHOT_TIMESTAMP);
+        assert!(score_event(&node.scorer, &event, now));
+
+        // The channel could not carry the amount, so the estimate no longer goes above it
+        let (_, max) = node
+            .scorer
+            .read()
+            .unwrap()
+            .estimated_channel_liquidity_range(scid, &target)
+            .unwrap();
+        assert!(max <= 1_000_000);
+
+        persist_scorer(&node.persister, &node.scorer).unwrap();
+        let restored = load_scorer(
+            &node.persister,
+            node.network_graph.clone(),
+            node.logger.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            restored.estimated_channel_liquidity_range(scid, &target),
+            node.scorer
+                .read()
+                .unwrap()
+                .estimated_channel_liquidity_range(scid, &target)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frequent_destinations_and_probing_budget() {
+        let (node, _temp) = test_node([71u8; 32]).await;
+        let (payee_a, _temp_a) = test_node([72u8; 32]).await;
+        let (payee_b, _temp_b) = test_node([73u8; 32]).await;
+
+        let mut payments = Vec::new();
+        for (payee, count) in [(&payee_a, 1), (&payee_b, 2)] {
+            for i in 0..count {
+                let invoice = payee
+                    .create_invoice(Some(1_000), format!("{}", i), &InvoiceOptions::default())
+                    .await
+                    .unwrap();
+                payments.push(Payment {
+                    payment_hash: format!("{}{}", payee.get_node_id(), i),
+                    amount_msat: 1_000,
+                    direction: PaymentDirection::Outbound,
+                    status: PaymentStatus::Succeeded,
+                    invoice: Some(invoice),
+                    created_at: chrono::Utc::now(),
+                    settled_at: None,
+                    preimage: None,
+                    fee_paid_msat: None,
+                    custom_tlvs: Vec::new(),
+                    parts: None,
+                    path_fees_msat: Vec::new(),
+                    path_ids: Vec::new(),
+                    offer_id: None,
+                    claim_deadline: None,
+                });
+            }
+        }
+
+        assert_eq!(
+            frequent_destinations(&payments, 5),
+            vec![payee_b.get_node_id(), payee_a.get_node_id()]
+        );
+        assert_eq!(frequent_destinations(&payments, 1).len(), 1);
+
+        // Without channels there is no route to probe over
+        for payment in &payments {
+            node.storage.save_payment(payment).await.unwrap();
+        }
+        let sent = probe_frequent_destinations(
+            &node.channel_manager,
+            &*node.storage,
+            &ProberConfig::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(sent, 0);
+    }
+}
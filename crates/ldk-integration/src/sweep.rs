@@ -0,0 +1,191 @@
+//! Sweeping of spendable outputs from closed channels into the on-chain wallet
+
+use bitcoin::{Amount, ScriptBuf};
+use lightning::chain::BestBlock;
+use lightning::io;
+use lightning::sign::{ChangeDestinationSource, KeysManager, SpendableOutputDescriptor};
+use lightning::util::persist::{
+    KVStore, OUTPUT_SWEEPER_PERSISTENCE_KEY, OUTPUT_SWEEPER_PERSISTENCE_PRIMARY_NAMESPACE,
+    OUTPUT_SWEEPER_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use lightning::util::ser::ReadableArgs;
+use lightning::util::sweep::{OutputSpendStatus, OutputSweeper, TrackedSpendableOutput};
+use lightning_persister::fs_store::FilesystemStore;
+use std::sync::Arc;
+
+use ulw_bdk::BdkWallet;
+use ulw_core::{Error, Result};
+
+use crate::broadcast::Broadcaster;
+use crate::chain::ChainSource;
+use crate::fees::WalletFeeEstimator;
+use crate::node::SimpleLogger;
+
+pub(crate) type Sweeper = OutputSweeper<
+    Arc<Broadcaster>,
+    Arc<WalletChangeDestination>,
+    Arc<WalletFeeEstimator>,
+    Arc<dyn ChainSource>,
+    Arc<FilesystemStore>,
+    Arc<SimpleLogger>,
+    Arc<KeysManager>,
+>;
+
+/// Hands out fresh on-chain wallet addresses as the destination of sweep transactions
+pub struct WalletChangeDestination {
+    wallet: Arc<BdkWallet>,
+}
+
+impl WalletChangeDestination {
+    pub fn new(wallet: Arc<BdkWallet>) -> Self {
+        Self { wallet }
+    }
+}
+
+impl ChangeDestinationSource for WalletChangeDestination {
+    fn get_change_destination_script(&self) -> std::result::Result<ScriptBuf, ()> {
+        // The sweeper retries on the next block if the wallet happens to be busy
+        self.wallet
+            .try_get_new_address()
+            .map(|address| address.script_pubkey())
+            .map_err(|e| tracing::warn!("No sweep destination available: {}", e))
+    }
+}
+
+/// Restore the output sweeper from `persister`, or start tracking from `best_block`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn load_sweeper(
+    persister: Arc<FilesystemStore>,
+    best_block: BestBlock,
+    broadcaster: Arc<Broadcaster>,
+    fee_estimator: Arc<WalletFeeEstimator>,
+    keys_manager: Arc<KeysManager>,
+    wallet: Arc<BdkWallet>,
+    chain_source: Arc<dyn ChainSource>,
+    logger: Arc<SimpleLogger>,
+) -> Result<Sweeper> {
+    let change_destination = Arc::new(WalletChangeDestination::new(wallet));
+
+    match persister.read(
+        OUTPUT_SWEEPER_PERSISTENCE_PRIMARY_NAMESPACE,
+        OUTPUT_SWEEPER_PERSISTENCE_SECONDARY_NAMESPACE,
+        OUTPUT_SWEEPER_PERSISTENCE_KEY,
+    ) {
+        Ok(bytes) => Sweeper::read(
+            &mut io::Cursor::new(bytes),
+            (
+                broadcaster,
+                fee_estimator,
+                Some(chain_source),
+                keys_manager,
+                change_destination,
+                persister.clone(),
+                logger,
+            ),
+        )
+        .map_err(|e| Error::Storage(format!("Failed to read output sweeper: {:?}", e))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Sweeper::new(
+            best_block,
+            broadcaster,
+            fee_estimator,
+            Some(chain_source),
+            keys_manager,
+            change_destination,
+            persister.clone(),
+            logger,
+        )),
+        Err(e) => Err(Error::Storage(format!(
+            "Failed to read output sweeper: {}",
+            e
+        ))),
+    }
+}
+
+/// Value of a spendable output descriptor
+fn descriptor_value(descriptor: &SpendableOutputDescriptor) -> Amount {
+    match descriptor {
+        SpendableOutputDescriptor::StaticOutput { output, .. } => output.value,
+        SpendableOutputDescriptor::DelayedPaymentOutput(d) => d.output.value,
+        SpendableOutputDescriptor::StaticPaymentOutput(d) => d.output.value,
+    }
+}
+
+/// Total value of tracked outputs whose sweep has not confirmed yet
+///
+/// Once a sweep confirms the funds show up in the on-chain wallet instead.
+pub(crate) fn pending_sweep_balance(outputs: &[TrackedSpendableOutput]) -> Amount {
+    outputs
+        .iter()
+        .filter(|o| {
+            !matches!(
+                o.status,
+                OutputSpendStatus::PendingThresholdConfirmations { .. }
+            )
+        })
+        .map(|o| descriptor_value(&o.descriptor))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, Transaction, TxOut, Txid};
+    use lightning::chain::transaction::OutPoint;
+
+    fn static_output(value: u64, status: OutputSpendStatus) -> TrackedSpendableOutput {
+        TrackedSpendableOutput {
+            descriptor: SpendableOutputDescriptor::StaticOutput {
+                outpoint: OutPoint {
+                    txid: Txid::all_zeros(),
+                    index: 0,
+                },
+                output: TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: ScriptBuf::new(),
+                },
+                channel_keys_id: None,
+            },
+            channel_id: None,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_pending_sweep_balance_excludes_confirmed_sweeps() {
+        let spending_tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let outputs = vec![
+            static_output(
+                10_000,
+                OutputSpendStatus::PendingInitialBroadcast {
+                    delayed_until_height: None,
+                },
+            ),
+            static_output(
+                20_000,
+                OutputSpendStatus::PendingFirstConfirmation {
+                    first_broadcast_hash: BlockHash::all_zeros(),
+                    latest_broadcast_height: 100,
+                    latest_spending_tx: spending_tx.clone(),
+                },
+            ),
+            static_output(
+                40_000,
+                OutputSpendStatus::PendingThresholdConfirmations {
+                    first_broadcast_hash: BlockHash::all_zeros(),
+                    latest_broadcast_height: 100,
+                    latest_spending_tx: spending_tx,
+                    confirmation_height: 101,
+                    confirmation_hash: BlockHash::all_zeros(),
+                },
+            ),
+        ];
+
+        assert_eq!(pending_sweep_balance(&outputs), Amount::from_sat(30_000));
+    }
+}
@@ -0,0 +1,480 @@
+//! Watchtower client
+//!
+//! After every commitment update, builds the transaction that would claim the counterparty's
+//! balance if they broadcast the commitment we just replaced, and signs it once they revoke
+//! that commitment. The signed justice transaction is sealed into a blob the tower can only open
+//! when the breach appears on chain, queued in the node's store and sent to the tower in the
+//! background, so breaches are punished while the wallet is offline.
+
+use bitcoin::{ScriptBuf, Transaction, Txid};
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use lightning::chain::chainmonitor::Persist;
+use lightning::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate};
+use lightning::chain::transaction::OutPoint;
+use lightning::chain::ChannelMonitorUpdateStatus;
+use lightning::ln::chan_utils::CommitmentTransaction;
+use lightning::sign::InMemorySigner;
+use lightning::util::persist::KVStore;
+use lightning_persister::fs_store::FilesystemStore;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ulw_core::{Error, Result};
+use ulw_tower::protocol::{Hint, JusticeBlob, TowerClient};
+
+use crate::fees::WalletFeeEstimator;
+use crate::node::LdkNode;
+
+/// How often queued blobs are sent to the tower while the node is running
+pub(crate) const TOWER_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+const WATCHTOWER_PRIMARY_NAMESPACE: &str = "watchtower";
+
+/// Justice transactions waiting for their commitment to be revoked, by channel
+const UNSIGNED_SECONDARY_NAMESPACE: &str = "unsigned";
+
+/// Sealed blobs waiting to be sent to the tower, by hint
+const PENDING_SECONDARY_NAMESPACE: &str = "pending";
+
+/// Where justice transactions pay to, kept so restarts do not use up wallet addresses
+const SWEEP_SCRIPT_KEY: &str = "sweep_script";
+
+/// A justice transaction for a counterparty commitment that has not been revoked yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UnsignedJustice {
+    commitment_txid: Txid,
+    justice_tx: Transaction,
+    value_sats: u64,
+    commitment_number: u64,
+}
+
+/// The tower blobs are sent to
+#[derive(Debug, Clone)]
+struct TowerConfig {
+    address: SocketAddr,
+    sweep_script: ScriptBuf,
+}
+
+/// Persists channel monitors and queues justice blobs for the tower as they change
+pub(crate) struct WatchtowerPersister {
+    store: Arc<FilesystemStore>,
+    fee_estimator: Arc<WalletFeeEstimator>,
+    tower: Mutex<Option<TowerConfig>>,
+    /// Set once we warned that commitments are not backed up for lack of a tower
+    warned_without_tower: AtomicBool,
+    /// Serializes updates to the unsigned justice transactions
+    unsigned: Mutex<()>,
+}
+
+impl WatchtowerPersister {
+    pub(crate) fn new(store: Arc<FilesystemStore>, fee_estimator: Arc<WalletFeeEstimator>) -> Self {
+        Self {
+            store,
+            fee_estimator,
+            tower: Mutex::new(None),
+            warned_without_tower: AtomicBool::new(false),
+            unsigned: Mutex::new(()),
+        }
+    }
+
+    /// Track the counterparty `commitments` of a channel, and sign justice transactions for any
+    /// that are now revoked
+    fn watch_commitments(
+        &self,
+        funding_txo: OutPoint,
+        monitor: &ChannelMonitor<InMemorySigner>,
+        commitments: Vec<CommitmentTransaction>,
+    ) -> Result<()> {
+        let Some(sweep_script) = self
+            .tower
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tower| tower.sweep_script.clone())
+        else {
+            if !self.warned_without_tower.swap(true, Ordering::Relaxed) {
+                tracing::warn!("No watchtower set, channel commitments are not being backed up");
+            } else {
+                tracing::debug!(
+                    "No watchtower set, not backing up commitments of {}",
+                    funding_txo
+                );
+            }
+            return Ok(());
+        };
+        // The tower cannot bump the justice transaction, so it pays for quick confirmation
+        let feerate_per_kw = self
+            .fee_estimator
+            .get_est_sat_per_1000_weight(ConfirmationTarget::UrgentOnChainSweep)
+            as u64;
+
+        let _guard = self.unsigned.lock().unwrap();
+        let key = format!("{}_{}", funding_txo.txid, funding_txo.index);
+        let mut unsigned: Vec<UnsignedJustice> = match self.store.read(
+            WATCHTOWER_PRIMARY_NAMESPACE,
+            UNSIGNED_SECONDARY_NAMESPACE,
+            &key,
+        ) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| Error::Storage(format!("Invalid justice transactions: {}", e)))?,
+            Err(e) if e.kind() == lightning::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(Error::Storage(e.to_string())),
+        };
+
+        for commitment in commitments {
+            let trusted = commitment.trust();
+            // Nothing to claim when the counterparty has no balance in this commitment
+            let Some(output_index) = trusted.revokeable_output_index() else {
+                continue;
+            };
+            // Fails when the output would not cover the fee
+            let Ok(justice_tx) =
+                trusted.build_to_local_justice_tx(feerate_per_kw, sweep_script.clone())
+            else {
+                continue;
+            };
+            unsigned.push(UnsignedJustice {
+                commitment_txid: trusted.txid(),
+                justice_tx,
+                value_sats: trusted.built_transaction().transaction.output[output_index]
+                    .value
+                    .to_sat(),
+                commitment_number: trusted.commitment_number(),
+            });
+        }
+
+        // Commitments are revoked in order, so stop at the first one that cannot be signed yet
+        let mut signed = 0;
+        for justice in &unsigned {
+            let Ok(justice_tx) = monitor.sign_to_local_justice_tx(
+                justice.justice_tx.clone(),
+                0,
+                justice.value_sats,
+                justice.commitment_number,
+            ) else {
+                break;
+            };
+            self.queue(&JusticeBlob::seal(&justice.commitment_txid, &justice_tx))?;
+            signed += 1;
+        }
+        unsigned.drain(..signed);
+
+        let json = serde_json::to_vec(&unsigned)
+            .map_err(|e| Error::Storage(format!("Failed to encode justice transactions: {}", e)))?;
+        self.store
+            .write(
+                WATCHTOWER_PRIMARY_NAMESPACE,
+                UNSIGNED_SECONDARY_NAMESPACE,
+                &key,
+                &json,
+            )
+            .map_err(|e| Error::Storage(e.to_string()))
+    }
+
+    /// Keep `blob` until the tower has it
+    pub(crate) fn queue(&self, blob: &JusticeBlob) -> Result<()> {
+        self.store
+            .write(
+                WATCHTOWER_PRIMARY_NAMESPACE,
+                PENDING_SECONDARY_NAMESPACE,
+                &hex::encode(blob.hint),
+                &blob.encrypted,
+            )
+            .map_err(|e| Error::Storage(format!("Failed to queue justice blob: {}", e)))
+    }
+
+    /// Number of blobs not yet sent to the tower
+    pub(crate) fn pending(&self) -> Result<usize> {
+        self.store
+            .list(WATCHTOWER_PRIMARY_NAMESPACE, PENDING_SECONDARY_NAMESPACE)
+            .map(|keys| keys.len())
+            .map_err(|e| Error::Storage(e.to_string()))
+    }
+
+    /// Send queued blobs to the tower, returning how many were sent
+    pub(crate) async fn flush(&self) -> Result<usize> {
+        let Some(address) = self.tower.lock().unwrap().as_ref().map(|t| t.address) else {
+            return Ok(0);
+        };
+        let keys = self
+            .store
+            .list(WATCHTOWER_PRIMARY_NAMESPACE, PENDING_SECONDARY_NAMESPACE)
+            .map_err(|e| Error::Storage(e.to_string()))?;
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let mut client = TowerClient::connect(address).await?;
+        for key in &keys {
+            let hint = hex::decode(key)
+                .ok()
+                .and_then(|hint| Hint::try_from(hint).ok())
+                .ok_or_else(|| Error::Storage(format!("Invalid justice blob key {}", key)))?;
+            let encrypted = self
+                .store
+                .read(
+                    WATCHTOWER_PRIMARY_NAMESPACE,
+                    PENDING_SECONDARY_NAMESPACE,
+                    key,
+                )
+                .map_err(|e| Error::Storage(e.to_string()))?;
+            client.store(&JusticeBlob { hint, encrypted }).await?;
+            self.store
+                .remove(
+                    WATCHTOWER_PRIMARY_NAMESPACE,
+                    PENDING_SECONDARY_NAMESPACE,
+                    key,
+                    false,
+                )
+                .map_err(|e| Error::Storage(e.to_string()))?;
+        }
+        tracing::debug!("Sent {} justice blobs to tower {}", keys.len(), address);
+        Ok(keys.len())
+    }
+}
+
+impl Persist<InMemorySigner> for WatchtowerPersister {
+    fn persist_new_channel(
+        &self,
+        funding_txo: OutPoint,
+        monitor: &ChannelMonitor<InMemorySigner>,
+    ) -> ChannelMonitorUpdateStatus {
+        let status = self.store.persist_new_channel(funding_txo, monitor);
+        if let Some(commitment) = monitor.initial_counterparty_commitment_tx() {
+            if let Err(e) = self.watch_commitments(funding_txo, monitor, vec![commitment]) {
+                tracing::error!("Failed to back up justice transaction: {}", e);
+            }
+        }
+        status
+    }
+
+    fn update_persisted_channel(
+        &self,
+        funding_txo: OutPoint,
+        update: Option<&ChannelMonitorUpdate>,
+        monitor: &ChannelMonitor<InMemorySigner>,
+    ) -> ChannelMonitorUpdateStatus {
+        let status = self
+            .store
+            .update_persisted_channel(funding_txo, update, monitor);
+        if let Some(update) = update {
+            let commitments = monitor.counterparty_commitment_txs_from_update(update);
+            if let Err(e) = self.watch_commitments(funding_txo, monitor, commitments) {
+                tracing::error!("Failed to back up justice transactions: {}", e);
+            }
+        }
+        status
+    }
+
+    fn archive_persisted_channel(&self, funding_txo: OutPoint) {
+        let key = format!("{}_{}", funding_txo.txid, funding_txo.index);
+        if let Err(e) = self.store.remove(
+            WATCHTOWER_PRIMARY_NAMESPACE,
+            UNSIGNED_SECONDARY_NAMESPACE,
+            &key,
+            false,
+        ) {
+            tracing::warn!("Failed to remove justice transactions of {}: {}", key, e);
+        }
+        Persist::<InMemorySigner>::archive_persisted_channel(&*self.store, funding_txo)
+    }
+}
+
+impl LdkNode {
+    /// Back up justice transactions for our channels to the watchtower at `address`
+    ///
+    /// Only commitments made from now on are covered. Justice transactions pay to an on-chain
+    /// wallet address that is kept across restarts.
+    pub async fn set_watchtower(&self, address: SocketAddr) -> Result<()> {
+        let sweep_script =
+            match self
+                .persister
+                .read(WATCHTOWER_PRIMARY_NAMESPACE, "", SWEEP_SCRIPT_KEY)
+            {
+                Ok(bytes) => ScriptBuf::from_bytes(bytes),
+                Err(e) if e.kind() == lightning::io::ErrorKind::NotFound => {
+                    let script = self.wallet.get_new_address().await?.script_pubkey();
+                    self.persister
+                        .write(
+                            WATCHTOWER_PRIMARY_NAMESPACE,
+                            "",
+                            SWEEP_SCRIPT_KEY,
+                            script.as_bytes(),
+                        )
+                        .map_err(|e| Error::Storage(e.to_string()))?;
+                    script
+                }
+                Err(e) => return Err(Error::Storage(e.to_string())),
+            };
+
+        tracing::info!("Backing up justice transactions to tower {}", address);
+        *self.watchtower.tower.lock().unwrap() = Some(TowerConfig {
+            address,
+            sweep_script,
+        });
+        Ok(())
+    }
+
+    /// Send queued justice transactions to the watchtower now, returning how many were sent
+    pub async fn flush_watchtower(&self) -> Result<usize> {
+        self.watchtower.flush().await
+    }
+
+    /// Number of justice transactions not yet sent to the watchtower
+    pub fn pending_watchtower_blobs(&self) -> Result<usize> {
+        self.watchtower.pending()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::tests::MockChainSource;
+    use crate::node::tests::{open_zero_conf_channel, test_node, test_node_with_chain_source};
+    use bitcoin::hashes::Hash;
+    use tokio::net::TcpListener;
+    use ulw_tower::test_utils::{spending_tx, MockChain};
+    use ulw_tower::Tower;
+
+    /// Start a tower on a mock chain with only a genesis block
+    async fn start_tower() -> (Arc<Tower>, Arc<MockChain>, SocketAddr) {
+        let chain = Arc::new(MockChain::default());
+        chain.mine(Vec::new());
+        let tower = Arc::new(Tower::new(chain.clone(), None).unwrap());
+        tower.scan().await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(tower.clone().serve(listener));
+        (tower, chain, address)
+    }
+
+    #[tokio::test]
+    async fn test_justice_blobs_reach_tower_and_punish_breach() {
+        let (node, _temp) = test_node([130u8; 32]).await;
+        let (tower, chain, address) = start_tower().await;
+
+        let breach = spending_tx(Txid::all_zeros(), 100_000);
+        node.set_watchtower(address).await.unwrap();
+        let sweep_script = node.watchtower.tower.lock().unwrap().clone().unwrap();
+        let mut justice = spending_tx(breach.compute_txid(), 90_000);
+        justice.output[0].script_pubkey = sweep_script.sweep_script.clone();
+        node.watchtower
+            .queue(&JusticeBlob::seal(&breach.compute_txid(), &justice))
+            .unwrap();
+        assert_eq!(node.pending_watchtower_blobs().unwrap(), 1);
+
+        assert_eq!(node.flush_watchtower().await.unwrap(), 1);
+        assert_eq!(node.pending_watchtower_blobs().unwrap(), 0);
+        assert_eq!(node.flush_watchtower().await.unwrap(), 0);
+        assert_eq!(tower.blob_count(), 1);
+
+        // The sweep destination survives setting the tower again
+        node.set_watchtower(address).await.unwrap();
+        assert_eq!(
+            node.watchtower
+                .tower
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .sweep_script,
+            sweep_script.sweep_script
+        );
+
+        // The wallet is offline when the peer broadcasts the revoked commitment
+        chain.mine(vec![breach.compute_txid()]);
+        assert_eq!(tower.scan().await.unwrap(), vec![justice.compute_txid()]);
+        assert_eq!(*chain.broadcasts.lock().unwrap(), vec![justice]);
+    }
+
+    #[tokio::test]
+    async fn test_revoked_commitment_is_punished_through_tower() {
+        let chain_source = Arc::new(MockChainSource::new());
+        let (node, _temp) = test_node_with_chain_source([132u8; 32], chain_source.clone()).await;
+        let (peer, _peer_temp) = test_node_with_chain_source([133u8; 32], chain_source).await;
+        let (tower, tower_chain, address) = start_tower().await;
+        node.set_watchtower(address).await.unwrap();
+        let sweep_script = node
+            .watchtower
+            .tower
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .sweep_script
+            .clone();
+
+        // A zero-conf channel with a balance on the peer's side, funded without the wallet
+        peer.set_accept_keysend(true);
+        let channel = open_zero_conf_channel(&node, &peer, 40_000_000).await;
+        let funding_txo = channel.funding_txo.unwrap();
+
+        // The peer's first commitment, which it revokes once the payment updates the channel
+        let revoked = node
+            .chain_monitor
+            .get_monitor(funding_txo)
+            .unwrap()
+            .initial_counterparty_commitment_tx()
+            .unwrap()
+            .trust()
+            .txid();
+        assert_eq!(node.pending_watchtower_blobs().unwrap(), 0);
+
+        let payment = node.send_keysend(peer.get_node_id(), 10_000_000, Vec::new());
+        let pump_peer = async {
+            loop {
+                peer.process_events().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::select! {
+            sent = payment => { sent.unwrap(); }
+            _ = pump_peer => unreachable!(),
+        }
+
+        // Revoking its first commitment left a signed justice transaction to send, later ones
+        // may still be in flight when the payment completes
+        let pending = node.pending_watchtower_blobs().unwrap();
+        assert!(pending >= 1);
+        assert_eq!(node.flush_watchtower().await.unwrap(), pending);
+        assert_eq!(tower.blob_count(), pending);
+
+        // The wallet is offline when the peer broadcasts its revoked commitment
+        tower_chain.mine(vec![revoked]);
+        let justice_txids = tower.scan().await.unwrap();
+        assert_eq!(justice_txids.len(), 1);
+
+        let broadcasts = tower_chain.broadcasts.lock().unwrap();
+        assert_eq!(broadcasts.len(), 1);
+        let justice = &broadcasts[0];
+        assert_eq!(justice.compute_txid(), justice_txids[0]);
+        assert_eq!(justice.input.len(), 1);
+        assert_eq!(justice.input[0].previous_output.txid, revoked);
+        assert!(!justice.input[0].witness.is_empty());
+        assert_eq!(justice.output[0].script_pubkey, sweep_script);
+    }
+
+    #[tokio::test]
+    async fn test_flush_keeps_blobs_while_tower_is_down() {
+        let (node, _temp) = test_node([131u8; 32]).await;
+
+        let breach = spending_tx(Txid::all_zeros(), 100_000);
+        let justice = spending_tx(breach.compute_txid(), 90_000);
+        node.watchtower
+            .queue(&JusticeBlob::seal(&breach.compute_txid(), &justice))
+            .unwrap();
+
+        // Without a tower the queue is left alone
+        assert_eq!(node.flush_watchtower().await.unwrap(), 0);
+
+        let unused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = unused.local_addr().unwrap();
+        drop(unused);
+        node.set_watchtower(address).await.unwrap();
+        assert!(node.flush_watchtower().await.is_err());
+        assert_eq!(node.pending_watchtower_blobs().unwrap(), 1);
+    }
+}
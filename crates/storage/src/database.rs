@@ -1,14 +1,168 @@
 //! Wallet database implementation
 
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::{params, Connection, OptionalExtension, Row};
 use std::path::Path;
 use std::sync::Mutex;
 use ulw_core::{
     traits::WalletStorage,
-    types::{ChannelInfo, Payment, PaymentDirection, PaymentStatus},
+    types::{
+        BroadcastRecord, ChannelInfo, ChannelState, LnurlAuthRecord, OfferKind, OfferRecord,
+        Payment, PaymentDirection, PaymentStatus,
+    },
     Error, Result,
 };
 
+use crate::migrations::run_migrations;
+
+const PAYMENT_COLUMNS: &str =
+    "payment_hash, amount_msat, direction, status, invoice, created_at, settled_at, \
+     preimage, fee_paid_msat, custom_tlvs, parts, path_fees_msat, offer_id, \
+     claim_deadline, path_ids";
+
+fn payment_from_row(row: &Row) -> rusqlite::Result<Payment> {
+    Ok(Payment {
+        payment_hash: row.get(0)?,
+        amount_msat: row.get::<_, i64>(1)? as u64,
+        direction: direction_from_str(&row.get::<_, String>(2)?),
+        status: match row.get::<_, String>(3)?.as_str() {
+            "pending" => PaymentStatus::Pending,
+            "held" => PaymentStatus::Held,
+            "succeeded" => PaymentStatus::Succeeded,
+            _ => PaymentStatus::Failed,
+        },
+        invoice: row.get(4)?,
+        created_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
+            .unwrap()
+            .into(),
+        settled_at: row
+            .get::<_, Option<String>>(6)?
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.into()),
+        preimage: row.get(7)?,
+        fee_paid_msat: row.get::<_, Option<i64>>(8)?.map(|fee| fee as u64),
+        custom_tlvs: row
+            .get::<_, Option<String>>(9)?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        parts: row.get::<_, Option<i64>>(10)?.map(|parts| parts as u32),
+        path_fees_msat: row
+            .get::<_, Option<String>>(11)?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        offer_id: row.get(12)?,
+        claim_deadline: row.get(13)?,
+        path_ids: row
+            .get::<_, Option<String>>(14)?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+    })
+}
+
+/// JSON encoding of `values`, or NULL when there are none
+fn optional_json<T: serde::Serialize>(values: &[T]) -> Result<Option<String>> {
+    if values.is_empty() {
+        return Ok(None);
+    }
+    serde_json::to_string(values)
+        .map(Some)
+        .map_err(|e| Error::Internal(e.to_string()))
+}
+
+fn direction_str(direction: PaymentDirection) -> &'static str {
+    match direction {
+        PaymentDirection::Inbound => "inbound",
+        PaymentDirection::Outbound => "outbound",
+    }
+}
+
+fn direction_from_str(direction: &str) -> PaymentDirection {
+    match direction {
+        "inbound" => PaymentDirection::Inbound,
+        _ => PaymentDirection::Outbound,
+    }
+}
+
+const OFFER_COLUMNS: &str =
+    "id, kind, direction, encoded, amount_msat, description, quantity_max, created_at";
+
+fn offer_from_row(row: &Row) -> rusqlite::Result<OfferRecord> {
+    Ok(OfferRecord {
+        id: row.get(0)?,
+        kind: match row.get::<_, String>(1)?.as_str() {
+            "refund" => OfferKind::Refund,
+            _ => OfferKind::Offer,
+        },
+        direction: direction_from_str(&row.get::<_, String>(2)?),
+        encoded: row.get(3)?,
+        amount_msat: row.get::<_, Option<i64>>(4)?.map(|amount| amount as u64),
+        description: row.get(5)?,
+        quantity_max: row
+            .get::<_, Option<i64>>(6)?
+            .map(|quantity| quantity as u64),
+        created_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(7)?)
+            .unwrap()
+            .into(),
+    })
+}
+
+const LNURL_AUTH_COLUMNS: &str = "domain, linking_key, first_login_at, last_login_at";
+
+fn lnurl_auth_from_row(row: &Row) -> rusqlite::Result<LnurlAuthRecord> {
+    Ok(LnurlAuthRecord {
+        domain: row.get(0)?,
+        linking_key: row.get(1)?,
+        first_login_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(2)?)
+            .unwrap()
+            .into(),
+        last_login_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+            .unwrap()
+            .into(),
+    })
+}
+
+const CHANNEL_COLUMNS: &str =
+    "channel_id, counterparty_node_id, capacity_sats, local_balance_msat, \
+     remote_balance_msat, state, funding_txo, closing_txid, claimable_at_height";
+
+fn channel_from_row(row: &Row) -> rusqlite::Result<ChannelInfo> {
+    Ok(ChannelInfo {
+        channel_id: row.get(0)?,
+        counterparty_node_id: row.get(1)?,
+        capacity_sats: row.get::<_, i64>(2)? as u64,
+        local_balance_msat: row.get::<_, i64>(3)? as u64,
+        remote_balance_msat: row.get::<_, i64>(4)? as u64,
+        state: match row.get::<_, String>(5)?.as_str() {
+            "opening" => ChannelState::Opening,
+            "active" => ChannelState::Active,
+            "closing" => ChannelState::Closing,
+            _ => ChannelState::Closed,
+        },
+        funding_txo: row.get(6)?,
+        closing_txid: row.get(7)?,
+        claimable_at_height: row.get(8)?,
+    })
+}
+
+const BROADCAST_COLUMNS: &str =
+    "txid, raw_tx, attempts, last_error, created_at, last_attempt_at, confirmed";
+
+fn broadcast_from_row(row: &Row) -> rusqlite::Result<BroadcastRecord> {
+    Ok(BroadcastRecord {
+        txid: row.get(0)?,
+        raw_tx: row.get(1)?,
+        attempts: row.get(2)?,
+        last_error: row.get(3)?,
+        created_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+            .unwrap()
+            .into(),
+        last_attempt_at: row
+            .get::<_, Option<String>>(5)?
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.into()),
+        confirmed: row.get(6)?,
+    })
+}
+
 pub struct WalletDatabase {
     conn: Mutex<Connection>,
 }
@@ -51,33 +205,43 @@ impl WalletDatabase {
                 ",
         )
         .map_err(|e| Error::Storage(e.to_string()))?;
-        Ok(())
+        run_migrations(&conn)
     }
 }
 
 #[async_trait::async_trait]
 impl WalletStorage for WalletDatabase {
     async fn save_payment(&self, payment: &Payment) -> Result<()> {
+        let custom_tlvs = optional_json(&payment.custom_tlvs)?;
+        let path_fees_msat = optional_json(&payment.path_fees_msat)?;
+        let path_ids = optional_json(&payment.path_ids)?;
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT OR REPLACE INTO payments
-                (payment_hash, amount_msat, direction, status, invoice, created_at, settled_at)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            &format!(
+                "INSERT OR REPLACE INTO payments ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                PAYMENT_COLUMNS
+            ),
             params![
                 payment.payment_hash,
                 payment.amount_msat as i64,
-                match payment.direction {
-                    PaymentDirection::Inbound => "inbound",
-                    PaymentDirection::Outbound => "outbound",
-                },
+                direction_str(payment.direction),
                 match payment.status {
                     PaymentStatus::Pending => "pending",
+                    PaymentStatus::Held => "held",
                     PaymentStatus::Succeeded => "succeeded",
                     PaymentStatus::Failed => "failed",
                 },
                 payment.invoice,
                 payment.created_at.to_rfc3339(),
                 payment.settled_at.as_ref().map(|t| t.to_rfc3339()),
+                payment.preimage,
+                payment.fee_paid_msat.map(|fee| fee as i64),
+                custom_tlvs,
+                payment.parts.map(|parts| parts as i64),
+                path_fees_msat,
+                payment.offer_id,
+                payment.claim_deadline,
+                path_ids,
             ],
         )
         .map_err(|e| Error::Storage(e.to_string()))?;
@@ -86,36 +250,15 @@ impl WalletStorage for WalletDatabase {
 
     async fn get_payment(&self, payment_hash: &str) -> Result<Option<Payment>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-                "SELECT payment_hash, amount_msat, direction, status, invoice, created_at, settled_at
-                FROM payments WHERE payment_hash = ?1",
-            )
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM payments WHERE payment_hash = ?1",
+                PAYMENT_COLUMNS
+            ))
             .map_err(|e| Error::Storage(e.to_string()))?;
 
         let payment = stmt
-            .query_row(params![payment_hash], |row| {
-                Ok(Payment {
-                    payment_hash: row.get(0)?,
-                    amount_msat: row.get::<_, i64>(1)? as u64,
-                    direction: match row.get::<_, String>(2)?.as_str() {
-                        "inbound" => PaymentDirection::Inbound,
-                        _ => PaymentDirection::Outbound,
-                    },
-                    status: match row.get::<_, String>(3)?.as_str() {
-                        "pending" => PaymentStatus::Pending,
-                        "succeeded" => PaymentStatus::Succeeded,
-                        _ => PaymentStatus::Failed,
-                    },
-                    invoice: row.get(4)?,
-                    created_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
-                        .unwrap()
-                        .into(),
-                    settled_at: row
-                        .get::<_, Option<String>>(6)?
-                        .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
-                        .map(|dt| dt.into()),
-                })
-            })
+            .query_row(params![payment_hash], payment_from_row)
             .optional()
             .map_err(|e| Error::Storage(e.to_string()))?;
 
@@ -124,36 +267,15 @@ impl WalletStorage for WalletDatabase {
 
     async fn list_payments(&self) -> Result<Vec<Payment>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-                "SELECT payment_hash, amount_msat, direction, status, invoice, created_at, settled_at
-                FROM payments ORDER BY created_at DESC",
-            )
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM payments ORDER BY created_at DESC",
+                PAYMENT_COLUMNS
+            ))
             .map_err(|e| Error::Storage(e.to_string()))?;
 
         let payments = stmt
-            .query_map([], |row| {
-                Ok(Payment {
-                    payment_hash: row.get(0)?,
-                    amount_msat: row.get::<_, i64>(1)? as u64,
-                    direction: match row.get::<_, String>(2)?.as_str() {
-                        "inbound" => PaymentDirection::Inbound,
-                        _ => PaymentDirection::Outbound,
-                    },
-                    status: match row.get::<_, String>(3)?.as_str() {
-                        "pending" => PaymentStatus::Pending,
-                        "succeeded" => PaymentStatus::Succeeded,
-                        _ => PaymentStatus::Failed,
-                    },
-                    invoice: row.get(4)?,
-                    created_at: chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
-                        .unwrap()
-                        .into(),
-                    settled_at: row
-                        .get::<_, Option<String>>(6)?
-                        .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
-                        .map(|dt| dt.into()),
-                })
-            })
+            .query_map([], payment_from_row)
             .map_err(|e| Error::Storage(e.to_string()))?
             .collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| Error::Storage(e.to_string()))?;
@@ -164,46 +286,37 @@ impl WalletStorage for WalletDatabase {
     async fn save_channel(&self, channel: &ChannelInfo) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-                "INSERT OR REPLACE INTO channels
-                (channel_id, counterparty_node_id, capacity_sats, local_balance_msat, remote_balance_msat, state)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                params![
-                    channel.channel_id,
-                    channel.counterparty_node_id,
-                    channel.capacity_sats as i64,
-                    channel.local_balance_msat as i64,
-                    channel.remote_balance_msat as i64,
-                    format!("{:?}", channel.state).to_lowercase(),
-                ],
-            )
-            .map_err(|e| Error::Storage(e.to_string()))?;
+            &format!(
+                "INSERT OR REPLACE INTO channels ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                CHANNEL_COLUMNS
+            ),
+            params![
+                channel.channel_id,
+                channel.counterparty_node_id,
+                channel.capacity_sats as i64,
+                channel.local_balance_msat as i64,
+                channel.remote_balance_msat as i64,
+                format!("{:?}", channel.state).to_lowercase(),
+                channel.funding_txo,
+                channel.closing_txid,
+                channel.claimable_at_height,
+            ],
+        )
+        .map_err(|e| Error::Storage(e.to_string()))?;
         Ok(())
     }
 
     async fn get_channel(&self, channel_id: &str) -> Result<Option<ChannelInfo>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-                "SELECT channel_id, counterparty_node_id, capacity_sats, local_balance_msat, remote_balance_msat, state
-                FROM channels WHERE channel_id = ?1",
-            )
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM channels WHERE channel_id = ?1",
+                CHANNEL_COLUMNS
+            ))
             .map_err(|e| Error::Storage(e.to_string()))?;
 
         let channel = stmt
-            .query_row(params![channel_id], |row| {
-                Ok(ChannelInfo {
-                    channel_id: row.get(0)?,
-                    counterparty_node_id: row.get(1)?,
-                    capacity_sats: row.get::<_, i64>(2)? as u64,
-                    local_balance_msat: row.get::<_, i64>(3)? as u64,
-                    remote_balance_msat: row.get::<_, i64>(4)? as u64,
-                    state: match row.get::<_, String>(5)?.as_str() {
-                        "opening" => ulw_core::types::ChannelState::Opening,
-                        "active" => ulw_core::types::ChannelState::Active,
-                        "closing" => ulw_core::types::ChannelState::Closing,
-                        _ => ulw_core::types::ChannelState::Closed,
-                    },
-                })
-            })
+            .query_row(params![channel_id], channel_from_row)
             .optional()
             .map_err(|e| Error::Storage(e.to_string()))?;
 
@@ -212,34 +325,190 @@ impl WalletStorage for WalletDatabase {
 
     async fn list_channels(&self) -> Result<Vec<ChannelInfo>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-                "SELECT channel_id, counterparty_node_id, capacity_sats, local_balance_msat, remote_balance_msat, state
-                FROM channels ORDER BY channel_id",
-            )
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM channels ORDER BY channel_id",
+                CHANNEL_COLUMNS
+            ))
             .map_err(|e| Error::Storage(e.to_string()))?;
 
         let channels = stmt
-            .query_map([], |row| {
-                Ok(ChannelInfo {
-                    channel_id: row.get(0)?,
-                    counterparty_node_id: row.get(1)?,
-                    capacity_sats: row.get::<_, i64>(2)? as u64,
-                    local_balance_msat: row.get::<_, i64>(3)? as u64,
-                    remote_balance_msat: row.get::<_, i64>(4)? as u64,
-                    state: match row.get::<_, String>(5)?.as_str() {
-                        "opening" => ulw_core::types::ChannelState::Opening,
-                        "active" => ulw_core::types::ChannelState::Active,
-                        "closing" => ulw_core::types::ChannelState::Closing,
-                        _ => ulw_core::types::ChannelState::Closed,
-                    },
-                })
-            })
+            .query_map([], channel_from_row)
             .map_err(|e| Error::Storage(e.to_string()))?
             .collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| Error::Storage(e.to_string()))?;
 
         Ok(channels)
     }
+
+    async fn save_broadcast(&self, record: &BroadcastRecord) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO broadcasts ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                BROADCAST_COLUMNS
+            ),
+            params![
+                record.txid,
+                record.raw_tx,
+                record.attempts,
+                record.last_error,
+                record.created_at.to_rfc3339(),
+                record.last_attempt_at.as_ref().map(|t| t.to_rfc3339()),
+                record.confirmed,
+            ],
+        )
+        .map_err(|e| Error::Storage(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_broadcast(&self, txid: &str) -> Result<Option<BroadcastRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM broadcasts WHERE txid = ?1",
+                BROADCAST_COLUMNS
+            ))
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        let record = stmt
+            .query_row(params![txid], broadcast_from_row)
+            .optional()
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        Ok(record)
+    }
+
+    async fn list_unconfirmed_broadcasts(&self) -> Result<Vec<BroadcastRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM broadcasts WHERE confirmed = 0 ORDER BY created_at",
+                BROADCAST_COLUMNS
+            ))
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        let records = stmt
+            .query_map([], broadcast_from_row)
+            .map_err(|e| Error::Storage(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        Ok(records)
+    }
+
+    async fn save_offer(&self, offer: &OfferRecord) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO offers ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                OFFER_COLUMNS
+            ),
+            params![
+                offer.id,
+                match offer.kind {
+                    OfferKind::Offer => "offer",
+                    OfferKind::Refund => "refund",
+                },
+                direction_str(offer.direction),
+                offer.encoded,
+                offer.amount_msat.map(|amount| amount as i64),
+                offer.description,
+                offer.quantity_max.map(|quantity| quantity as i64),
+                offer.created_at.to_rfc3339(),
+            ],
+        )
+        .map_err(|e| Error::Storage(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_offer(&self, id: &str) -> Result<Option<OfferRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM offers WHERE id = ?1",
+                OFFER_COLUMNS
+            ))
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        let offer = stmt
+            .query_row(params![id], offer_from_row)
+            .optional()
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        Ok(offer)
+    }
+
+    async fn list_offers(&self) -> Result<Vec<OfferRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM offers ORDER BY created_at DESC",
+                OFFER_COLUMNS
+            ))
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        let offers = stmt
+            .query_map([], offer_from_row)
+            .map_err(|e| Error::Storage(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        Ok(offers)
+    }
+
+    async fn save_lnurl_auth(&self, record: &LnurlAuthRecord) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO lnurl_auths ({}) VALUES (?1, ?2, ?3, ?4)",
+                LNURL_AUTH_COLUMNS
+            ),
+            params![
+                record.domain,
+                record.linking_key,
+                record.first_login_at.to_rfc3339(),
+                record.last_login_at.to_rfc3339(),
+            ],
+        )
+        .map_err(|e| Error::Storage(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_lnurl_auth(&self, domain: &str) -> Result<Option<LnurlAuthRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM lnurl_auths WHERE domain = ?1",
+                LNURL_AUTH_COLUMNS
+            ))
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        let record = stmt
+            .query_row(params![domain], lnurl_auth_from_row)
+            .optional()
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        Ok(record)
+    }
+
+    async fn list_lnurl_auths(&self) -> Result<Vec<LnurlAuthRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM lnurl_auths ORDER BY last_login_at DESC",
+                LNURL_AUTH_COLUMNS
+            ))
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        let records = stmt
+            .query_map([], lnurl_auth_from_row)
+            .map_err(|e| Error::Storage(e.to_string()))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::Storage(e.to_string()))?;
+
+        Ok(records)
+    }
 }
 
 #[cfg(test)]
@@ -260,6 +529,14 @@ mod tests {
             invoice: Some("lnbc...".to_string()),
             created_at: chrono::Utc::now(),
             settled_at: None,
+            preimage: None,
+            fee_paid_msat: None,
+            custom_tlvs: Vec::new(),
+            parts: None,
+            path_fees_msat: Vec::new(),
+            path_ids: Vec::new(),
+            offer_id: None,
+            claim_deadline: None,
         };
 
         // Save payment
@@ -269,9 +546,184 @@ mod tests {
         let retrieved = db.get_payment("test_hash").await.unwrap().unwrap();
         assert_eq!(retrieved.payment_hash, "test_hash");
         assert_eq!(retrieved.amount_msat, 1000);
+        assert!(retrieved.preimage.is_none());
+
+        // HTLCs of a hold invoice arrive
+        let mut held = payment.clone();
+        held.status = PaymentStatus::Held;
+        held.claim_deadline = Some(850);
+        db.save_payment(&held).await.unwrap();
+
+        let retrieved = db.get_payment("test_hash").await.unwrap().unwrap();
+        assert_eq!(retrieved.status, PaymentStatus::Held);
+        assert_eq!(retrieved.claim_deadline, Some(850));
+
+        // Record the result
+        let mut settled = payment.clone();
+        settled.status = PaymentStatus::Succeeded;
+        settled.settled_at = Some(chrono::Utc::now());
+        settled.preimage = Some("ab".repeat(32));
+        settled.fee_paid_msat = Some(12);
+        settled.custom_tlvs = vec![(65_537, vec![0xca, 0xfe])];
+        settled.parts = Some(2);
+        settled.path_fees_msat = vec![5, 7];
+        settled.path_ids = vec!["aa".to_string(), "bb".to_string()];
+        db.save_payment(&settled).await.unwrap();
+
+        let retrieved = db.get_payment("test_hash").await.unwrap().unwrap();
+        assert_eq!(retrieved.status, PaymentStatus::Succeeded);
+        assert_eq!(retrieved.preimage, settled.preimage);
+        assert_eq!(retrieved.fee_paid_msat, Some(12));
+        assert_eq!(retrieved.custom_tlvs, settled.custom_tlvs);
+        assert_eq!(retrieved.parts, Some(2));
+        assert_eq!(retrieved.path_fees_msat, vec![5, 7]);
+        assert_eq!(retrieved.path_ids, vec!["aa", "bb"]);
 
         // List payments
         let payments = db.list_payments().await.unwrap();
         assert_eq!(payments.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_channel_close_details() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = WalletDatabase::new(temp_file.path()).unwrap();
+
+        let mut channel = ChannelInfo {
+            channel_id: "chan".to_string(),
+            counterparty_node_id: "peer".to_string(),
+            capacity_sats: 100_000,
+            local_balance_msat: 60_000_000,
+            remote_balance_msat: 40_000_000,
+            state: ChannelState::Active,
+            funding_txo: Some("txid:0".to_string()),
+            closing_txid: None,
+            claimable_at_height: None,
+        };
+        db.save_channel(&channel).await.unwrap();
+
+        channel.state = ChannelState::Closed;
+        channel.closing_txid = Some("closing".to_string());
+        channel.claimable_at_height = Some(900);
+        db.save_channel(&channel).await.unwrap();
+
+        let retrieved = db.get_channel("chan").await.unwrap().unwrap();
+        assert_eq!(retrieved.state, ChannelState::Closed);
+        assert_eq!(retrieved.closing_txid.as_deref(), Some("closing"));
+        assert_eq!(retrieved.claimable_at_height, Some(900));
+        assert_eq!(db.list_channels().await.unwrap().len(), 1);
+
+        // Reopening an existing database must not re-run migrations
+        drop(db);
+        let db = WalletDatabase::new(temp_file.path()).unwrap();
+        assert!(db.get_channel("chan").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_records() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = WalletDatabase::new(temp_file.path()).unwrap();
+
+        let mut record = BroadcastRecord {
+            txid: "txid".to_string(),
+            raw_tx: "0200".to_string(),
+            attempts: 1,
+            last_error: Some("timeout".to_string()),
+            created_at: chrono::Utc::now(),
+            last_attempt_at: Some(chrono::Utc::now()),
+            confirmed: false,
+        };
+        db.save_broadcast(&record).await.unwrap();
+        assert_eq!(db.list_unconfirmed_broadcasts().await.unwrap().len(), 1);
+
+        record.attempts = 2;
+        record.last_error = None;
+        record.confirmed = true;
+        db.save_broadcast(&record).await.unwrap();
+
+        let retrieved = db.get_broadcast("txid").await.unwrap().unwrap();
+        assert_eq!(retrieved.attempts, 2);
+        assert!(retrieved.last_error.is_none());
+        assert!(retrieved.confirmed);
+        assert!(db.list_unconfirmed_broadcasts().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_offer_records() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = WalletDatabase::new(temp_file.path()).unwrap();
+
+        let offer = OfferRecord {
+            id: "offer_id".to_string(),
+            kind: OfferKind::Offer,
+            direction: PaymentDirection::Inbound,
+            encoded: "lno1...".to_string(),
+            amount_msat: Some(5_000),
+            description: Some("Coffee".to_string()),
+            quantity_max: Some(3),
+            created_at: chrono::Utc::now() - chrono::Duration::minutes(1),
+        };
+        let refund = OfferRecord {
+            id: "payment_id".to_string(),
+            kind: OfferKind::Refund,
+            direction: PaymentDirection::Outbound,
+            encoded: "lnr1...".to_string(),
+            amount_msat: Some(2_000),
+            description: None,
+            quantity_max: None,
+            created_at: chrono::Utc::now(),
+        };
+        db.save_offer(&offer).await.unwrap();
+        db.save_offer(&refund).await.unwrap();
+
+        let retrieved = db.get_offer("offer_id").await.unwrap().unwrap();
+        assert_eq!(retrieved.kind, OfferKind::Offer);
+        assert_eq!(retrieved.direction, PaymentDirection::Inbound);
+        assert_eq!(retrieved.quantity_max, Some(3));
+        assert_eq!(retrieved.description.as_deref(), Some("Coffee"));
+        assert!(db.get_offer("missing").await.unwrap().is_none());
+
+        let offers = db.list_offers().await.unwrap();
+        assert_eq!(offers.len(), 2);
+        assert_eq!(offers[0].id, "payment_id");
+        assert_eq!(offers[0].kind, OfferKind::Refund);
+    }
+
+    #[tokio::test]
+    async fn test_lnurl_auth_records() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = WalletDatabase::new(temp_file.path()).unwrap();
+
+        let first_login = chrono::Utc::now() - chrono::Duration::days(1);
+        let mut record = LnurlAuthRecord {
+            domain: "site.com".to_string(),
+            linking_key: "02aa".to_string(),
+            first_login_at: first_login,
+            last_login_at: first_login,
+        };
+        db.save_lnurl_auth(&record).await.unwrap();
+        db.save_lnurl_auth(&LnurlAuthRecord {
+            domain: "other.com".to_string(),
+            linking_key: "03bb".to_string(),
+            first_login_at: first_login - chrono::Duration::days(1),
+            last_login_at: first_login - chrono::Duration::days(1),
+        })
+        .await
+        .unwrap();
+
+        record.last_login_at = chrono::Utc::now();
+        db.save_lnurl_auth(&record).await.unwrap();
+
+        let retrieved = db.get_lnurl_auth("site.com").await.unwrap().unwrap();
+        assert_eq!(retrieved.linking_key, "02aa");
+        assert_eq!(
+            retrieved.first_login_at.timestamp(),
+            first_login.timestamp()
+        );
+        assert!(db.get_lnurl_auth("missing.com").await.unwrap().is_none());
+
+        let records = db.list_lnurl_auths().await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].domain, "site.com");
+    }
 }
@@ -1,8 +1,74 @@
 //! Database migrations
 
-use ulw_core::Result;
+use rusqlite::Connection;
+use ulw_core::{Error, Result};
+
+/// Schema changes applied on top of the base schema, in order.
+///
+/// The database `user_version` records how many of these have been applied.
+const MIGRATIONS: &[&str] = &[
+    // 1: funding and closing details for channels
+    "ALTER TABLE channels ADD COLUMN funding_txo TEXT;
+     ALTER TABLE channels ADD COLUMN closing_txid TEXT;
+     ALTER TABLE channels ADD COLUMN claimable_at_height INTEGER;",
+    // 2: payment results
+    "ALTER TABLE payments ADD COLUMN preimage TEXT;
+     ALTER TABLE payments ADD COLUMN fee_paid_msat INTEGER;",
+    // 3: transactions broadcast on behalf of the Lightning node
+    "CREATE TABLE broadcasts (
+         txid TEXT PRIMARY KEY,
+         raw_tx TEXT NOT NULL,
+         attempts INTEGER NOT NULL,
+         last_error TEXT,
+         created_at TEXT NOT NULL,
+         last_attempt_at TEXT,
+         confirmed INTEGER NOT NULL
+     );
+     CREATE INDEX idx_broadcasts_confirmed ON broadcasts(confirmed);",
+    // 4: custom TLV records of keysend payments
+    "ALTER TABLE payments ADD COLUMN custom_tlvs TEXT;",
+    // 5: multi-path payment details
+    "ALTER TABLE payments ADD COLUMN parts INTEGER;
+     ALTER TABLE payments ADD COLUMN path_fees_msat TEXT;",
+    // 6: BOLT12 offers and refunds
+    "CREATE TABLE offers (
+         id TEXT PRIMARY KEY,
+         kind TEXT NOT NULL,
+         direction TEXT NOT NULL,
+         encoded TEXT NOT NULL,
+         amount_msat INTEGER,
+         description TEXT,
+         quantity_max INTEGER,
+         created_at TEXT NOT NULL
+     );
+     CREATE INDEX idx_offers_created ON offers(created_at DESC);
+     ALTER TABLE payments ADD COLUMN offer_id TEXT;",
+    // 7: hold invoices
+    "ALTER TABLE payments ADD COLUMN claim_deadline INTEGER;",
+    // 8: services logged in to with LNURL-auth
+    "CREATE TABLE lnurl_auths (
+         domain TEXT PRIMARY KEY,
+         linking_key TEXT NOT NULL,
+         first_login_at TEXT NOT NULL,
+         last_login_at TEXT NOT NULL
+     );",
+    // 9: successful paths of multi-path payments
+    "ALTER TABLE payments ADD COLUMN path_ids TEXT;",
+];
+
+pub fn run_migrations(conn: &Connection) -> Result<()> {
+    let version = conn
+        .query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| Error::Storage(e.to_string()))? as usize;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            i + 1
+        ))
+        .map_err(|e| Error::Storage(format!("Migration {} failed: {}", i + 1, e)))?;
+    }
 
-pub fn run_migrations() -> Result<()> {
-    // Placeholder for future migrations
     Ok(())
 }
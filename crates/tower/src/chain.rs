@@ -0,0 +1,86 @@
+//! Chain access for the tower
+//!
+//! The tower only needs the txids of each new block and a way to broadcast, which any Esplora
+//! server provides.
+
+use async_trait::async_trait;
+use bitcoin::consensus::encode;
+use bitcoin::{Transaction, Txid};
+
+use ulw_core::{Error, Result};
+
+/// The chain the tower watches for breaches
+#[async_trait]
+pub trait TowerChain: Send + Sync {
+    /// Height of the best block
+    async fn tip_height(&self) -> Result<u32>;
+
+    /// Txids of the transactions in the best chain's block at `height`
+    async fn block_txids(&self, height: u32) -> Result<Vec<Txid>>;
+
+    /// Hand `tx` to the network
+    async fn broadcast(&self, tx: &Transaction) -> Result<()>;
+}
+
+/// Chain data from an Esplora HTTP API
+pub struct EsploraChain {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl EsploraChain {
+    /// Use the Esplora API at `base_url`, e.g. `http://localhost:3002`
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn get_text(&self, path: &str) -> Result<String> {
+        let url = format!("{}{}", self.base_url, path);
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| Error::Network(format!("Failed to fetch {}: {}", url, e)))?
+            .text()
+            .await
+            .map_err(|e| Error::Network(format!("Failed to read {}: {}", url, e)))
+    }
+}
+
+#[async_trait]
+impl TowerChain for EsploraChain {
+    async fn tip_height(&self) -> Result<u32> {
+        self.get_text("/blocks/tip/height")
+            .await?
+            .trim()
+            .parse()
+            .map_err(|e| Error::Network(format!("Invalid tip height: {}", e)))
+    }
+
+    async fn block_txids(&self, height: u32) -> Result<Vec<Txid>> {
+        let hash = self.get_text(&format!("/block-height/{}", height)).await?;
+        let txids = self
+            .get_text(&format!("/block/{}/txids", hash.trim()))
+            .await?;
+        serde_json::from_str(&txids)
+            .map_err(|e| Error::Network(format!("Invalid txids for block {}: {}", height, e)))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<()> {
+        let url = format!("{}/tx", self.base_url);
+        self.client
+            .post(&url)
+            .body(encode::serialize_hex(tx))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                Error::Network(format!("Failed to broadcast {}: {}", tx.compute_txid(), e))
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,14 @@
+//! Watchtower protocol and a minimal reference tower
+//!
+//! Wallets that are often offline cannot react when a peer broadcasts a revoked commitment
+//! transaction. They hand an encrypted justice transaction for each revoked commitment to a
+//! tower, which watches the chain and broadcasts it if the breach appears.
+
+pub mod chain;
+pub mod protocol;
+pub mod server;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
+pub use protocol::{JusticeBlob, TowerClient};
+pub use server::Tower;
@@ -0,0 +1,65 @@
+//! Reference watchtower server
+
+use clap::Parser;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing_subscriber::EnvFilter;
+use ulw_core::{Error, Result};
+use ulw_tower::chain::EsploraChain;
+use ulw_tower::Tower;
+
+#[derive(Parser)]
+#[command(name = "ulw-tower")]
+#[command(about = "Watchtower for Unified Lightning Wallet clients", long_about = None)]
+#[command(version)]
+struct Cli {
+    /// Address to accept clients on
+    #[arg(long, default_value = "127.0.0.1:9911")]
+    listen: SocketAddr,
+
+    /// Esplora API to watch the chain through
+    #[arg(long, default_value = "http://localhost:3002")]
+    esplora: String,
+
+    /// File to keep blobs in across restarts
+    #[arg(long, default_value = "tower.json")]
+    data: PathBuf,
+
+    /// Seconds between checks for new blocks
+    #[arg(long, default_value = "30")]
+    poll_secs: u64,
+
+    /// Enable verbose logging
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let filter = if cli.verbose {
+        EnvFilter::new("debug")
+    } else {
+        EnvFilter::new("info")
+    };
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+
+    let chain = Arc::new(EsploraChain::new(&cli.esplora));
+    let tower = Arc::new(Tower::new(chain, Some(cli.data))?);
+    let listener = TcpListener::bind(cli.listen)
+        .await
+        .map_err(|e| Error::Network(format!("Failed to listen on {}: {}", cli.listen, e)))?;
+
+    tracing::info!(
+        "Watchtower listening on {} with {} blobs, watching {}",
+        cli.listen,
+        tower.blob_count(),
+        cli.esplora
+    );
+    tokio::spawn(tower.clone().watch(Duration::from_secs(cli.poll_secs)));
+    tower.serve(listener).await
+}
@@ -0,0 +1,264 @@
+//! Watchtower wire protocol
+//!
+//! A client hands the tower one blob per revoked commitment transaction. The blob holds the
+//! justice transaction for that commitment, encrypted under the commitment's txid, and is filed
+//! under a hint that is a hash of the txid. The tower can only decrypt a blob once the revoked
+//! commitment appears on chain, and learns nothing about the channel before that.
+//!
+//! Messages are JSON objects, one per line, over TCP. The tower answers each request with one
+//! response.
+
+use bitcoin::consensus::encode;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{Transaction, Txid};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+
+use ulw_core::{Error, Result};
+
+/// Length of the hint a blob is filed under
+pub const HINT_LEN: usize = 16;
+
+/// Largest encrypted blob the tower accepts, in bytes
+pub const MAX_BLOB_LEN: usize = 4096;
+
+/// Longest message line, enough for a hex-encoded blob of the largest size
+const MAX_LINE_LEN: u64 = 2 * MAX_BLOB_LEN as u64 + 256;
+
+const NONCE_LEN: usize = 12;
+
+/// How long a client waits to connect to the tower or for its answer
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Identifies the blob for a breach without revealing the breaching txid
+pub type Hint = [u8; HINT_LEN];
+
+/// Hint under which the justice transaction for `breach_txid` is filed
+pub fn breach_hint(breach_txid: &Txid) -> Hint {
+    let hash = sha256::Hash::hash(breach_txid.as_byte_array());
+    let mut hint = [0u8; HINT_LEN];
+    hint.copy_from_slice(&hash[..HINT_LEN]);
+    hint
+}
+
+/// A justice transaction, encrypted until its breach is seen on chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JusticeBlob {
+    pub hint: Hint,
+    /// Nonce followed by the ChaCha20-Poly1305 ciphertext of the transaction
+    pub encrypted: Vec<u8>,
+}
+
+impl JusticeBlob {
+    /// Encrypt `justice_tx`, which spends the revoked commitment `breach_txid`
+    pub fn seal(breach_txid: &Txid, justice_tx: &Transaction) -> Self {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(breach_txid.as_byte_array()));
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                encode::serialize(justice_tx).as_slice(),
+            )
+            .expect("encrypting into a Vec cannot fail");
+
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend_from_slice(&ciphertext);
+        Self {
+            hint: breach_hint(breach_txid),
+            encrypted,
+        }
+    }
+
+    /// Decrypt the justice transaction, now that `breach_txid` is known
+    pub fn open(&self, breach_txid: &Txid) -> Result<Transaction> {
+        if self.encrypted.len() < NONCE_LEN {
+            return Err(Error::Internal("Justice blob is truncated".to_string()));
+        }
+        let (nonce, ciphertext) = self.encrypted.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(breach_txid.as_byte_array()));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::Internal(format!("Justice blob is not for {}", breach_txid)))?;
+        encode::deserialize(&plaintext)
+            .map_err(|e| Error::Internal(format!("Invalid justice transaction: {}", e)))
+    }
+}
+
+/// Message from a client to the tower
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Keep a hex-encoded blob under a hex-encoded hint
+    Store { hint: String, blob: String },
+}
+
+/// Answer from the tower to a [`Request`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Stored,
+    Error { message: String },
+}
+
+impl Request {
+    /// Request to store `blob`
+    pub fn store(blob: &JusticeBlob) -> Self {
+        Request::Store {
+            hint: hex::encode(blob.hint),
+            blob: hex::encode(&blob.encrypted),
+        }
+    }
+}
+
+impl TryFrom<Request> for JusticeBlob {
+    type Error = Error;
+
+    fn try_from(request: Request) -> Result<Self> {
+        let Request::Store { hint, blob } = request;
+        let hint = hex::decode(hint)
+            .ok()
+            .and_then(|hint| Hint::try_from(hint).ok())
+            .ok_or_else(|| Error::Internal("Invalid hint".to_string()))?;
+        let encrypted =
+            hex::decode(blob).map_err(|e| Error::Internal(format!("Invalid blob: {}", e)))?;
+        if encrypted.len() > MAX_BLOB_LEN {
+            return Err(Error::Internal(format!(
+                "Blob of {} bytes is over the limit of {} bytes",
+                encrypted.len(),
+                MAX_BLOB_LEN
+            )));
+        }
+        Ok(JusticeBlob { hint, encrypted })
+    }
+}
+
+/// Read one message, or `None` once the peer has closed the connection
+pub(crate) async fn read_message<T, R>(reader: &mut R) -> Result<Option<T>>
+where
+    T: DeserializeOwned,
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    let read = reader
+        .take(MAX_LINE_LEN)
+        .read_line(&mut line)
+        .await
+        .map_err(|e| Error::Network(format!("Failed to read message: {}", e)))?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') {
+        return Err(Error::Network("Message too long".to_string()));
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| Error::Network(format!("Invalid message: {}", e)))
+}
+
+/// Write one message
+pub(crate) async fn write_message<T, W>(writer: &mut W, message: &T) -> Result<()>
+where
+    T: Serialize,
+    W: AsyncWrite + Unpin,
+{
+    let mut line = serde_json::to_vec(message)
+        .map_err(|e| Error::Internal(format!("Failed to encode message: {}", e)))?;
+    line.push(b'\n');
+    writer
+        .write_all(&line)
+        .await
+        .map_err(|e| Error::Network(format!("Failed to send message: {}", e)))
+}
+
+/// Connection to a watchtower
+pub struct TowerClient {
+    address: SocketAddr,
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl TowerClient {
+    /// Connect to the tower listening on `address`
+    pub async fn connect(address: SocketAddr) -> Result<Self> {
+        let stream = tokio::time::timeout(CLIENT_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| Error::Network(format!("Timed out connecting to tower {}", address)))?
+            .map_err(|e| {
+                Error::Network(format!("Failed to connect to tower {}: {}", address, e))
+            })?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            address,
+            reader: BufReader::new(reader),
+            writer,
+        })
+    }
+
+    /// Hand `blob` to the tower, returning once the tower has stored it
+    pub async fn store(&mut self, blob: &JusticeBlob) -> Result<()> {
+        write_message(&mut self.writer, &Request::store(blob)).await?;
+        let response = tokio::time::timeout(CLIENT_TIMEOUT, read_message(&mut self.reader))
+            .await
+            .map_err(|_| Error::Network(format!("Tower {} did not answer", self.address)))??;
+        match response {
+            Some(Response::Stored) => Ok(()),
+            Some(Response::Error { message }) => Err(Error::Network(format!(
+                "Tower {} refused blob: {}",
+                self.address, message
+            ))),
+            None => Err(Error::Network(format!(
+                "Tower {} closed the connection",
+                self.address
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::spending_tx;
+
+    #[test]
+    fn test_blob_only_opens_with_breach_txid() {
+        let breach = spending_tx(Txid::all_zeros(), 100_000);
+        let justice = spending_tx(breach.compute_txid(), 90_000);
+
+        let blob = JusticeBlob::seal(&breach.compute_txid(), &justice);
+        assert_eq!(blob.hint, breach_hint(&breach.compute_txid()));
+        assert_eq!(blob.open(&breach.compute_txid()).unwrap(), justice);
+
+        let other = spending_tx(Txid::all_zeros(), 50_000).compute_txid();
+        assert_ne!(breach_hint(&other), blob.hint);
+        assert!(blob.open(&other).is_err());
+    }
+
+    #[test]
+    fn test_store_request_round_trip() {
+        let breach = spending_tx(Txid::all_zeros(), 100_000);
+        let blob = JusticeBlob::seal(
+            &breach.compute_txid(),
+            &spending_tx(breach.compute_txid(), 90_000),
+        );
+
+        let line = serde_json::to_string(&Request::store(&blob)).unwrap();
+        assert!(line.starts_with(r#"{"type":"store""#));
+        let request: Request = serde_json::from_str(&line).unwrap();
+        assert_eq!(JusticeBlob::try_from(request).unwrap(), blob);
+
+        let oversized = Request::Store {
+            hint: hex::encode(blob.hint),
+            blob: hex::encode(vec![0u8; MAX_BLOB_LEN + 1]),
+        };
+        assert!(JusticeBlob::try_from(oversized).is_err());
+    }
+}
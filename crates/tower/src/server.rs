@@ -0,0 +1,371 @@
+//! The tower
+//!
+//! Keeps blobs by hint and checks every transaction in each new block against them. When a
+//! txid's hint matches, the txid decrypts the blob and the justice transaction inside is
+//! broadcast. Blobs are kept until their breach is buried, in case a reorg moves it.
+
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+
+use ulw_core::{Error, Result};
+
+use crate::chain::TowerChain;
+use crate::protocol::{breach_hint, read_message, write_message, JusticeBlob, Request, Response};
+
+/// Blocks below the last scanned one that are scanned again, to catch breaches moved by a reorg
+///
+/// A breach with this many confirmations is considered buried.
+const RESCAN_DEPTH: u32 = 6;
+
+/// What the tower keeps across restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TowerState {
+    /// Highest block scanned so far
+    scanned_height: Option<u32>,
+    /// Hex-encoded blobs by hex-encoded hint
+    blobs: HashMap<String, Vec<String>>,
+    /// Height of the block each punished breach is in, or `None` if a reorg dropped it
+    #[serde(default)]
+    breaches: HashMap<Txid, Option<u32>>,
+}
+
+/// A watchtower serving clients and watching `chain`
+pub struct Tower {
+    chain: Arc<dyn TowerChain>,
+    path: Option<PathBuf>,
+    state: Mutex<TowerState>,
+}
+
+impl Tower {
+    /// Create a tower, keeping its blobs in the file at `path` if one is given
+    pub fn new(chain: Arc<dyn TowerChain>, path: Option<PathBuf>) -> Result<Self> {
+        let state = match &path {
+            Some(path) => match fs::read(path) {
+                Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                    Error::Storage(format!("Invalid tower state {}: {}", path.display(), e))
+                })?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => TowerState::default(),
+                Err(e) => {
+                    return Err(Error::Storage(format!(
+                        "Failed to read tower state {}: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            },
+            None => TowerState::default(),
+        };
+
+        Ok(Self {
+            chain,
+            path,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Number of blobs whose breach is not buried yet
+    pub fn blob_count(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .blobs
+            .values()
+            .map(Vec::len)
+            .sum()
+    }
+
+    /// Keep `blob` until its breach is seen
+    pub fn store(&self, blob: JusticeBlob) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let blobs = state.blobs.entry(hex::encode(blob.hint)).or_default();
+        let encrypted = hex::encode(&blob.encrypted);
+        if !blobs.contains(&encrypted) {
+            blobs.push(encrypted);
+        }
+        self.save(&state)
+    }
+
+    fn save(&self, state: &TowerState) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec(state)
+            .map_err(|e| Error::Storage(format!("Failed to encode tower state: {}", e)))?;
+
+        // Replace the old state in one step, so a crash never loses blobs already stored
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)
+            .and_then(|()| fs::rename(&tmp, path))
+            .map_err(|e| Error::Storage(format!("Failed to write tower state: {}", e)))
+    }
+
+    /// Accept clients on `listener` until the task is dropped
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener
+                .accept()
+                .await
+                .map_err(|e| Error::Network(format!("Failed to accept client: {}", e)))?;
+            let tower = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = tower.handle_client(stream).await {
+                    tracing::debug!("Client {} disconnected: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle_client(&self, stream: TcpStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        while let Some(request) = read_message::<Request, _>(&mut reader).await? {
+            let response = match JusticeBlob::try_from(request).and_then(|blob| self.store(blob)) {
+                Ok(()) => Response::Stored,
+                Err(e) => Response::Error {
+                    message: e.to_string(),
+                },
+            };
+            write_message(&mut writer, &response).await?;
+        }
+        Ok(())
+    }
+
+    /// Check the blocks since the last scan for breaches, broadcasting their justice transactions
+    ///
+    /// Returns the txids of the justice transactions broadcast, including those broadcast again
+    /// because a reorg moved their breach. The first scan starts at the current tip.
+    pub async fn scan(&self) -> Result<Vec<Txid>> {
+        let tip = self.chain.tip_height().await?;
+        let scanned = self.state.lock().unwrap().scanned_height;
+        let start = scanned.map_or(tip, |height| height.saturating_sub(RESCAN_DEPTH));
+
+        let mut broadcast = Vec::new();
+        let mut seen = HashSet::new();
+        for height in start..=tip {
+            for txid in self.chain.block_txids(height).await? {
+                broadcast.extend(self.punish(&txid, height).await?);
+                if self.state.lock().unwrap().breaches.contains_key(&txid) {
+                    seen.insert(txid);
+                }
+            }
+            let mut state = self.state.lock().unwrap();
+            state.scanned_height = Some(state.scanned_height.map_or(height, |h| h.max(height)));
+            self.save(&state)?;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        for (txid, height) in state.breaches.iter_mut() {
+            // A breach no longer in the blocks scanned again was dropped by a reorg
+            if height.is_some_and(|h| h >= start) && !seen.contains(txid) {
+                *height = None;
+            }
+        }
+        self.forget_buried(&mut state, tip);
+        self.save(&state)?;
+        Ok(broadcast)
+    }
+
+    /// Broadcast the justice transactions for `txid`, if it is a breach we hold blobs for
+    ///
+    /// Nothing is broadcast again for a breach already punished in the block at `height`.
+    async fn punish(&self, txid: &Txid, height: u32) -> Result<Vec<Txid>> {
+        let hint = hex::encode(breach_hint(txid));
+        let blobs = {
+            let state = self.state.lock().unwrap();
+            if state.breaches.get(txid) == Some(&Some(height)) {
+                return Ok(Vec::new());
+            }
+            let Some(blobs) = state.blobs.get(&hint).cloned() else {
+                return Ok(Vec::new());
+            };
+            blobs
+        };
+
+        let mut broadcast = Vec::new();
+        for encrypted in &blobs {
+            let blob = JusticeBlob {
+                hint: breach_hint(txid),
+                encrypted: hex::decode(encrypted).unwrap_or_default(),
+            };
+            // Another txid with the same hint cannot decrypt the blob
+            let Ok(justice_tx) = blob.open(txid) else {
+                continue;
+            };
+            self.chain.broadcast(&justice_tx).await?;
+            tracing::info!(
+                "Breach {} seen at height {}, broadcast justice transaction {}",
+                txid,
+                height,
+                justice_tx.compute_txid()
+            );
+            broadcast.push(justice_tx.compute_txid());
+        }
+
+        if !broadcast.is_empty() {
+            let mut state = self.state.lock().unwrap();
+            state.breaches.insert(*txid, Some(height));
+            self.save(&state)?;
+        }
+        Ok(broadcast)
+    }
+
+    /// Drop the blobs of breaches with `RESCAN_DEPTH` confirmations at `tip`
+    fn forget_buried(&self, state: &mut TowerState, tip: u32) {
+        let buried: Vec<Txid> = state
+            .breaches
+            .iter()
+            .filter(|(_, height)| {
+                height.is_some_and(|h| (tip + 1).saturating_sub(h) >= RESCAN_DEPTH)
+            })
+            .map(|(txid, _)| *txid)
+            .collect();
+        for txid in buried {
+            state.breaches.remove(&txid);
+            let hint = hex::encode(breach_hint(&txid));
+            // Other blobs with the same hint are for breaches that have not happened yet
+            if let Some(blobs) = state.blobs.get_mut(&hint) {
+                blobs.retain(|encrypted| {
+                    let blob = JusticeBlob {
+                        hint: breach_hint(&txid),
+                        encrypted: hex::decode(encrypted).unwrap_or_default(),
+                    };
+                    blob.open(&txid).is_err()
+                });
+                if blobs.is_empty() {
+                    state.blobs.remove(&hint);
+                }
+            }
+            tracing::debug!("Breach {} buried, dropped its blobs", txid);
+        }
+    }
+
+    /// Scan for breaches every `interval` until the task is dropped
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.scan().await {
+                tracing::warn!("Breach scan failed: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::TowerClient;
+    use crate::test_utils::{spending_tx, MockChain};
+    use bitcoin::hashes::Hash;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_tower_broadcasts_justice_for_breach() {
+        let chain = Arc::new(MockChain::default());
+        chain.mine(vec![]);
+        let tower = Arc::new(Tower::new(chain.clone(), None).unwrap());
+        assert!(tower.scan().await.unwrap().is_empty());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(tower.clone().serve(listener));
+
+        let breach = spending_tx(Txid::all_zeros(), 100_000);
+        let justice = spending_tx(breach.compute_txid(), 90_000);
+        let mut client = TowerClient::connect(address).await.unwrap();
+        client
+            .store(&JusticeBlob::seal(&breach.compute_txid(), &justice))
+            .await
+            .unwrap();
+        assert_eq!(tower.blob_count(), 1);
+
+        // Unrelated transactions leave the blob alone
+        chain.mine(vec![spending_tx(Txid::all_zeros(), 1).compute_txid()]);
+        assert!(tower.scan().await.unwrap().is_empty());
+        assert_eq!(tower.blob_count(), 1);
+
+        chain.mine(vec![breach.compute_txid()]);
+        assert_eq!(tower.scan().await.unwrap(), vec![justice.compute_txid()]);
+        assert_eq!(*chain.broadcasts.lock().unwrap(), vec![justice.clone()]);
+
+        // Rescanning the breach's block does not broadcast again, and the blob is kept until
+        // the breach is buried
+        for _ in 0..RESCAN_DEPTH - 2 {
+            chain.mine(vec![]);
+            assert!(tower.scan().await.unwrap().is_empty());
+            assert_eq!(tower.blob_count(), 1);
+        }
+        chain.mine(vec![]);
+        assert!(tower.scan().await.unwrap().is_empty());
+        assert_eq!(tower.blob_count(), 0);
+        assert_eq!(*chain.broadcasts.lock().unwrap(), vec![justice]);
+    }
+
+    #[tokio::test]
+    async fn test_tower_punishes_breach_moved_by_reorg() {
+        let chain = Arc::new(MockChain::default());
+        chain.mine(vec![]);
+        let tower = Tower::new(chain.clone(), None).unwrap();
+        tower.scan().await.unwrap();
+
+        let breach = spending_tx(Txid::all_zeros(), 100_000);
+        let justice = spending_tx(breach.compute_txid(), 90_000);
+        tower
+            .store(JusticeBlob::seal(&breach.compute_txid(), &justice))
+            .unwrap();
+        chain.mine(vec![breach.compute_txid()]);
+        assert_eq!(tower.scan().await.unwrap(), vec![justice.compute_txid()]);
+
+        // A reorg drops the breach and its justice transaction from the block
+        chain.blocks.lock().unwrap()[1].clear();
+        chain.mine(vec![]);
+        assert!(tower.scan().await.unwrap().is_empty());
+
+        // The breach confirms again later, beyond where it would have been buried
+        for _ in 0..RESCAN_DEPTH {
+            chain.mine(vec![]);
+            tower.scan().await.unwrap();
+        }
+        assert_eq!(tower.blob_count(), 1);
+        chain.mine(vec![breach.compute_txid()]);
+        assert_eq!(tower.scan().await.unwrap(), vec![justice.compute_txid()]);
+        assert_eq!(chain.broadcasts.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tower_keeps_blobs_across_restarts() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("tower.json");
+        let chain = Arc::new(MockChain::default());
+        chain.mine(vec![]);
+
+        let breach = spending_tx(Txid::all_zeros(), 100_000);
+        let justice = spending_tx(breach.compute_txid(), 90_000);
+        let tower = Tower::new(chain.clone(), Some(path.clone())).unwrap();
+        tower
+            .store(JusticeBlob::seal(&breach.compute_txid(), &justice))
+            .unwrap();
+        tower.scan().await.unwrap();
+        drop(tower);
+
+        // The breach confirms while the tower is down
+        chain.mine(vec![breach.compute_txid()]);
+        let tower = Tower::new(chain.clone(), Some(path.clone())).unwrap();
+        assert_eq!(tower.blob_count(), 1);
+        assert_eq!(tower.scan().await.unwrap(), vec![justice.compute_txid()]);
+        drop(tower);
+
+        // Having punished the breach survives a restart too
+        let tower = Tower::new(chain.clone(), Some(path)).unwrap();
+        assert_eq!(tower.blob_count(), 1);
+        assert!(tower.scan().await.unwrap().is_empty());
+    }
+}
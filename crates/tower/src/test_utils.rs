@@ -0,0 +1,59 @@
+//! Helpers for testing the tower and its clients, behind the `test-utils` feature
+
+use async_trait::async_trait;
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use std::sync::Mutex;
+
+use ulw_core::Result;
+
+use crate::chain::TowerChain;
+
+/// Regtest chain of blocks given as lists of txids, recording what is broadcast
+#[derive(Default)]
+pub struct MockChain {
+    pub blocks: Mutex<Vec<Vec<Txid>>>,
+    pub broadcasts: Mutex<Vec<Transaction>>,
+}
+
+impl MockChain {
+    /// Extend the chain by a block containing `txids`
+    pub fn mine(&self, txids: Vec<Txid>) {
+        self.blocks.lock().unwrap().push(txids);
+    }
+}
+
+#[async_trait]
+impl TowerChain for MockChain {
+    async fn tip_height(&self) -> Result<u32> {
+        Ok(self.blocks.lock().unwrap().len() as u32 - 1)
+    }
+
+    async fn block_txids(&self, height: u32) -> Result<Vec<Txid>> {
+        Ok(self.blocks.lock().unwrap()[height as usize].clone())
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<()> {
+        self.broadcasts.lock().unwrap().push(tx.clone());
+        Ok(())
+    }
+}
+
+/// Transaction spending output 0 of `spent`, distinguished by `value`
+pub fn spending_tx(spent: Txid, value: u64) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(spent, 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new_op_return([]),
+        }],
+    }
+}
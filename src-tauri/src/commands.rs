@@ -5,11 +5,14 @@ use tauri::State;
 use tokio::sync::Mutex;
 
 use ulw_bdk::BdkWallet;
+use ulw_core::types::PaymentConstraints;
+use ulw_ldk::payments::InvoiceOptions;
 use ulw_ldk::LdkNode;
+use ulw_storage::WalletDatabase;
 
 // Application state holding wallet instances
 pub struct AppState {
-    pub bdk_wallet: Arc<Mutex<Option<BdkWallet>>>,
+    pub bdk_wallet: Arc<Mutex<Option<Arc<BdkWallet>>>>,
     pub ldk_node: Arc<Mutex<Option<LdkNode>>>,
     pub data_dir: PathBuf,
 }
@@ -59,7 +62,7 @@ pub async fn init_wallet(
     .map_err(|e| e.to_string())?;
 
     let mut wallet_guard = state.bdk_wallet.lock().await;
-    *wallet_guard = Some(wallet);
+    *wallet_guard = Some(Arc::new(wallet));
 
     Ok("Wallet initialized successfully".to_string())
 }
@@ -160,26 +163,13 @@ pub async fn create_lightning_invoice(
     let mut node_guard = state.ldk_node.lock().await;
 
     if node_guard.is_none() {
-        // Create Lightning node with demo entropy
-        // In production, derive from BDK wallet mnemonic
-        let entropy = derive_demo_entropy();
-        let lightning_dir = state.data_dir.join("lightning");
-
-        let node = LdkNode::new(
-            bitcoin::Network::Regtest,  // TODO: Get from wallet config
-            lightning_dir,
-            entropy,
-        )
-        .await
-        .map_err(|e| format!("Failed to create LDK node: {}", e))?;
-
-        *node_guard = Some(node);
+        *node_guard = Some(create_ldk_node(&state).await?);
     }
 
     // Create invoice
     if let Some(node) = node_guard.as_ref() {
         let invoice = node
-            .create_invoice(Some(amount_msats), description, 3600)
+            .create_invoice(Some(amount_msats), description, &InvoiceOptions::default())
             .await
             .map_err(|e| e.to_string())?;
 
@@ -192,24 +182,166 @@ pub async fn create_lightning_invoice(
 #[tauri::command]
 pub async fn pay_lightning_invoice(
     invoice: String,
+    amount_sats: Option<u64>,
     state: State<'_, AppState>,
 ) -> Result<String, String> {
     log::info!("Paying invoice: {}", invoice);
 
+    let amount_msat = match amount_sats {
+        Some(sats) => Some(sats.checked_mul(1000).ok_or("Amount is too large")?),
+        None => None,
+    };
+
     let node_guard = state.ldk_node.lock().await;
 
     if let Some(node) = node_guard.as_ref() {
-        let payment_hash = node
-            .pay_invoice(invoice)
+        let sent = node
+            .pay_invoice(&invoice, amount_msat, &PaymentConstraints::default())
             .await
             .map_err(|e| e.to_string())?;
 
-        Ok(hex::encode(payment_hash.0))
+        Ok(hex::encode(sent.preimage.0))
     } else {
         Err("Lightning node not initialized. Create an invoice first.".to_string())
     }
 }
 
+// Log in to a service with LNURL-auth, returning the linking key it knows us by
+#[tauri::command]
+pub async fn lnurl_auth(
+    lnurl: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    log::info!("Logging in with LNURL-auth");
+
+    let mut node_guard = state.ldk_node.lock().await;
+
+    if node_guard.is_none() {
+        *node_guard = Some(create_ldk_node(&state).await?);
+    }
+
+    let node = node_guard.as_ref().ok_or("Lightning node not initialized")?;
+    let record = node.lnurl_auth(&lnurl).await.map_err(|e| e.to_string())?;
+
+    Ok(record.linking_key)
+}
+
+// Open a Lightning channel funded from the on-chain wallet
+#[tauri::command]
+pub async fn open_channel(
+    node_id: String,
+    peer_addr: String,
+    amount_sats: u64,
+    push_msat: u64,
+    announce: bool,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    log::info!("Opening {} sat channel to {}@{}", amount_sats, node_id, peer_addr);
+
+    let mut node_guard = state.ldk_node.lock().await;
+
+    if node_guard.is_none() {
+        *node_guard = Some(create_ldk_node(&state).await?);
+    }
+
+    let node = node_guard.as_ref().ok_or("Lightning node not initialized")?;
+    let node_id: bitcoin::secp256k1::PublicKey = node_id.parse().map_err(|e| format!("Invalid node ID: {}", e))?;
+    let addr: std::net::SocketAddr = peer_addr.parse().map_err(|e| format!("Invalid peer address: {}", e))?;
+
+    node.connect_peer(node_id, addr).await.map_err(|e| e.to_string())?;
+    let channel_id = node
+        .open_channel(node_id, amount_sats, push_msat, announce)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(channel_id.to_string())
+}
+
+// Close a Lightning channel, cooperatively unless `force` is set
+#[tauri::command]
+pub async fn close_channel(
+    channel_id: String,
+    force: bool,
+    fee_rate_sat_per_vb: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<Option<String>, String> {
+    log::info!("Closing channel {} (force: {})", channel_id, force);
+
+    let node_guard = state.ldk_node.lock().await;
+
+    if let Some(node) = node_guard.as_ref() {
+        let channel = node
+            .close_channel(&channel_id, force, fee_rate_sat_per_vb)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(channel.closing_txid)
+    } else {
+        Err("Lightning node not initialized".to_string())
+    }
+}
+
+// List Lightning channels
+#[tauri::command]
+pub async fn list_channels(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let node_guard = state.ldk_node.lock().await;
+
+    if let Some(node) = node_guard.as_ref() {
+        let channels = node.list_channels().await.map_err(|e| e.to_string())?;
+        Ok(channels
+            .iter()
+            .map(|c| format!("{} | Peer: {} | Capacity: {} sats | State: {:?}",
+                c.channel_id,
+                c.counterparty_node_id,
+                c.capacity_sats,
+                c.state
+            ))
+            .collect())
+    } else {
+        Err("Lightning node not initialized".to_string())
+    }
+}
+
+// Create the Lightning node on top of the initialized on-chain wallet
+async fn create_ldk_node(state: &AppState) -> Result<LdkNode, String> {
+    let wallet = state
+        .bdk_wallet
+        .lock()
+        .await
+        .clone()
+        .ok_or("Wallet not initialized")?;
+
+    // Create Lightning node with demo entropy
+    // In production, derive from BDK wallet mnemonic
+    let entropy = derive_demo_entropy();
+    let lightning_dir = state.data_dir.join("lightning");
+
+    std::fs::create_dir_all(&state.data_dir).map_err(|e| e.to_string())?;
+    let storage = WalletDatabase::new(state.data_dir.join("tauri_wallet.db"))
+        .map_err(|e| e.to_string())?;
+
+    let node = LdkNode::new(
+        bitcoin::Network::Regtest,  // TODO: Get from wallet config
+        lightning_dir,
+        entropy,
+        wallet,
+        Arc::new(storage),
+    )
+    .await
+    .map_err(|e| format!("Failed to create LDK node: {}", e))?;
+
+    node.start().map_err(|e| e.to_string())?;
+    Ok(node)
+}
+
+// Stop the Lightning node so channel state is persisted before the app exits
+pub async fn shutdown(state: &AppState) {
+    if let Some(node) = state.ldk_node.lock().await.as_ref() {
+        if let Err(e) = node.stop().await {
+            log::error!("Failed to stop Lightning node: {}", e);
+        }
+    }
+}
+
 // Helper function to derive demo entropy
 // WARNING: This is for demo purposes only!
 // In production, derive from BDK wallet mnemonic
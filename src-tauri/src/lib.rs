@@ -1,6 +1,7 @@
 mod commands;
 
 use commands::AppState;
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -27,7 +28,17 @@ pub fn run() {
       commands::list_transactions,
       commands::create_lightning_invoice,
       commands::pay_lightning_invoice,
+      commands::lnurl_auth,
+      commands::open_channel,
+      commands::close_channel,
+      commands::list_channels,
     ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application")
+    .run(|app, event| {
+      if let tauri::RunEvent::Exit = event {
+        let state = app.state::<AppState>();
+        tauri::async_runtime::block_on(commands::shutdown(&state));
+      }
+    });
 }